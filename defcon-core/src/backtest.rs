@@ -0,0 +1,185 @@
+//! Running the classifier and the level policy over the recent changes of a
+//! past period, to see what levels new thresholds or keywords would have set
+//! before deploying them.
+//!
+//! Only metrics computed from the recent changes themselves can be replayed,
+//! and `baseline` and `smoothing`, which build on earlier live runs, are left
+//! out. Report page edits by hand, `override_mins` and cooperating bots don't
+//! exist in a backtest either; `min_dwell_mins` and the hysteresis do.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::bail;
+
+use crate::classifier::{is_revert, Classifier};
+use crate::metrics::Metrics;
+use crate::rpm::{
+    fetch_changes, is_self_revert, read_changes, reverted_users, window_start, Change, RecentEdits,
+    Revert,
+};
+use crate::run::dwelling;
+use crate::scoring::Signal;
+use crate::settings::Settings;
+
+/// The metrics a backtest can compute.
+pub const METRICS: [&str; 3] = ["rpm", "ratio", "reverters"];
+
+/// One simulated run.
+pub struct Step {
+    pub time: DateTime<Utc>,
+    pub rpm: f32,
+    pub metrics: Metrics,
+    /// The level before the run, 0 at the first one.
+    pub previous_level: u8,
+    pub level: u8,
+}
+
+/// A change, classified.
+struct Edit {
+    timestamp: i64,
+    revert: Option<Revert>,
+}
+
+/// Check that every metric of `settings` can be computed from recent
+/// changes alone.
+pub fn check_settings(settings: &Settings) -> color_eyre::Result<()> {
+    if let Some(name) = settings
+        .available_metrics()
+        .into_iter()
+        .find(|name| !METRICS.contains(&name.as_str()))
+    {
+        bail!(
+            "can't backtest the {name} metric: only {} can be computed from recent changes",
+            METRICS.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Simulate a run every `run_interval_mins` from `from` until `to`, over the
+/// recent changes from the wiki or from the `dump` at the given path (see
+/// `rpm::read_changes`). The wiki only keeps recent changes for about a
+/// month; a dump has to hold the namespaces and users the settings count,
+/// since it's only filtered by `excluded_users`.
+pub async fn backtest(
+    client: &mw::Client,
+    settings: &Settings,
+    classifier: &Classifier,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    dump: Option<&Path>,
+) -> color_eyre::Result<Vec<Step>> {
+    check_settings(settings)?;
+    if from >= to {
+        bail!("the backtest has to start before it ends");
+    }
+    // the first run counts the window before it
+    let since = Utc
+        .timestamp_opt(window_start(settings, from.timestamp()), 0)
+        .single()
+        .unwrap_or(from);
+    let changes = match dump {
+        Some(path) => read_changes(path)?,
+        None => fetch_changes(client, settings, since, Some(to)).await?,
+    };
+    let reverted = reverted_users(client, settings, classifier, &changes).await?;
+    let edits: Vec<Edit> = changes
+        .into_iter()
+        .filter(|change| !settings.excluded_users.contains(&change.user))
+        .map(|change| classify(settings, classifier, change, &reverted))
+        .collect();
+    simulate(settings, &edits, from, to)
+}
+
+/// `change` as an edit, a revert if the classifier says so and it isn't a
+/// self-revert by `reverted`, the users from `rpm::reverted_users`.
+fn classify(
+    settings: &Settings,
+    classifier: &Classifier,
+    change: Change,
+    reverted: &HashMap<u64, String>,
+) -> Edit {
+    let revert = (is_revert(
+        classifier,
+        settings.detection,
+        &change.comment,
+        &change.tags,
+    ) && !is_self_revert(settings, &change, reverted))
+    .then(|| Revert {
+        namespace: change.namespace,
+        weight: settings.revert_weights.of(&change.tags),
+        reverted: reverted.get(&change.rcid).cloned(),
+        title: change.title,
+        user: change.user,
+    });
+    Edit {
+        timestamp: change.timestamp,
+        revert,
+    }
+}
+
+/// The edits the run at `now` would have counted, the way
+/// `rpm::recent_edits` buckets them. `edits` are oldest first.
+fn window(settings: &Settings, edits: &[Edit], now: i64) -> RecentEdits {
+    let oldest = window_start(settings, now);
+    let start = edits.partition_point(|edit| edit.timestamp < oldest);
+    let end = edits.partition_point(|edit| edit.timestamp <= now);
+    let edits = &edits[start..end];
+    RecentEdits {
+        edits: edits.len(),
+        reverts: edits
+            .iter()
+            .filter_map(|edit| edit.revert.clone())
+            .collect(),
+        minutes: (now - oldest).max(60) as f32 / 60.0,
+    }
+}
+
+fn simulate(
+    settings: &Settings,
+    edits: &[Edit],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> color_eyre::Result<Vec<Step>> {
+    let interval = Duration::minutes(settings.run_interval_mins.max(1) as i64);
+    let mut steps = Vec::new();
+    let mut level = 0;
+    let mut changed = None;
+    let mut time = from;
+    while time < to {
+        let recent = window(settings, edits, time.timestamp());
+        let metrics: Metrics = settings
+            .metrics
+            .iter()
+            .map(|name| {
+                let value = match name.as_str() {
+                    "rpm" => f64::from(recent.rpm(settings)),
+                    "ratio" => f64::from(recent.ratio(settings)),
+                    "reverters" => recent.reverters() as f64,
+                    _ => unreachable!("checked by check_settings"),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        let signal = Signal::new(settings, &metrics)?;
+        let mut next = signal.level(settings, &metrics, level)?;
+        if dwelling(settings, changed, time, level, next) {
+            next = level;
+        }
+        if next != level {
+            changed = Some(time);
+        }
+        steps.push(Step {
+            time,
+            rpm: recent.rpm(settings),
+            metrics,
+            previous_level: level,
+            level: next,
+        });
+        level = next;
+        time += interval;
+    }
+    Ok(steps)
+}
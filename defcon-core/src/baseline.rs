@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use tracing::info;
+
+use crate::metrics::Metrics;
+
+const HOURS_PER_WEEK: usize = 7 * 24;
+/// Quiet hours can have a baseline close to zero, which would make any
+/// single revert look like a huge surge.
+const MIN_BASELINE_RPM: f64 = 0.5;
+
+/// Settings of the `[baseline]` table. With it, every run adds a `relative`
+/// metric: the RPM divided by the usual RPM for the current hour of the week.
+#[derive(serde::Deserialize)]
+pub struct BaselineSettings {
+    /// JSON file the baselines are kept in.
+    pub file: PathBuf,
+    /// Weight of a new sample in its hour's moving average.
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+}
+
+fn default_alpha() -> f64 {
+    0.1
+}
+
+/// Hours since Monday 00:00 UTC.
+pub fn hour_of_week(time: DateTime<Utc>) -> usize {
+    time.weekday().num_days_from_monday() as usize * 24 + time.hour() as usize
+}
+
+/// Average RPM for each hour of the week.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Baseline {
+    hours: Vec<Option<f64>>,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Baseline {
+            hours: vec![None; HOURS_PER_WEEK],
+        }
+    }
+}
+
+impl Baseline {
+    /// Read the baselines from `path`, starting over if it doesn't exist yet.
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Baseline::default()),
+            Err(e) => {
+                return Err(e)
+                    .wrap_err_with(|| format!("could not read baseline {}", path.display()))
+            }
+        };
+        let mut baseline: Baseline = serde_json::from_str(&text)
+            .wrap_err_with(|| format!("invalid baseline {}", path.display()))?;
+        baseline.hours.resize(HOURS_PER_WEEK, None);
+        Ok(baseline)
+    }
+
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write baseline {}", path.display()))
+    }
+
+    /// The usual RPM at `hour` of the week, if it has been sampled before.
+    pub fn get(&self, hour: usize) -> Option<f64> {
+        self.hours[hour]
+    }
+
+    /// Fold `rpm` into the moving average of `hour`.
+    pub fn observe(&mut self, hour: usize, rpm: f64, alpha: f64) {
+        let average = &mut self.hours[hour];
+        *average = Some(average.map_or(rpm, |average| average + alpha * (rpm - average)));
+    }
+
+    /// `rpm` relative to the baseline of `hour`, 1 being normal. Without a
+    /// baseline for `hour` yet, everything is normal.
+    pub fn relative(&self, hour: usize, rpm: f64) -> f64 {
+        self.get(hour)
+            .map_or(1.0, |average| rpm / average.max(MIN_BASELINE_RPM))
+    }
+}
+
+/// Add the `relative` metric to `metrics`, and with `save`, fold the RPM into
+/// the stored baseline.
+pub fn apply(
+    settings: &BaselineSettings,
+    metrics: &mut Metrics,
+    now: DateTime<Utc>,
+    save: bool,
+) -> color_eyre::Result<()> {
+    let rpm = metrics.get("rpm").copied().unwrap_or(0.0);
+    let hour = hour_of_week(now);
+    let mut baseline = Baseline::load(&settings.file)?;
+    let relative = baseline.relative(hour, rpm);
+    info!(
+        "{:.0}% of normal for {} {:02}:00 UTC",
+        relative * 100.0,
+        now.weekday(),
+        now.hour()
+    );
+    metrics.insert("relative".to_owned(), relative);
+    if save {
+        baseline.observe(hour, rpm, settings.alpha);
+        baseline.save(&settings.file)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::fmt::Write;
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::{bail, eyre};
+use serde_json::Value;
+use tracing::info;
+
+use crate::history::{History, Sample};
+use crate::settings::Settings;
+use crate::wiki::{csrf_token, edit_page, writes_enabled, MAXLAG};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 320.0;
+const LEFT: f64 = 50.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 20.0;
+/// Room below the plot for the level strip and the time axis.
+const BOTTOM: f64 = 60.0;
+const STRIP_HEIGHT: f64 = 12.0;
+
+/// Settings of the `[chart]` table: an RPM and level chart drawn from the
+/// database and uploaded to the wiki.
+#[derive(serde::Deserialize)]
+pub struct ChartSettings {
+    /// File name to upload to, without the `File:` prefix.
+    pub file: String,
+    /// API of the wiki to upload to, e.g. Commons; defaults to `api_url`.
+    pub api_url: Option<String>,
+    /// Page showing the chart in a gallery, updated after every upload.
+    pub gallery_page: Option<String>,
+    /// Minutes between uploads in `--daemon` and `--live` mode.
+    #[serde(default = "default_interval_mins")]
+    pub interval_mins: u64,
+    /// Hours of history to draw.
+    #[serde(default = "default_hours")]
+    pub hours: i64,
+    /// License template for the file description page.
+    #[serde(default = "default_license")]
+    pub license: String,
+}
+
+fn default_interval_mins() -> u64 {
+    60
+}
+
+fn default_hours() -> i64 {
+    24
+}
+
+fn default_license() -> String {
+    "{{PD-self}}".to_owned()
+}
+
+fn level_color(level: u8) -> &'static str {
+    match level {
+        1 => "#d33",
+        2 => "#f80",
+        3 => "#fc3",
+        4 => "#9c3",
+        5 => "#3a3",
+        _ => "#ccc",
+    }
+}
+
+/// Draw the RPM of `samples` as a line and their level as a coloured strip
+/// below it, over the `hours` before `now`.
+pub fn render_svg(
+    samples: &[Sample],
+    settings: &Settings,
+    now: DateTime<Utc>,
+    hours: i64,
+) -> String {
+    let start = (now - Duration::hours(hours)).timestamp() as f64;
+    let span = (hours * 3600) as f64;
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let max_rpm = samples
+        .iter()
+        .map(|sample| sample.rpm)
+        .chain(settings.thresholds.last().map(|&t| f64::from(t)))
+        .fold(1.0, f64::max)
+        * 1.1;
+    let x = |time: i64| LEFT + (time as f64 - start) / span * plot_width;
+    let y = |rpm: f64| TOP + plot_height - rpm / max_rpm * plot_height;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" "#,
+            r#"font-family="sans-serif" font-size="11">"#
+        ),
+        WIDTH, HEIGHT
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    // thresholds, labelled with the level above them
+    for (i, &t) in settings.thresholds.iter().enumerate() {
+        let ty = y(f64::from(t));
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{}" y1="{ty:.1}" x2="{}" y2="{ty:.1}" stroke="#aaa" stroke-dasharray="4 3"/>"##,
+            LEFT,
+            WIDTH - RIGHT
+        );
+        let _ = writeln!(
+            svg,
+            r##"<text x="{}" y="{:.1}" fill="#888" text-anchor="end">level {}</text>"##,
+            WIDTH - RIGHT - 2.0,
+            ty - 3.0,
+            settings.levels.get(i + 1).unwrap_or(0)
+        );
+    }
+
+    // axes
+    let axis_y = TOP + plot_height;
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{}" y1="{}" x2="{}" y2="{axis_y}" stroke="#000"/>"##,
+        LEFT, TOP, LEFT
+    );
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{}" y1="{axis_y}" x2="{}" y2="{axis_y}" stroke="#000"/>"##,
+        LEFT,
+        WIDTH - RIGHT
+    );
+    for i in 0..=4 {
+        let rpm = max_rpm * f64::from(i) / 4.0;
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{rpm:.1}</text>"#,
+            LEFT - 4.0,
+            y(rpm) + 4.0
+        );
+    }
+    let label_y = TOP + plot_height / 2.0;
+    let _ = writeln!(
+        svg,
+        r#"<text x="12" y="{label_y}" transform="rotate(-90 12 {label_y})" text-anchor="middle">RPM</text>"#
+    );
+    let step = (hours / 4).max(1);
+    for h in (0..=hours).step_by(step as usize) {
+        let time = now - Duration::hours(hours - h);
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            x(time.timestamp()),
+            HEIGHT - 12.0,
+            time.format("%H:%M")
+        );
+    }
+
+    // level strip, each sample's colour lasting until the next one
+    let strip_y = TOP + plot_height + 6.0;
+    for (i, sample) in samples.iter().enumerate() {
+        let end = samples.get(i + 1).map_or(now.timestamp(), |next| next.time);
+        let (x1, x2) = (x(sample.time), x(end));
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x1:.1}" y="{strip_y}" width="{:.1}" height="{}" fill="{}"/>"#,
+            (x2 - x1).max(0.5),
+            STRIP_HEIGHT,
+            level_color(sample.level)
+        );
+    }
+
+    let points: Vec<String> = samples
+        .iter()
+        .map(|sample| format!("{:.1},{:.1}", x(sample.time), y(sample.rpm)))
+        .collect();
+    let _ = writeln!(
+        svg,
+        r##"<polyline points="{}" fill="none" stroke="#36c" stroke-width="1.5"/>"##,
+        points.join(" ")
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Upload `svg` as `chart.file`. The file API needs a multipart request,
+/// which goes straight through reqwest with the OAuth token.
+async fn upload(
+    settings: &Settings,
+    chart: &ChartSettings,
+    svg: String,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    let api_url = chart.api_url.as_deref().unwrap_or(&settings.api_url);
+    let (client, _) = mw::ClientBuilder::new(api_url)
+        .user_agent(mw::ua!(user_agent!()))
+        .login_oauth(&settings.oauth_token)
+        .await?;
+    let token = csrf_token(&client).await?;
+    let description = format!(
+        "== Summary ==\nRevert rate and vandalism level of {} over the {} hours up to {}, \
+         drawn by [[User:DeadbeefBot|DeadbeefBot]].\n\n== Licensing ==\n{}",
+        settings.wiki,
+        chart.hours,
+        now.format("%Y-%m-%d %H:%M UTC"),
+        chart.license
+    );
+    let form = reqwest::multipart::Form::new()
+        .text("action", "upload")
+        .text("format", "json")
+        .text("filename", chart.file.clone())
+        .text(
+            "comment",
+            settings.messages.get("defcon-summary-chart", &[]),
+        )
+        .text("text", description)
+        .text("ignorewarnings", "1")
+        .text("maxlag", MAXLAG)
+        .text("assert", "bot")
+        .text("token", token)
+        .part(
+            "file",
+            reqwest::multipart::Part::text(svg)
+                .file_name(chart.file.clone())
+                .mime_str("image/svg+xml")?,
+        );
+    let res: Value = reqwest::Client::builder()
+        .user_agent(user_agent!())
+        .build()?
+        .post(api_url)
+        .bearer_auth(&settings.oauth_token)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(error) = res.get("error") {
+        bail!("upload of {} failed: {error}", chart.file);
+    }
+    match res["upload"]["result"].as_str() {
+        Some("Success") => Ok(()),
+        result => Err(eyre!(
+            "upload of {} didn't succeed: {}",
+            chart.file,
+            result.unwrap_or("no result")
+        )),
+    }
+}
+
+/// Draw the chart of the hours up to `now` from the database, upload it and
+/// update the gallery page.
+pub async fn update_chart(
+    client: &mw::Client,
+    settings: &Settings,
+    chart: &ChartSettings,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    let path = match &settings.database {
+        Some(path) => path,
+        None => bail!("the chart needs a `database` to draw from"),
+    };
+    let history = History::open(path, &settings.wiki)?;
+    let samples = history.since((now - Duration::hours(chart.hours)).timestamp())?;
+    let svg = render_svg(&samples, settings, now, chart.hours);
+    if settings.dry_run {
+        println!("would upload {} ({} samples)", chart.file, samples.len());
+        return Ok(());
+    }
+    if !writes_enabled(client, settings).await? {
+        return Ok(());
+    }
+    upload(settings, chart, svg, now).await?;
+    info!("uploaded {}", chart.file);
+
+    if let Some(page) = &chart.gallery_page {
+        let text = format!(
+            "<gallery>\nFile:{}|Revert rate and level over the last {} hours (updated {})\n</gallery>",
+            chart.file,
+            chart.hours,
+            now.format("%Y-%m-%d %H:%M UTC")
+        );
+        let summary = settings.messages.get("defcon-summary-chart", &[]);
+        edit_page(client, page, &text, &summary, None).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+use crate::model::Model;
+use crate::plugin::Plugin;
+
+static VANDALISM_KEYWORDS: [&str; 8] = [
+    "revert",
+    "rv ",
+    "long-term abuse",
+    "long term abuse",
+    "lta",
+    "abuse",
+    "rvv ",
+    "undid",
+];
+static NOT_VANDALISM_KEYWORDS: [&str; 12] = [
+    "uaa",
+    "good faith",
+    "agf",
+    "unsourced",
+    "unreferenced",
+    "self",
+    "speculat",
+    "original research",
+    "rv tag",
+    "typo",
+    "incorrect",
+    "format",
+];
+/// Change tags MediaWiki applies to reverting edits.
+pub static REVERT_TAGS: [&str; 3] = ["mw-rollback", "mw-undo", "mw-manual-revert"];
+
+lazy_static! {
+    /// One `/* section */` marker; summaries of edits to several sections
+    /// have one for each.
+    static ref SECTION_MARKER_RE: Regex = Regex::new(r"(?s)/\*.*?\*/").unwrap();
+    static ref WIKILINK_RE: Regex = Regex::new(r"\[\[([^\[\]|]*)(?:\|([^\[\]]*))?\]\]").unwrap();
+    static ref TEMPLATE_RE: Regex = Regex::new(r"\{\{[^{}]*\}\}").unwrap();
+    static ref EXTERNAL_LINK_RE: Regex = Regex::new(r"\[https?://[^\]]*\]|https?://\S+").unwrap();
+}
+
+/// Link targets whose text is a username, which tells nothing about the edit
+/// and may well contain a keyword.
+static USER_LINK_PREFIXES: [&str; 3] = ["user:", "user talk:", "special:contributions/"];
+
+/// A keyword list compiled into a single automaton, so that a summary is
+/// scanned once however long the list grows. Entries prefixed with `re:` in
+/// the settings are regular expressions, everything else is a plain
+/// substring; both match case-insensitively, against summaries folded with
+/// `fold_case`.
+struct KeywordSet(RegexSet);
+
+impl KeywordSet {
+    fn new(entries: &[String]) -> Result<Self, regex::Error> {
+        let patterns = entries.iter().map(|entry| match entry.strip_prefix("re:") {
+            Some(re) => re.to_owned(),
+            None => regex::escape(&fold_case(entry)),
+        });
+        let set = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()?;
+        Ok(KeywordSet(set))
+    }
+
+    fn matches(&self, edit_summary: &str) -> bool {
+        self.0.is_match(edit_summary)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Keywords {
+    #[serde(default = "default_vandalism_keywords")]
+    pub vandalism: Vec<String>,
+    #[serde(default = "default_not_vandalism_keywords")]
+    pub not_vandalism: Vec<String>,
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Keywords {
+            vandalism: default_vandalism_keywords(),
+            not_vandalism: default_not_vandalism_keywords(),
+        }
+    }
+}
+
+fn default_vandalism_keywords() -> Vec<String> {
+    VANDALISM_KEYWORDS
+        .iter()
+        .map(|&kwd| kwd.to_owned())
+        .collect()
+}
+
+fn default_not_vandalism_keywords() -> Vec<String> {
+    NOT_VANDALISM_KEYWORDS
+        .iter()
+        .map(|&kwd| kwd.to_owned())
+        .collect()
+}
+
+/// Edit summary classifier compiled from the configured keyword lists, and
+/// the classifier plugin and model if there are any.
+pub struct Classifier {
+    vandalism: KeywordSet,
+    not_vandalism: KeywordSet,
+    plugin: Option<Arc<Plugin>>,
+    model: Option<Arc<Model>>,
+}
+
+/// `text` case-folded, so that keywords match whatever the case and script:
+/// lowercased the Unicode way, with the letters whose lowercase depends on
+/// the position or the language mapped to a single form (final sigma to
+/// sigma, and Turkish dotted and dotless i to i).
+pub fn fold_case(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'İ' | 'ı' => folded.push('i'),
+            'ς' => folded.push('σ'),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// `edit_summary` without markup that only adds noise: templates and
+/// external links are removed, and so are links to users, while other
+/// wikilinks are replaced by the text they show, e.g. "rv [[WP:LTA|LTA]] by
+/// [[Special:Contributions/Foo|Foo]]" becomes "rv LTA by ".
+pub fn strip_markup(edit_summary: &str) -> String {
+    let text = TEMPLATE_RE.replace_all(edit_summary, " ");
+    let text = EXTERNAL_LINK_RE.replace_all(&text, " ");
+    WIKILINK_RE
+        .replace_all(&text, |caps: &regex::Captures<'_>| {
+            let target = caps[1]
+                .trim_start_matches(':')
+                .trim()
+                .replace('_', " ")
+                .to_lowercase();
+            if USER_LINK_PREFIXES
+                .iter()
+                .any(|prefix| target.starts_with(prefix))
+            {
+                return String::new();
+            }
+            caps.get(2)
+                .unwrap_or_else(|| caps.get(1).unwrap())
+                .as_str()
+                .to_owned()
+        })
+        .into_owned()
+}
+
+/// `edit_summary` without the section markers MediaWiki adds, each removed
+/// on its own so that the text between them stays.
+pub fn strip_section_markers(edit_summary: &str) -> String {
+    SECTION_MARKER_RE
+        .replace_all(edit_summary, " ")
+        .into_owned()
+}
+
+/// `edit_summary` without section markers and noisy markup, case-folded.
+fn normalize(edit_summary: &str) -> String {
+    let edit_summary = strip_section_markers(edit_summary);
+    fold_case(&strip_markup(&edit_summary))
+}
+
+impl Classifier {
+    pub fn new(keywords: &Keywords) -> Result<Self, regex::Error> {
+        Ok(Classifier {
+            vandalism: KeywordSet::new(&keywords.vandalism)?,
+            not_vandalism: KeywordSet::new(&keywords.not_vandalism)?,
+            plugin: None,
+            model: None,
+        })
+    }
+
+    pub fn with_plugin(self, plugin: Option<Arc<Plugin>>) -> Self {
+        Classifier { plugin, ..self }
+    }
+
+    pub fn with_model(self, model: Option<Arc<Model>>) -> Self {
+        Classifier { model, ..self }
+    }
+
+    /// What the model makes of an edit summary. Without a model, the edit
+    /// isn't counted.
+    pub fn is_revert_by_model(&self, edit_summary: &str) -> bool {
+        self.model
+            .as_ref()
+            .is_some_and(|model| model.is_revert_of_vandalism(&normalize(edit_summary)))
+    }
+
+    /// What the classifier plugin makes of an edit. Without a plugin, or if
+    /// it fails, the edit isn't counted.
+    pub fn is_revert_by_plugin(&self, edit_summary: &str, tags: &[String]) -> bool {
+        let plugin = match &self.plugin {
+            Some(plugin) => plugin,
+            None => return false,
+        };
+        plugin.classify(edit_summary, tags).unwrap_or_else(|e| {
+            tracing::warn!("{e:?}");
+            false
+        })
+    }
+
+    pub fn is_revert_of_vandalism(&self, edit_summary: &str) -> bool {
+        let edit_summary = normalize(edit_summary);
+        !self.not_vandalism.matches(&edit_summary) && self.vandalism.matches(&edit_summary)
+    }
+}
+
+pub fn has_revert_tag(tags: &[String]) -> bool {
+    tags.iter().any(|tag| REVERT_TAGS.contains(&tag.as_str()))
+}
+
+/// How reverts are told apart from other edits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detection {
+    /// Match the edit summary against the keyword lists.
+    #[default]
+    Keywords,
+    /// Count edits carrying one of the `REVERT_TAGS`.
+    Tags,
+    /// Count an edit if either of the above flags it.
+    Both,
+    /// Ask the classifier plugin.
+    Plugin,
+    /// Score the edit summary with the n-gram model in `model`.
+    Model,
+}
+
+pub fn is_revert(
+    classifier: &Classifier,
+    detection: Detection,
+    edit_summary: &str,
+    tags: &[String],
+) -> bool {
+    match detection {
+        Detection::Keywords => classifier.is_revert_of_vandalism(edit_summary),
+        Detection::Tags => has_revert_tag(tags),
+        Detection::Both => has_revert_tag(tags) || classifier.is_revert_of_vandalism(edit_summary),
+        Detection::Plugin => classifier.is_revert_by_plugin(edit_summary, tags),
+        Detection::Model => classifier.is_revert_by_model(edit_summary),
+    }
+}
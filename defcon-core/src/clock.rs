@@ -0,0 +1,26 @@
+use chrono::prelude::*;
+
+/// Where the current time comes from. Each run reads it once and works with
+/// that instant throughout, so that the window boundaries of all metrics
+/// line up; a fixed clock makes a run reproducible.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock stopped at one instant.
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
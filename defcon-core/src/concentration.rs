@@ -0,0 +1,211 @@
+//! Noticing when most of the reverts undo edits from a single account or IP
+//! range. A concentrated attack is better met with a block than with more
+//! patrollers, so it's mentioned in the info text and can raise an alert.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use tracing::{info, warn};
+
+use crate::metrics::Context;
+use crate::rpm::{recent_edits, RecentEdits};
+use crate::settings::Settings;
+use crate::spike::send_alert;
+
+/// Settings of the `[concentration]` table.
+#[derive(serde::Deserialize)]
+pub struct ConcentrationSettings {
+    /// Share of the reverts, from 0 to 1, that have to be against one source
+    /// for the wave to count as concentrated.
+    #[serde(default = "default_min_share")]
+    pub min_share: f64,
+    /// Reverts against one source below which nothing is concentrated.
+    #[serde(default = "default_min_reverts")]
+    pub min_reverts: usize,
+    /// Prefix length IPv4 addresses are grouped into ranges by.
+    #[serde(default = "default_ipv4_prefix")]
+    pub ipv4_prefix: u8,
+    /// Prefix length IPv6 addresses are grouped into ranges by.
+    #[serde(default = "default_ipv6_prefix")]
+    pub ipv6_prefix: u8,
+    /// JSON file the last alert is kept in, needed for `noticeboard` and
+    /// `webhook`.
+    pub file: Option<PathBuf>,
+    /// Minutes after an alert about a source during which it isn't alerted
+    /// about again.
+    #[serde(default = "default_cooldown_mins")]
+    pub cooldown_mins: i64,
+    /// Page to add a new section to.
+    pub noticeboard: Option<String>,
+    /// URL to POST `{"text": ...}` to.
+    pub webhook: Option<String>,
+}
+
+fn default_min_share() -> f64 {
+    0.5
+}
+
+fn default_min_reverts() -> usize {
+    5
+}
+
+fn default_ipv4_prefix() -> u8 {
+    24
+}
+
+fn default_ipv6_prefix() -> u8 {
+    64
+}
+
+fn default_cooldown_mins() -> i64 {
+    60
+}
+
+/// The source most of the recent reverts were against.
+pub struct Concentration {
+    /// The account, or the IP range in CIDR notation.
+    pub source: String,
+    /// Reverts against it.
+    pub reverts: usize,
+    /// Its share of all the reverts, from 0 to 1.
+    pub share: f64,
+}
+
+/// The account `user` edits from, or the range its IP address is in.
+pub fn source(concentration: &ConcentrationSettings, user: &str) -> String {
+    match user.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let prefix = concentration.ipv4_prefix.min(32);
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            format!("{}/{prefix}", Ipv4Addr::from(u32::from(ip) & mask))
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let prefix = concentration.ipv6_prefix.min(128);
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            format!("{}/{prefix}", Ipv6Addr::from(u128::from(ip) & mask))
+        }
+        Err(_) => user.to_owned(),
+    }
+}
+
+/// The source of at least `min_share` and `min_reverts` of `recent.reverts`,
+/// if there is one. Reverts whose reverted user isn't known count towards
+/// the total only.
+pub fn find(concentration: &ConcentrationSettings, recent: &RecentEdits) -> Option<Concentration> {
+    let mut per_source: HashMap<String, usize> = HashMap::new();
+    for user in recent
+        .reverts
+        .iter()
+        .filter_map(|revert| revert.reverted.as_ref())
+    {
+        *per_source.entry(source(concentration, user)).or_default() += 1;
+    }
+    let (source, reverts) = per_source
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))?;
+    let share = reverts as f64 / recent.reverts.len() as f64;
+    (reverts >= concentration.min_reverts && share >= concentration.min_share).then(|| {
+        Concentration {
+            source,
+            reverts,
+            share,
+        }
+    })
+}
+
+/// Whether the reverts of the window up to `ctx.now` are concentrated, if
+/// `[concentration]` is set.
+pub async fn check(ctx: &Context<'_>) -> color_eyre::Result<Option<Concentration>> {
+    let concentration = match &ctx.settings.concentration {
+        Some(concentration) => concentration,
+        None => return Ok(None),
+    };
+    let recent = recent_edits(ctx).await?;
+    let found = find(concentration, &recent);
+    if let Some(found) = &found {
+        info!(
+            source = %found.source,
+            reverts = found.reverts,
+            share = found.share,
+            "reverts are concentrated"
+        );
+    }
+    Ok(found)
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct AlertState {
+    /// Unix timestamp of the last alert by source.
+    last_alerts: HashMap<String, i64>,
+}
+
+impl AlertState {
+    fn load(path: &Path) -> color_eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("invalid concentration state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AlertState::default()),
+            Err(e) => Err(e)
+                .wrap_err_with(|| format!("could not read concentration state {}", path.display())),
+        }
+    }
+
+    fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write concentration state {}", path.display()))
+    }
+}
+
+/// Alert about `found` unless its source was alerted about in the last
+/// `cooldown_mins`. Alerting failures are logged but don't fail the run.
+pub async fn alert(
+    client: &mw::Client,
+    settings: &Settings,
+    concentration: &ConcentrationSettings,
+    found: &Concentration,
+    level: u8,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    let path = match &concentration.file {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let message = format!(
+        "{} of the reverts ({:.0}%) on {} undo edits from {} (level {level})",
+        found.reverts,
+        found.share * 100.0,
+        settings.wiki,
+        found.source
+    );
+    if settings.dry_run {
+        println!("would alert: {message}");
+        return Ok(());
+    }
+    let mut state = AlertState::load(path)?;
+    let cooldown_secs = concentration.cooldown_mins * 60;
+    state
+        .last_alerts
+        .retain(|_, last| now.timestamp() - *last < cooldown_secs);
+    if state.last_alerts.contains_key(&found.source) {
+        info!("not alerting again so soon: {message}");
+        return Ok(());
+    }
+    warn!("{message}");
+    send_alert(
+        client,
+        settings,
+        concentration.noticeboard.as_deref(),
+        concentration.webhook.as_deref(),
+        "Concentrated vandalism",
+        &settings.messages.get("defcon-summary-concentration", &[]),
+        &message,
+    )
+    .await;
+    state
+        .last_alerts
+        .insert(found.source.clone(), now.timestamp());
+    state.save(path)
+}
@@ -0,0 +1,240 @@
+//! Samples of recent edits with the classifier's verdict, written as TSV for
+//! someone to label by hand, and measuring the classifier against the
+//! labels.
+
+use std::io::Write;
+
+use chrono::prelude::*;
+use color_eyre::eyre::bail;
+
+use crate::classifier::{is_revert, Classifier};
+use crate::rpm::fetch_changes;
+use crate::settings::Settings;
+
+/// Columns of the TSV, the last one left empty for the label: 1 if the edit
+/// reverts vandalism, 0 if not.
+pub const COLUMNS: [&str; 7] = [
+    "rcid",
+    "timestamp",
+    "title",
+    "summary",
+    "tags",
+    "verdict",
+    "is_vandalism_revert",
+];
+
+/// An edit and what the classifier made of it.
+pub struct Row {
+    pub rcid: u64,
+    pub time: DateTime<Utc>,
+    pub title: String,
+    pub summary: String,
+    pub tags: Vec<String>,
+    /// Whether the classifier counts it as a revert of vandalism.
+    pub verdict: bool,
+}
+
+/// Up to `count` of the edits made since `since`: up to half of them ones
+/// the classifier counts, which would be too rare to judge it by otherwise,
+/// and the rest ones it doesn't. Both are spread evenly over the period.
+pub async fn sample(
+    client: &mw::Client,
+    settings: &Settings,
+    classifier: &Classifier,
+    since: DateTime<Utc>,
+    count: usize,
+) -> color_eyre::Result<Vec<Row>> {
+    let changes = fetch_changes(client, settings, since, None).await?;
+    let (positives, negatives): (Vec<Row>, Vec<Row>) = changes
+        .into_iter()
+        .filter(|change| !settings.excluded_users.contains(&change.user))
+        .map(|change| Row {
+            rcid: change.rcid,
+            time: Utc
+                .timestamp_opt(change.timestamp, 0)
+                .single()
+                .unwrap_or(since),
+            verdict: is_revert(
+                classifier,
+                settings.detection,
+                &change.comment,
+                &change.tags,
+            ),
+            title: change.title,
+            summary: change.comment,
+            tags: change.tags,
+        })
+        .partition(|row| row.verdict);
+    let positives = spread(positives, count / 2);
+    let negatives = spread(negatives, count - positives.len());
+    let mut rows: Vec<Row> = positives.into_iter().chain(negatives).collect();
+    rows.sort_by_key(|row| (row.time, row.rcid));
+    Ok(rows)
+}
+
+/// `count` of `rows`, evenly spaced.
+fn spread(rows: Vec<Row>, count: usize) -> Vec<Row> {
+    if rows.len() <= count {
+        return rows;
+    }
+    let len = rows.len();
+    rows.into_iter()
+        .enumerate()
+        .filter(|(i, _)| i * count / len != (i + 1) * count / len)
+        .map(|(_, row)| row)
+        .collect()
+}
+
+/// `field` without what would break a TSV line.
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Write `rows` as TSV with a header, leaving the labels empty.
+pub fn write_tsv(rows: &[Row], mut out: impl Write) -> color_eyre::Result<()> {
+    writeln!(out, "{}", COLUMNS.join("\t"))?;
+    for row in rows {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t",
+            row.rcid,
+            row.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            tsv_field(&row.title),
+            tsv_field(&row.summary),
+            tsv_field(&row.tags.join("|")),
+            u8::from(row.verdict),
+        )?;
+    }
+    Ok(())
+}
+
+/// A labeled edit from a TSV.
+pub struct Labeled {
+    /// Line of the TSV it's on, from 1.
+    pub line: usize,
+    pub summary: String,
+    pub tags: Vec<String>,
+    /// Whether it reverts vandalism.
+    pub label: bool,
+}
+
+fn parse_label(label: &str) -> Option<bool> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "y" => Some(true),
+        "0" | "false" | "no" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+/// The labeled edits in `tsv`: as written by `write_tsv` once labeled, or
+/// without a header as a summary and a label per line. Edits that weren't
+/// labeled are left out.
+pub fn read_tsv(tsv: &str) -> color_eyre::Result<Vec<Labeled>> {
+    let mut lines = tsv.lines().enumerate().peekable();
+    let header: Option<Vec<&str>> = lines
+        .peek()
+        .map(|&(_, line)| line.split('\t').collect())
+        .filter(|columns: &Vec<&str>| columns.contains(&"summary"));
+    let (summary, tags, label) = match &header {
+        Some(columns) => {
+            lines.next();
+            let index = |name: &str| columns.iter().position(|column| *column == name);
+            let label = match index("is_vandalism_revert") {
+                Some(label) => label,
+                None => bail!("the TSV has no `is_vandalism_revert` column"),
+            };
+            (index("summary").unwrap_or(0), index("tags"), label)
+        }
+        None => (0, None, 1),
+    };
+    let mut labeled = Vec::new();
+    for (i, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let value = fields.get(label).copied().unwrap_or("");
+        if value.trim().is_empty() {
+            continue;
+        }
+        let label = match parse_label(value) {
+            Some(label) => label,
+            None => bail!("line {}: `{value}` isn't a label, use 1 or 0", i + 1),
+        };
+        labeled.push(Labeled {
+            line: i + 1,
+            summary: fields.get(summary).copied().unwrap_or("").to_owned(),
+            tags: tags
+                .and_then(|tags| fields.get(tags))
+                .filter(|tags| !tags.is_empty())
+                .map_or_else(Vec::new, |tags| {
+                    tags.split('|').map(str::to_owned).collect()
+                }),
+            label,
+        });
+    }
+    Ok(labeled)
+}
+
+/// How the classifier did against labeled edits.
+#[derive(Default)]
+pub struct Evaluation<'a> {
+    pub true_positives: usize,
+    pub true_negatives: usize,
+    /// Edits counted as reverts of vandalism that aren't.
+    pub false_positives: Vec<&'a Labeled>,
+    /// Reverts of vandalism that weren't counted.
+    pub false_negatives: Vec<&'a Labeled>,
+}
+
+impl Evaluation<'_> {
+    /// The share of the edits counted that are reverts of vandalism.
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives.len(),
+        )
+    }
+
+    /// The share of the reverts of vandalism that are counted.
+    pub fn recall(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives.len(),
+        )
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 / whole as f64
+}
+
+/// Run the classifier of `settings` over `labeled`.
+pub fn evaluate<'a>(
+    settings: &Settings,
+    classifier: &Classifier,
+    labeled: &'a [Labeled],
+) -> Evaluation<'a> {
+    let mut evaluation = Evaluation::default();
+    for edit in labeled {
+        let verdict = is_revert(classifier, settings.detection, &edit.summary, &edit.tags);
+        match (verdict, edit.label) {
+            (true, true) => evaluation.true_positives += 1,
+            (false, false) => evaluation.true_negatives += 1,
+            (true, false) => evaluation.false_positives.push(edit),
+            (false, true) => evaluation.false_negatives.push(edit),
+        }
+    }
+    evaluation
+}
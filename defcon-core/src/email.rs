@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::WrapErr;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::info;
+
+use crate::settings::Settings;
+use crate::wiki::{index_url, ReportRevision};
+
+/// Settings of the `[email]` table, which emails the operator when the level
+/// stays high.
+#[derive(serde::Deserialize)]
+pub struct EmailSettings {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Alert when the level is at this level or a more severe one...
+    #[serde(default = "default_level")]
+    pub level: u8,
+    /// ...for this many minutes.
+    #[serde(default = "default_after_mins")]
+    pub after_mins: i64,
+    /// JSON file remembering which incident was last emailed about.
+    pub file: PathBuf,
+    /// Also email when a level change can't be published because the page
+    /// is protected.
+    #[serde(default)]
+    pub notify_uneditable: bool,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_level() -> u8 {
+    1
+}
+
+fn default_after_mins() -> i64 {
+    30
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct EmailState {
+    /// Revision of the report page that set the level last emailed about.
+    last_incident: Option<u64>,
+}
+
+impl EmailState {
+    fn load(path: &Path) -> color_eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("invalid email state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(EmailState::default()),
+            Err(e) => {
+                Err(e).wrap_err_with(|| format!("could not read email state {}", path.display()))
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write email state {}", path.display()))
+    }
+}
+
+/// Email `body` to `email.to`.
+pub async fn send(email: &EmailSettings, subject: &str, body: String) -> color_eyre::Result<()> {
+    let mut message = Message::builder()
+        .from(email.from.parse::<Mailbox>()?)
+        .subject(subject);
+    for to in &email.to {
+        message = message.to(to.parse::<Mailbox>()?);
+    }
+    let message = message.body(body)?;
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.smtp_host)?
+        .port(email.smtp_port);
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(message).await?;
+    Ok(())
+}
+
+/// Email the operator about a problem with the bot, if there's an `[email]`,
+/// logging any failure to.
+pub async fn notify(settings: &Settings, subject: &str, body: String) {
+    let email = match &settings.email {
+        Some(email) => email,
+        None => return,
+    };
+    if settings.dry_run {
+        println!("would email {}: {subject}", email.to.join(", "));
+        return;
+    }
+    if let Err(e) = send(email, subject, body).await {
+        tracing::error!("could not email {}: {e:?}", email.to.join(", "));
+    }
+}
+
+/// Email the operator if the level on the report page has been at
+/// `email.level` or worse for `email.after_mins` and the newly computed
+/// `level` keeps it there. Each stretch at that level is emailed about once;
+/// it counts as the same one for as long as the report page isn't edited.
+pub async fn check_sustained(
+    settings: &Settings,
+    email: &EmailSettings,
+    rev: &ReportRevision,
+    level: u8,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    if !settings.levels.is_as_severe(rev.level, email.level)
+        || !settings.levels.is_as_severe(level, email.level)
+    {
+        return Ok(());
+    }
+    let since = match rev.timestamp {
+        Some(since) => since,
+        None => return Ok(()),
+    };
+    if now - since < Duration::minutes(email.after_mins) {
+        return Ok(());
+    }
+    let mut state = EmailState::load(&email.file)?;
+    if state.last_incident == Some(rev.revid) {
+        return Ok(());
+    }
+
+    let subject = format!(
+        "{} has been at level {} since {}",
+        settings.wiki,
+        rev.level,
+        since.format("%H:%M UTC")
+    );
+    let body = format!(
+        "The vandalism level on {} has been at level {} since {}.\n\n\
+         Recent changes: {}\nReport page: {}\n",
+        settings.wiki,
+        rev.level,
+        since.format("%Y-%m-%d %H:%M UTC"),
+        index_url(settings, "Special:RecentChanges"),
+        index_url(settings, &settings.report_page)
+    );
+    if settings.dry_run {
+        println!("would email {}: {subject}", email.to.join(", "));
+        return Ok(());
+    }
+    send(email, &subject, body).await?;
+    info!("emailed {}: {subject}", email.to.join(", "));
+    state.last_incident = Some(rev.revid);
+    state.save(&email.file)
+}
@@ -0,0 +1,50 @@
+/// The failures callers act on, carried inside `color_eyre::Report`s and
+/// found again with `DefconError::find`.
+#[derive(Debug, thiserror::Error)]
+pub enum DefconError {
+    /// The settings are invalid.
+    #[error("{0}")]
+    Config(String),
+    /// An `error` in an API response.
+    #[error("API error {code}: {info}")]
+    Api { code: String, info: String },
+    /// The API refused an edit because the page changed since its base
+    /// revision.
+    #[error("edit conflict: {0}")]
+    EditConflict(String),
+    /// A response or page doesn't have the expected shape.
+    #[error("{0}")]
+    Parse(String),
+    /// The bot may not edit: the shutoff page says so, or its edits are
+    /// refused.
+    #[error("{0}")]
+    WritesDisabled(String),
+    /// Another run holds the `lock_file`.
+    #[error("{0}")]
+    Locked(String),
+    /// A publisher found the level it was given no longer stands, the report
+    /// page having changed since it was decided.
+    #[error("{0}")]
+    Declined(String),
+}
+
+impl DefconError {
+    /// The `DefconError` behind `e`, if there is one.
+    pub fn find(e: &color_eyre::Report) -> Option<&DefconError> {
+        e.chain().find_map(|e| e.downcast_ref::<DefconError>())
+    }
+
+    /// The process exit status for this failure, following `sysexits.h`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            // EX_CONFIG
+            DefconError::Config(_) => 78,
+            // EX_UNAVAILABLE
+            DefconError::Api { .. } | DefconError::WritesDisabled(_) => 69,
+            // EX_TEMPFAIL
+            DefconError::EditConflict(_) | DefconError::Locked(_) | DefconError::Declined(_) => 75,
+            // EX_DATAERR
+            DefconError::Parse(_) => 65,
+        }
+    }
+}
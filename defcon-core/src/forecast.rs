@@ -0,0 +1,46 @@
+use chrono::{prelude::*, Duration};
+
+use crate::history::History;
+use crate::settings::Settings;
+
+/// Smoothing factor of the level in Holt's method.
+const ALPHA: f64 = 0.5;
+/// Smoothing factor of the trend.
+const BETA: f64 = 0.3;
+
+/// Forecast the value `steps` samples after the last of `series` with Holt's
+/// linear exponential smoothing. Needs at least two samples.
+pub fn holt(series: &[f64], steps: f64) -> Option<f64> {
+    let (first, rest) = series.split_first()?;
+    let mut level = *first;
+    let mut trend = rest.first()? - first;
+    for &x in rest {
+        let previous = level;
+        level = ALPHA * x + (1.0 - ALPHA) * (level + trend);
+        trend = BETA * (level - previous) + (1.0 - BETA) * trend;
+    }
+    // a negative rate is as good as none
+    Some((level + steps * trend).max(0.0))
+}
+
+/// Hours of history the forecast is based on.
+const FORECAST_HISTORY_HOURS: i64 = 6;
+
+/// Forecast the RPM an hour from `now` from the samples in `history`, the
+/// last of which is `rpm`.
+pub fn forecast_rpm(
+    history: &History,
+    settings: &Settings,
+    now: DateTime<Utc>,
+    rpm: f32,
+) -> color_eyre::Result<Option<f32>> {
+    let since = (now - Duration::hours(FORECAST_HISTORY_HOURS)).timestamp();
+    let mut series: Vec<f64> = history
+        .since(since)?
+        .into_iter()
+        .map(|sample| sample.rpm)
+        .collect();
+    series.push(f64::from(rpm));
+    let steps = 60.0 / settings.run_interval_mins.max(1) as f64;
+    Ok(holt(&series, steps).map(|forecast| forecast as f32))
+}
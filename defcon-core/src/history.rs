@@ -0,0 +1,141 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use rusqlite::{params, Connection};
+
+use crate::metrics::Metrics;
+
+/// One run's worth of data.
+pub struct Sample {
+    /// Unix timestamp of the run.
+    pub time: i64,
+    pub rpm: f64,
+    /// The level on the report page after this run: the one computed if it
+    /// was published, else the one already there (0 for none).
+    pub level: u8,
+    pub metrics: Metrics,
+}
+
+/// Every sample taken for a wiki, kept in an SQLite database that may be
+/// shared between profiles.
+pub struct History {
+    conn: Connection,
+    wiki: String,
+}
+
+impl History {
+    pub fn open(path: &Path, wiki: &str) -> color_eyre::Result<Self> {
+        let conn = Connection::open(path)
+            .wrap_err_with(|| format!("could not open database {}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                wiki TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                rpm REAL NOT NULL,
+                level INTEGER NOT NULL,
+                metrics TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS samples_wiki_time ON samples (wiki, time);",
+        )?;
+        Ok(History {
+            conn,
+            wiki: wiki.to_owned(),
+        })
+    }
+
+    pub fn record(&self, sample: &Sample) -> color_eyre::Result<()> {
+        self.conn.execute(
+            "INSERT INTO samples (wiki, time, rpm, level, metrics) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.wiki,
+                sample.time,
+                sample.rpm,
+                sample.level,
+                serde_json::to_string(&sample.metrics)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The samples taken at or after `time`, oldest first.
+    pub fn since(&self, time: i64) -> color_eyre::Result<Vec<Sample>> {
+        self.query(
+            "SELECT time, rpm, level, metrics FROM samples
+             WHERE wiki = ?1 AND time >= ?2 ORDER BY time",
+            params![self.wiki, time],
+        )
+    }
+
+    /// The samples taken from `from` up to but excluding `to`, oldest first.
+    pub fn between(&self, from: i64, to: i64) -> color_eyre::Result<Vec<Sample>> {
+        self.query(
+            "SELECT time, rpm, level, metrics FROM samples
+             WHERE wiki = ?1 AND time >= ?2 AND time < ?3 ORDER BY time",
+            params![self.wiki, from, to],
+        )
+    }
+
+    /// The last `limit` samples, newest first.
+    pub fn latest(&self, limit: u32) -> color_eyre::Result<Vec<Sample>> {
+        self.query(
+            "SELECT time, rpm, level, metrics FROM samples
+             WHERE wiki = ?1 ORDER BY time DESC LIMIT ?2",
+            params![self.wiki, limit],
+        )
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> color_eyre::Result<Vec<Sample>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, u8>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        let mut samples = Vec::new();
+        for row in rows {
+            let (time, rpm, level, metrics) = row?;
+            samples.push(Sample {
+                time,
+                rpm,
+                level,
+                metrics: serde_json::from_str(&metrics)
+                    .wrap_err("invalid metrics in the database")?,
+            });
+        }
+        Ok(samples)
+    }
+}
+
+/// Write `samples` as CSV, with a column for every metric in any of them.
+pub fn write_csv(samples: &[Sample], mut out: impl Write) -> color_eyre::Result<()> {
+    let names: BTreeSet<&str> = samples
+        .iter()
+        .flat_map(|sample| sample.metrics.keys().map(String::as_str))
+        .collect();
+    write!(out, "time,rpm,level")?;
+    for name in &names {
+        write!(out, ",{name}")?;
+    }
+    writeln!(out)?;
+    for sample in samples {
+        let time = Utc
+            .timestamp_opt(sample.time, 0)
+            .single()
+            .map_or_else(String::new, |ts| ts.to_rfc3339());
+        write!(out, "{time},{},{}", sample.rpm, sample.level)?;
+        for name in &names {
+            match sample.metrics.get(*name) {
+                Some(value) => write!(out, ",{value}")?,
+                None => write!(out, ",")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
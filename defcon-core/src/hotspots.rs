@@ -0,0 +1,90 @@
+//! A list of the pages with the most reverts of vandalism in the window, so
+//! that patrollers can watchlist them.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use tracing::info;
+
+use crate::metrics::Context;
+use crate::rpm::{recent_edits, RecentEdits};
+use crate::settings::Settings;
+use crate::wiki::{edit_page, latest_revision, writes_enabled};
+
+/// Settings of the `[hotspots]` table.
+#[derive(serde::Deserialize)]
+pub struct HotspotSettings {
+    /// Page the list is written to, e.g. a subpage of the report page.
+    pub page: String,
+    /// Number of pages listed.
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+fn default_count() -> usize {
+    10
+}
+
+/// Up to `count` of the pages with the most reverts in `recent` and how
+/// many each had, most first.
+pub fn top_pages(recent: &RecentEdits, count: usize) -> Vec<(String, usize)> {
+    let mut per_page: HashMap<&str, usize> = HashMap::new();
+    for revert in &recent.reverts {
+        *per_page.entry(&revert.title).or_default() += 1;
+    }
+    let mut pages: Vec<(String, usize)> = per_page
+        .into_iter()
+        .map(|(title, reverts)| (title.to_owned(), reverts))
+        .collect();
+    pages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    pages.truncate(count);
+    pages
+}
+
+pub fn wikitext(settings: &Settings, pages: &[(String, usize)]) -> String {
+    let mut text = format!(
+        "Pages with the most reverts of vandalism on {} in the last {} minutes.\n\n",
+        settings.wiki, settings.window_mins
+    );
+    if pages.is_empty() {
+        text.push_str("None right now.\n");
+        return text;
+    }
+    text.push_str("{| class=\"wikitable\"\n! Page !! Reverts\n");
+    for (title, reverts) in pages {
+        let _ = writeln!(text, "|-\n| [[:{title}]] || {reverts}");
+    }
+    text.push_str("|}\n");
+    text
+}
+
+/// Write the current list to the hotspots page, unless it's already there.
+pub async fn update(ctx: &Context<'_>, hotspots: &HotspotSettings) -> color_eyre::Result<()> {
+    let Context {
+        client, settings, ..
+    } = *ctx;
+    let recent = recent_edits(ctx).await?;
+    let text = wikitext(settings, &top_pages(&recent, hotspots.count));
+    let rev = latest_revision(client, &hotspots.page, "ids|content").await?;
+    // saving drops the trailing newline
+    if rev["slots"]["main"]["content"].as_str() == Some(text.trim_end()) {
+        return Ok(());
+    }
+    if settings.dry_run {
+        println!("would edit {}:\n{text}", hotspots.page);
+        return Ok(());
+    }
+    if !writes_enabled(client, settings).await? {
+        return Ok(());
+    }
+    edit_page(
+        client,
+        &hotspots.page,
+        &text,
+        &settings.messages.get("defcon-summary-hotspots", &[]),
+        rev["revid"].as_u64(),
+    )
+    .await?;
+    info!("updated {}", hotspots.page);
+    Ok(())
+}
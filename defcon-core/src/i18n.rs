@@ -0,0 +1,81 @@
+//! Localized messages for the text the bot writes to the wiki, in banana
+//! format: one JSON file per language mapping message keys to text with `$1`,
+//! `$2`, ... for parameters.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use color_eyre::eyre::{bail, WrapErr};
+
+const FALLBACK_LANGUAGE: &str = "en";
+const BUILT_IN: [(&str, &str); 1] = [("en", include_str!("../i18n/en.json"))];
+
+/// The messages of one language, with English filling in the gaps.
+#[derive(Default)]
+pub struct Messages {
+    messages: HashMap<String, String>,
+}
+
+fn parse(json: &str) -> color_eyre::Result<HashMap<String, String>> {
+    let mut messages: HashMap<String, serde_json::Value> = serde_json::from_str(json)?;
+    messages.remove("@metadata");
+    messages
+        .into_iter()
+        .map(|(key, value)| -> color_eyre::Result<(String, String)> {
+            match value {
+                serde_json::Value::String(text) => Ok((key, text)),
+                _ => bail!("message `{key}` must be a string"),
+            }
+        })
+        .collect()
+}
+
+impl Messages {
+    /// The messages of `language`, from `<dir>/<language>.json` or the
+    /// built-in ones. Messages missing from both fall back to English.
+    pub fn load(language: &str, dir: Option<&Path>) -> color_eyre::Result<Self> {
+        let mut messages = HashMap::new();
+        let mut found = false;
+        let languages = if language == FALLBACK_LANGUAGE {
+            vec![FALLBACK_LANGUAGE]
+        } else {
+            vec![FALLBACK_LANGUAGE, language]
+        };
+        for lang in languages {
+            // later files override earlier ones
+            if let Some((_, json)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == lang) {
+                messages.extend(parse(json)?);
+                found |= lang == language;
+            }
+            if let Some(dir) = dir {
+                let path = dir.join(format!("{lang}.json"));
+                if path.exists() {
+                    let json = std::fs::read_to_string(&path)
+                        .wrap_err_with(|| format!("could not read {}", path.display()))?;
+                    messages.extend(
+                        parse(&json).wrap_err_with(|| format!("invalid {}", path.display()))?,
+                    );
+                    found |= lang == language;
+                }
+            }
+        }
+        if !found {
+            bail!("no messages for language `{language}`; add `{language}.json` to `messages_dir`");
+        }
+        Ok(Messages { messages })
+    }
+
+    /// The message `key` with `$1`, `$2`, ... replaced by `params`. A missing
+    /// message shows its key, the way MediaWiki does.
+    pub fn get(&self, key: &str, params: &[&str]) -> String {
+        let mut text = match self.messages.get(key) {
+            Some(text) => text.clone(),
+            None => return format!("⧼{key}⧽"),
+        };
+        // backwards, so that `$1` doesn't eat the start of `$10`
+        for (i, param) in params.iter().enumerate().rev() {
+            text = text.replace(&format!("${}", i + 1), param);
+        }
+        text
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+
+use futures_util::future::{join_all, BoxFuture};
+use tokio::time::MissedTickBehavior;
+
+use crate::chart::update_chart;
+use crate::clock::Clock;
+use crate::schedule::jitter;
+use crate::settings::Settings;
+use crate::stats::post_weekly_stats;
+use crate::stop;
+
+/// Run `job` every `mins` minutes plus up to `jitter_secs`, logging its
+/// failures, until a stop is requested.
+async fn every<F, Fut>(mins: u64, jitter_secs: u64, name: &str, mut job: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = color_eyre::Result<()>>,
+{
+    let period = std::time::Duration::from_secs(mins.max(1) * 60);
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut stops = stop::subscribe();
+    loop {
+        tokio::select! {
+            _ = async {
+                interval.tick().await;
+                tokio::time::sleep(jitter(jitter_secs)).await;
+            } => {}
+            _ = stops.changed() => return,
+        }
+        if let Err(e) = job().await {
+            tracing::error!("{name} failed: {e:?}");
+        }
+    }
+}
+
+/// Run the periodic jobs other than the level update, for as long as the
+/// daemon runs or until a stop, each reading the time from `clock`. Returns
+/// right away if none are configured.
+pub async fn run_jobs(client: &mw::Client, settings: &Settings, clock: &dyn Clock) {
+    let mut jobs: Vec<BoxFuture<'_, ()>> = Vec::new();
+    if let Some(chart) = &settings.chart {
+        jobs.push(Box::pin(every(
+            chart.interval_mins,
+            settings.jitter_secs,
+            "chart",
+            move || update_chart(client, settings, chart, clock.now()),
+        )));
+    }
+    if let Some(stats) = &settings.stats {
+        // cheap to check, and posts soon after the week is over
+        jobs.push(Box::pin(every(
+            60,
+            settings.jitter_secs,
+            "weekly statistics",
+            move || post_weekly_stats(client, settings, stats, clock.now()),
+        )));
+    }
+    join_all(jobs).await;
+}
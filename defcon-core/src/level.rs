@@ -0,0 +1,165 @@
+use std::fmt;
+
+pub static DEFAULT_THRESHOLDS: [f32; 4] = [2.0, 4.0, 6.0, 8.0];
+pub static DEFAULT_LEVELS: [u8; 5] = [5, 4, 3, 2, 1];
+
+/// The levels a wiki uses, from the calmest to the most severe, e.g.
+/// `[5, 4, 3, 2, 1]` for enwiki's scale where 1 is the worst. 0 stands for
+/// no level, so it can't be one of them.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(transparent)]
+pub struct Levels(Vec<u8>);
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels(DEFAULT_LEVELS.to_vec())
+    }
+}
+
+impl Levels {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// The level `rank` steps up from the calmest one.
+    pub fn get(&self, rank: usize) -> Option<u8> {
+        self.0.get(rank).copied()
+    }
+
+    /// How many steps `level` is up from the calmest one, or `None` if it
+    /// isn't on the scale.
+    pub fn rank(&self, level: u8) -> Option<usize> {
+        self.0.iter().position(|&l| l == level)
+    }
+
+    pub fn contains(&self, level: u8) -> bool {
+        self.rank(level).is_some()
+    }
+
+    /// Whether `level` is on the scale and at least as severe as `than`.
+    pub fn is_as_severe(&self, level: u8, than: u8) -> bool {
+        match (self.rank(level), self.rank(than)) {
+            (Some(level), Some(than)) => level >= than,
+            _ => false,
+        }
+    }
+
+    /// How many steps apart `a` and `b` are, if both are on the scale.
+    pub fn distance(&self, a: u8, b: u8) -> Option<usize> {
+        Some(self.rank(a)?.abs_diff(self.rank(b)?))
+    }
+}
+
+/// Which way the RPM is heading.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trend {
+    Rising,
+    Falling,
+    Stable,
+}
+
+impl Trend {
+    /// Compare `rpm` to the `previous` sample. Changes of less than
+    /// `tolerance` (a fraction of `previous`) count as stable.
+    pub fn between(previous: Option<f32>, rpm: f32, tolerance: f32) -> Self {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Trend::Stable,
+        };
+        let change = rpm - previous;
+        if change.abs() <= previous * tolerance {
+            Trend::Stable
+        } else if change > 0.0 {
+            Trend::Rising
+        } else {
+            Trend::Falling
+        }
+    }
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Trend::Rising => "rising",
+            Trend::Falling => "falling",
+            Trend::Stable => "stable",
+        })
+    }
+}
+
+/// How many thresholds `rpm` is above, i.e. the rank of its level.
+fn rank(rpm: f32, thresholds: &[f32]) -> usize {
+    thresholds.iter().take_while(|&&t| rpm > t).count()
+}
+
+/// `thresholds` holds the upper RPM bound of every level but the most severe
+/// one, in increasing order; anything above the last one is the most severe
+/// level.
+pub fn rpm_to_level(rpm: f32, thresholds: &[f32], levels: &Levels) -> u8 {
+    levels.0[rank(rpm, thresholds)]
+}
+
+/// Where `value` falls on a 0–100 scale, for finer granularity than the
+/// levels: each level covers an equal share of it, from 0 for no activity up
+/// to the last threshold, and 100 once `value` is as far past the last
+/// threshold as that is past the one before it.
+pub fn severity(value: f32, thresholds: &[f32]) -> f32 {
+    let band = 100.0 / (thresholds.len() + 1) as f32;
+    let mut lower = 0.0;
+    for (i, &upper) in thresholds.iter().enumerate() {
+        if value <= upper {
+            let fraction = if upper > lower {
+                (value - lower) / (upper - lower)
+            } else {
+                1.0
+            };
+            return band * (i as f32 + fraction.max(0.0));
+        }
+        lower = upper;
+    }
+    let width = match thresholds {
+        [.., before, last] => last - before,
+        [last] => *last,
+        [] => return 100.0,
+    };
+    let fraction = if width > 0.0 {
+        (value - lower) / width
+    } else {
+        1.0
+    };
+    (band * (thresholds.len() as f32 + fraction)).min(100.0)
+}
+
+/// Like `rpm_to_level`, but only moves away from `curr_level` once `rpm` is
+/// past a threshold by more than `margin`, so that an RPM hovering around a
+/// threshold doesn't flip the level back and forth.
+pub fn next_level(
+    curr_level: u8,
+    rpm: f32,
+    thresholds: &[f32],
+    levels: &Levels,
+    margin: f32,
+) -> u8 {
+    let new = rank(rpm, thresholds);
+    let curr = match levels.rank(curr_level) {
+        Some(curr) => curr,
+        // no (valid) level on the page yet
+        None => return levels.0[new],
+    };
+    let new = if new > curr {
+        rank(rpm - margin, thresholds).max(curr)
+    } else if new < curr {
+        rank(rpm + margin, thresholds).min(curr)
+    } else {
+        new
+    };
+    levels.0[new]
+}
@@ -0,0 +1,63 @@
+//! Computing the vandalism level ("defcon") of a wiki from its recent
+//! changes, and publishing it to a report page.
+
+macro_rules! user_agent {
+    () => {
+        concat!(
+            "DeadbeefBot/defcon-rs/",
+            env!("CARGO_PKG_VERSION"),
+            " (https://en.wikipedia.org/wiki/User:DeadbeefBot)"
+        )
+    };
+}
+
+pub mod backtest;
+pub mod baseline;
+pub mod chart;
+pub mod classifier;
+pub mod clock;
+pub mod concentration;
+pub mod dataset;
+pub mod email;
+pub mod error;
+pub mod forecast;
+pub mod history;
+pub mod hotspots;
+pub mod i18n;
+pub mod jobs;
+pub mod level;
+pub mod live;
+pub mod lock;
+pub mod metrics;
+pub mod model;
+pub mod monitor;
+pub mod plugin;
+pub mod policy;
+pub mod publish;
+pub mod report;
+pub mod rpm;
+pub mod rules;
+pub mod run;
+pub mod schedule;
+pub mod scoring;
+pub mod server;
+pub mod settings;
+pub mod smoothing;
+pub mod spike;
+pub mod state;
+pub mod stats;
+pub mod stop;
+pub mod systemd;
+pub mod tape;
+pub mod tune;
+pub mod wiki;
+
+pub use classifier::{Classifier, Detection, Keywords};
+pub use error::DefconError;
+pub use level::{next_level, rpm_to_level, severity, Levels, Trend};
+pub use metrics::{MetricSource, Metrics};
+pub use publish::{LevelUpdate, Publisher};
+pub use rpm::reverts_per_minute;
+pub use rules::Rules;
+pub use scoring::{Score, Signal};
+pub use settings::{load_profiles, Settings};
@@ -0,0 +1,285 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{prelude::*, Duration};
+use futures_util::future::join;
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use tracing::info;
+
+use crate::classifier::is_revert;
+use crate::clock::Clock;
+use crate::concentration;
+use crate::jobs::run_jobs;
+use crate::metrics::{collect_all, Context, MetricSource};
+use crate::monitor;
+use crate::publish::Publisher;
+use crate::rpm::{count_reverts, named_user, window_key};
+use crate::rules::Rules;
+use crate::run::{update_hotspots, update_level};
+use crate::schedule::jitter;
+use crate::settings::Settings;
+use crate::stop;
+use crate::systemd;
+
+/// Minimal parser for `text/event-stream` bodies. Only `data` fields are
+/// kept; ids, event names and comments are ignored.
+#[derive(Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    data: String,
+}
+
+impl SseParser {
+    /// Feed a chunk of the response body, returning the data of every event
+    /// completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(std::mem::take(&mut self.data));
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                if !self.data.is_empty() {
+                    self.data.push('\n');
+                }
+                self.data.push_str(data.strip_prefix(' ').unwrap_or(data));
+            }
+        }
+        events
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RecentChangeEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    wiki: String,
+    namespace: i64,
+    title: String,
+    timestamp: i64,
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    bot: bool,
+    #[serde(default)]
+    comment: String,
+    /// The `rcid` of the change.
+    #[serde(default)]
+    id: Option<u64>,
+}
+
+/// What a profile followed live has seen of the stream, kept across reloads
+/// like the windows of `rpm`.
+struct LiveWindow {
+    /// The time, page and weight of the reverts in the window, oldest first.
+    reverts: VecDeque<(DateTime<Utc>, String, f32)>,
+    /// The time of the newest event seen, which the stream is resumed from.
+    since: DateTime<Utc>,
+    /// The `rcid` of the newest change to the wiki seen, so that the events
+    /// replayed when resuming aren't counted twice.
+    last_id: Option<u64>,
+}
+
+lazy_static! {
+    /// The live windows of the profiles run by this process, by
+    /// `rpm::window_key`.
+    static ref WINDOWS: Mutex<HashMap<String, LiveWindow>> = Mutex::new(HashMap::new());
+}
+
+/// Follow the EventStreams `recentchange` feed, keeping the timestamps, pages
+/// and weights of reverts in the last interval in memory and updating the
+/// report page every `run_interval_mins`, until a stop is requested. The
+/// other periodic jobs run alongside. After a reload, the stream is resumed
+/// from the newest event seen, with the reverts before it kept.
+pub async fn run_live(
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+    publishers: &[Box<dyn Publisher>],
+) -> color_eyre::Result<()> {
+    settings.validate_live()?;
+    systemd::ready();
+    let (_, res) = join(
+        run_jobs(client, settings, clock),
+        follow_stream(client, settings, clock, rules, sources, publishers),
+    )
+    .await;
+    res
+}
+
+async fn follow_stream(
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+    publishers: &[Box<dyn Publisher>],
+) -> color_eyre::Result<()> {
+    let http = reqwest::Client::builder()
+        .user_agent(user_agent!())
+        .build()?;
+    let window = Duration::minutes(settings.window_mins as i64);
+    let period = std::time::Duration::from_secs(settings.run_interval_mins.max(1) * 60);
+    let period_chrono = Duration::from_std(period)?;
+    // like an interval, but with its own jitter every time
+    let mut cycle = tokio::time::Instant::now();
+    let next_run = tokio::time::sleep_until(cycle + jitter(settings.jitter_secs));
+    tokio::pin!(next_run);
+
+    let namespaces = settings.namespace_ids()?;
+    let key = window_key(settings);
+    let cached = WINDOWS.lock().unwrap().remove(&key);
+    let (mut reverts, mut since, mut last_id) = match cached {
+        Some(cached) => (cached.reverts, cached.since, cached.last_id),
+        // start one interval back so the stream replays enough history to
+        // fill the window before we publish anything
+        None => (VecDeque::new(), clock.now() - window, None),
+    };
+    let mut caught_up = false;
+    let mut stops = stop::subscribe();
+
+    info!(
+        "following {} for {}",
+        settings.eventstreams_url, settings.wiki
+    );
+    loop {
+        let res = http
+            .get(&settings.eventstreams_url)
+            .query(&[("since", since.to_rfc3339_opts(SecondsFormat::Secs, true))])
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        let mut stream = match res {
+            Ok(res) => res.bytes_stream(),
+            Err(e) => {
+                tracing::warn!("could not connect to event stream: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        let mut parser = SseParser::default();
+
+        loop {
+            tokio::select! {
+                _ = stops.changed() => {
+                    WINDOWS.lock().unwrap().insert(key, LiveWindow { reverts, since, last_id });
+                    return Ok(());
+                }
+                chunk = stream.next() => {
+                    let chunk = match chunk {
+                        Some(Ok(chunk)) => chunk,
+                        Some(Err(e)) => {
+                            tracing::warn!("event stream failed: {e}");
+                            break;
+                        }
+                        None => {
+                            tracing::warn!("event stream closed");
+                            break;
+                        }
+                    };
+                    for data in parser.feed(&chunk) {
+                        let event: RecentChangeEvent = match serde_json::from_str(&data) {
+                            Ok(event) => event,
+                            Err(_) => continue,
+                        };
+                        let time = match Utc.timestamp_opt(event.timestamp, 0).single() {
+                            Some(time) => time,
+                            None => continue,
+                        };
+                        since = since.max(time);
+                        if !caught_up && clock.now() - time < Duration::minutes(1) {
+                            info!("event stream caught up");
+                            caught_up = true;
+                        }
+                        if event.wiki != settings.wiki {
+                            continue;
+                        }
+                        if let Some(id) = event.id {
+                            if last_id.is_some_and(|last| id <= last) {
+                                continue;
+                            }
+                            last_id = Some(id);
+                        }
+                        let classifier = rules.classifier();
+                        if event.kind == "edit"
+                            && (namespaces.is_empty() || namespaces.contains(&event.namespace))
+                            && !(settings.exclude_bots && event.bot)
+                            && !settings.excluded_users.contains(&event.user)
+                            && is_revert(classifier, settings.detection, &event.comment, &[])
+                            // without looking up revisions, only self-reverts
+                            // the summary shows
+                            && !(settings.exclude_self_reverts
+                                && named_user(&event.comment).as_ref() == Some(&event.user))
+                        {
+                            // the stream doesn't carry change tags
+                            let weight = settings.revert_weights.of(&[]);
+                            reverts.push_back((time, event.title, weight));
+                        }
+                    }
+                }
+                _ = &mut next_run, if caught_up => {
+                    cycle = (cycle + period).max(tokio::time::Instant::now());
+                    next_run.as_mut().reset(cycle + jitter(settings.jitter_secs));
+                    let now = clock.now();
+                    monitor::record_next_run(&settings.wiki, now + period_chrono);
+                    let cutoff = now - window;
+                    while reverts.front().is_some_and(|(t, _, _)| *t < cutoff) {
+                        reverts.pop_front();
+                    }
+                    let num_reverts = count_reverts(
+                        reverts.iter().map(|(_, title, weight)| (title.as_str(), *weight)),
+                        settings.max_reverts_per_page,
+                    );
+                    let rpm = num_reverts / (settings.window_mins as f32);
+                    rules.refresh(client).await;
+                    let ctx = Context::new(client, settings, rules.classifier(), now);
+                    // the revert rate comes from the stream, everything else
+                    // is still polled
+                    let polled = sources.iter().filter(|source| source.name() != "rpm");
+                    let started = std::time::Instant::now();
+                    let res = async {
+                        let mut metrics = collect_all(polled, &ctx).await?;
+                        monitor::record_api_latency(
+                            &settings.wiki,
+                            started.elapsed().as_secs_f64(),
+                        );
+                        metrics.insert("rpm".to_owned(), f64::from(rpm));
+                        info!("collected metrics: {metrics:?}");
+                        // polled too, from the recent changes
+                        let concentration = concentration::check(&ctx).await?;
+                        update_level(
+                            client,
+                            settings,
+                            &metrics,
+                            concentration.as_ref(),
+                            publishers,
+                            now,
+                        )
+                        .await?;
+                        update_hotspots(&ctx).await;
+                        Ok(())
+                    }
+                    .await;
+                    systemd::watchdog();
+                    monitor::record_run(&settings.wiki, res.is_ok());
+                    if let Err(e) = res {
+                        tracing::error!(
+                            last_api_code = monitor::last_api_code(&settings.wiki).as_deref(),
+                            "run failed: {e:?}"
+                        );
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
@@ -0,0 +1,37 @@
+//! The `lock_file`, held for as long as a run goes on so that two runs of a
+//! profile never overlap.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+use color_eyre::eyre::WrapErr;
+
+/// A held lock, released when dropped (or when the process exits, however it
+/// does).
+pub struct RunLock {
+    _file: File,
+}
+
+impl RunLock {
+    /// Lock the file at `path`, creating it if needed, or `None` if another
+    /// run holds it. The file says which process holds it.
+    pub fn acquire(path: &Path) -> color_eyre::Result<Option<Self>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .wrap_err_with(|| format!("could not open lock file {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).wrap_err_with(|| format!("could not lock {}", path.display()));
+            }
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Some(RunLock { _file: file }))
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use color_eyre::eyre::bail;
+use tokio::sync::OnceCell;
+
+use crate::classifier::Classifier;
+use crate::plugin::{PluginConfig, PluginMetric};
+use crate::rpm::{distinct_reverters, recent_edits, revert_ratio, reverts_per_minute, RecentEdits};
+use crate::settings::Settings;
+
+pub mod abusefilter;
+pub mod aiv;
+pub mod blocks;
+pub mod liftwing;
+pub mod rollbackers;
+
+/// Named metric values from one run.
+pub type Metrics = BTreeMap<String, f64>;
+
+/// What a metric source gets to work with when taking a sample.
+pub struct Context<'a> {
+    pub client: &'a mw::Client,
+    pub settings: &'a Settings,
+    pub classifier: &'a Classifier,
+    /// The time of the run, which every window ends at.
+    pub now: DateTime<Utc>,
+    /// The edits of the window, once something asked for them, see
+    /// `rpm::recent_edits`.
+    pub(crate) recent: OnceCell<RecentEdits>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(
+        client: &'a mw::Client,
+        settings: &'a Settings,
+        classifier: &'a Classifier,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Context {
+            client,
+            settings,
+            classifier,
+            now,
+            recent: OnceCell::new(),
+        }
+    }
+}
+
+/// A signal that feeds into the level, sampled once per run.
+#[async_trait]
+pub trait MetricSource: Send + Sync {
+    /// Name of the metric, as used in the settings and the output.
+    fn name(&self) -> &str;
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64>;
+}
+
+/// Reverts of vandalism per minute over the last interval.
+pub struct RevertRate;
+
+#[async_trait]
+impl MetricSource for RevertRate {
+    fn name(&self) -> &str {
+        "rpm"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let rpm = reverts_per_minute(ctx).await?;
+        Ok(f64::from(rpm))
+    }
+}
+
+/// Reverts of vandalism per edit over the last interval.
+pub struct RevertRatio;
+
+#[async_trait]
+impl MetricSource for RevertRatio {
+    fn name(&self) -> &str {
+        "ratio"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let ratio = revert_ratio(ctx).await?;
+        Ok(f64::from(ratio))
+    }
+}
+
+/// Distinct users reverting vandalism over the last interval. Many reverts by
+/// only a handful of patrollers means thin coverage.
+pub struct Reverters;
+
+#[async_trait]
+impl MetricSource for Reverters {
+    fn name(&self) -> &str {
+        "reverters"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let reverters = distinct_reverters(ctx).await?;
+        Ok(reverters as f64)
+    }
+}
+
+/// Reverts of vandalism per minute over the last interval on pages in one
+/// namespace, as `rpm_ns<number>`, e.g. `rpm_ns3` for user talk pages.
+/// Vandalism of different namespaces has different responders, whom the
+/// overall RPM doesn't tell apart.
+pub struct NamespaceRate {
+    name: String,
+    namespace: i64,
+}
+
+impl NamespaceRate {
+    /// The metric called `name`, if it's one.
+    pub fn parse(name: &str) -> Option<Self> {
+        let namespace = name.strip_prefix("rpm_ns")?.parse().ok()?;
+        Some(NamespaceRate {
+            name: name.to_owned(),
+            namespace,
+        })
+    }
+
+    pub fn namespace(&self) -> i64 {
+        self.namespace
+    }
+}
+
+#[async_trait]
+impl MetricSource for NamespaceRate {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let recent = recent_edits(ctx).await?;
+        Ok(f64::from(recent.rpm_in(ctx.settings, self.namespace)))
+    }
+}
+
+/// Build the metric sources named in `settings.metrics`, and those of the
+/// metric plugins.
+pub fn sources(settings: &Settings) -> color_eyre::Result<Vec<Box<dyn MetricSource>>> {
+    let mut sources = settings
+        .metrics
+        .iter()
+        .map(|name| -> color_eyre::Result<Box<dyn MetricSource>> {
+            Ok(match name.as_str() {
+                "rpm" => Box::new(RevertRate),
+                "ratio" => Box::new(RevertRatio),
+                "reverters" => Box::new(Reverters),
+                "damaging" => Box::new(liftwing::Damaging::new()?),
+                "revertrisk" => Box::new(liftwing::RevertRisk::new()?),
+                "abusefilter" => Box::new(abusefilter::AbuseFilterRate),
+                "blocks" => Box::new(blocks::BlockRate),
+                "aiv" => Box::new(aiv::AivBacklog),
+                "rollbacker_rpm" => Box::new(rollbackers::RollbackerRate),
+                "non_rollbacker_rpm" => Box::new(rollbackers::NonRollbackerRate),
+                _ => match NamespaceRate::parse(name) {
+                    Some(source) => Box::new(source),
+                    None => bail!("unknown metric `{name}`"),
+                },
+            })
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()?;
+    for plugin in &settings.plugins {
+        if let PluginConfig::Metric { name, path } = plugin {
+            sources.push(Box::new(PluginMetric::new(name.clone(), path)?));
+        }
+    }
+    Ok(sources)
+}
+
+/// Sample every source. A failing source fails the whole run, since the
+/// level would be computed from incomplete data otherwise.
+pub async fn collect_all<'s>(
+    sources: impl IntoIterator<Item = &'s Box<dyn MetricSource>>,
+    ctx: &Context<'_>,
+) -> color_eyre::Result<Metrics> {
+    let mut metrics = Metrics::new();
+    for source in sources {
+        let value = source.collect(ctx).await?;
+        tracing::debug!("{} = {value}", source.name());
+        metrics.insert(source.name().to_owned(), value);
+    }
+    Ok(metrics)
+}
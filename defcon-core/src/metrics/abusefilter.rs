@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration};
+
+use super::{Context, MetricSource};
+use crate::wiki::query_all;
+
+/// AbuseFilter hits per minute over the last interval, from `list=abuselog`.
+/// Filter hits tend to spike a few minutes before the reverts do.
+pub struct AbuseFilterRate;
+
+#[async_trait]
+impl MetricSource for AbuseFilterRate {
+    fn name(&self) -> &str {
+        "abusefilter"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let window_mins = ctx.settings.window_mins as i64;
+        let now = ctx.now;
+        let start = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let end = (now - Duration::minutes(window_mins)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let query = [
+            ("action", "query"),
+            ("list", "abuselog"),
+            ("aflstart", &start),
+            ("aflend", &end),
+            ("aflprop", "ids"),
+            ("afllimit", "max"),
+        ];
+        #[derive(serde::Deserialize)]
+        struct AbuseLog {
+            abuselog: Vec<serde::de::IgnoredAny>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Res {
+            query: AbuseLog,
+        }
+        let hits: usize = query_all(ctx.client, &query, |res: Res| {
+            Ok(vec![res.query.abuselog.len()])
+        })
+        .await?
+        .into_iter()
+        .sum();
+        Ok(hits as f64 / window_mins as f64)
+    }
+}
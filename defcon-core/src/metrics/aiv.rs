@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{Context, MetricSource};
+use crate::wiki::latest_revision;
+
+lazy_static! {
+    // every report starts with a {{vandal}} or {{IPvandal}} line, and
+    // reports are removed from the page once they're dealt with
+    static ref REPORT_RE: Regex =
+        Regex::new(r"(?im)^\*\s*\{\{\s*(?:ip)?vandal\s*\|").unwrap();
+}
+
+pub fn default_aiv_page() -> String {
+    "Wikipedia:Administrator intervention against vandalism".to_owned()
+}
+
+/// Number of open reports on `aiv_page`. A growing backlog means reports are
+/// coming in faster than admins can handle them.
+pub struct AivBacklog;
+
+#[async_trait]
+impl MetricSource for AivBacklog {
+    fn name(&self) -> &str {
+        "aiv"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let rev = latest_revision(ctx.client, &ctx.settings.aiv_page, "content").await?;
+        let text = rev["slots"]["main"]["content"].as_str().unwrap_or("");
+        Ok(REPORT_RE.find_iter(text).count() as f64)
+    }
+}
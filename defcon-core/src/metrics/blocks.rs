@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration};
+
+use super::{Context, MetricSource};
+use crate::wiki::query_all;
+
+static BLOCK_REASONS: [&str; 5] = ["vandal", "lta", "long-term abuse", "abuse", "sock"];
+
+pub fn default_block_reasons() -> Vec<String> {
+    BLOCK_REASONS
+        .iter()
+        .map(|&reason| reason.to_owned())
+        .collect()
+}
+
+/// Vandalism-related blocks per hour over the last interval, i.e. blocks
+/// whose reason contains one of `block_reasons`.
+pub struct BlockRate;
+
+#[async_trait]
+impl MetricSource for BlockRate {
+    fn name(&self) -> &str {
+        "blocks"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let window_mins = ctx.settings.window_mins as i64;
+        let now = ctx.now;
+        let start = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let end = (now - Duration::minutes(window_mins)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let query = [
+            ("action", "query"),
+            ("list", "logevents"),
+            ("letype", "block"),
+            ("leaction", "block/block"),
+            ("lestart", &start),
+            ("leend", &end),
+            ("leprop", "comment"),
+            ("lelimit", "max"),
+        ];
+        #[derive(serde::Deserialize)]
+        struct LogEvent {
+            #[serde(default)]
+            comment: String,
+        }
+        #[derive(serde::Deserialize)]
+        struct LogEvents {
+            logevents: Vec<LogEvent>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Res {
+            query: LogEvents,
+        }
+        let reasons: Vec<String> = ctx
+            .settings
+            .block_reasons
+            .iter()
+            .map(|reason| reason.to_lowercase())
+            .collect();
+        let blocks: usize = query_all(ctx.client, &query, |res: Res| {
+            Ok(vec![res
+                .query
+                .logevents
+                .iter()
+                .filter(|event| {
+                    let comment = event.comment.to_lowercase();
+                    reasons
+                        .iter()
+                        .any(|reason| comment.contains(reason.as_str()))
+                })
+                .count()])
+        })
+        .await?
+        .into_iter()
+        .sum();
+        Ok(blocks as f64 * 60.0 / window_mins as f64)
+    }
+}
@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use color_eyre::eyre::bail;
+use futures_util::{stream, StreamExt};
+use serde_json::{json, Value};
+
+use super::{Context, MetricSource};
+use crate::wiki::recent_revids;
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct LiftWingSettings {
+    /// Base URL of the Lift Wing model endpoints.
+    pub url: String,
+    /// Probability from which an edit counts as damaging.
+    pub threshold: f64,
+    /// Language code passed to the revert-risk model; derived from `wiki`
+    /// (e.g. `en` for `enwiki`) if unset.
+    pub lang: Option<String>,
+    /// How far back to look for edits to score, in minutes.
+    pub window_mins: i64,
+    /// Most edits to score per run; the rate is extrapolated from those.
+    pub max_edits: usize,
+    /// Requests to have in flight at once.
+    pub concurrency: usize,
+    /// Revisions per revert-risk request.
+    pub batch_size: usize,
+}
+
+impl Default for LiftWingSettings {
+    fn default() -> Self {
+        LiftWingSettings {
+            url: "https://api.wikimedia.org/service/lw/inference/v1/models".to_owned(),
+            threshold: 0.8,
+            lang: None,
+            window_mins: 10,
+            max_edits: 200,
+            concurrency: 4,
+            batch_size: 20,
+        }
+    }
+}
+
+/// Edits per minute that the Lift Wing `damaging` model is confident are
+/// damaging. Unlike the revert rate, this doesn't have to wait for patrollers
+/// to catch up with the vandalism.
+pub struct Damaging {
+    http: reqwest::Client,
+}
+
+fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().user_agent(user_agent!()).build()
+}
+
+fn model_url(settings: &LiftWingSettings, model: &str) -> String {
+    format!("{}/{model}:predict", settings.url.trim_end_matches('/'))
+}
+
+impl Damaging {
+    pub fn new() -> reqwest::Result<Self> {
+        Ok(Damaging {
+            http: http_client()?,
+        })
+    }
+
+    /// Probability that `revid` is damaging, or `None` if it couldn't be
+    /// scored (e.g. because it has been deleted).
+    async fn score(&self, url: &str, wiki: &str, revid: u64) -> Option<f64> {
+        let res = async {
+            self.http
+                .post(url)
+                .json(&json!({ "rev_id": revid }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await
+        }
+        .await;
+        match res {
+            Ok(res) => {
+                let score = &res[wiki]["scores"][revid.to_string()]["damaging"]["score"];
+                score["probability"]["true"].as_f64()
+            }
+            Err(e) => {
+                tracing::debug!("could not score revision {revid}: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MetricSource for Damaging {
+    fn name(&self) -> &str {
+        "damaging"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let settings = &ctx.settings.liftwing;
+        let wiki = &ctx.settings.wiki;
+        let revids = recent_revids(ctx.client, ctx.now, settings.window_mins).await?;
+        if revids.is_empty() {
+            return Ok(0.0);
+        }
+        let url = model_url(settings, &format!("{wiki}-damaging"));
+
+        let probabilities: Vec<f64> = stream::iter(revids.iter().take(settings.max_edits))
+            .map(|&revid| self.score(&url, wiki, revid))
+            .buffer_unordered(settings.concurrency.max(1))
+            .filter_map(|probability| async move { probability })
+            .collect()
+            .await;
+        if probabilities.is_empty() {
+            bail!("Lift Wing could not score any of the recent edits");
+        }
+
+        let damaging = probabilities
+            .iter()
+            .filter(|&&probability| probability >= settings.threshold)
+            .count();
+        let share = damaging as f64 / probabilities.len() as f64;
+        Ok(share * revids.len() as f64 / settings.window_mins.max(1) as f64)
+    }
+}
+
+/// Expected number of edits per minute that will be reverted, according to
+/// the language-agnostic revert-risk model: the sum of the scored edits'
+/// revert probabilities, extrapolated to all edits in the window. Edits are
+/// scored in batches, with at most `concurrency` requests in flight.
+pub struct RevertRisk {
+    http: reqwest::Client,
+}
+
+impl RevertRisk {
+    pub fn new() -> reqwest::Result<Self> {
+        Ok(RevertRisk {
+            http: http_client()?,
+        })
+    }
+
+    /// Revert probabilities of those of `revids` that could be scored.
+    async fn score_batch(&self, url: &str, lang: &str, revids: &[u64]) -> Vec<f64> {
+        let instances: Vec<_> = revids
+            .iter()
+            .map(|&revid| json!({ "rev_id": revid, "lang": lang }))
+            .collect();
+        let res = async {
+            self.http
+                .post(url)
+                .json(&json!({ "instances": instances }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await
+        }
+        .await;
+        match res {
+            Ok(res) => res["predictions"]
+                .as_array()
+                .map_or(&[][..], |predictions| &predictions[..])
+                .iter()
+                .filter_map(|prediction| prediction["output"]["probabilities"]["true"].as_f64())
+                .collect(),
+            Err(e) => {
+                tracing::debug!("could not score {} revisions: {e}", revids.len());
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MetricSource for RevertRisk {
+    fn name(&self) -> &str {
+        "revertrisk"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let settings = &ctx.settings.liftwing;
+        let lang = match &settings.lang {
+            Some(lang) => lang.clone(),
+            None => ctx.settings.wiki.trim_end_matches("wiki").to_owned(),
+        };
+        let revids = recent_revids(ctx.client, ctx.now, settings.window_mins).await?;
+        if revids.is_empty() {
+            return Ok(0.0);
+        }
+        let url = model_url(settings, "revertrisk-language-agnostic");
+
+        let sample = &revids[..revids.len().min(settings.max_edits)];
+        let batches: Vec<Vec<f64>> = stream::iter(sample.chunks(settings.batch_size.max(1)))
+            .map(|batch| self.score_batch(&url, &lang, batch))
+            .buffer_unordered(settings.concurrency.max(1))
+            .collect()
+            .await;
+        let probabilities: Vec<f64> = batches.into_iter().flatten().collect();
+        if probabilities.is_empty() {
+            bail!("Lift Wing could not score any of the recent edits");
+        }
+
+        let mean = probabilities.iter().sum::<f64>() / probabilities.len() as f64;
+        Ok(mean * revids.len() as f64 / settings.window_mins.max(1) as f64)
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use lazy_static::lazy_static;
+
+use super::{Context, MetricSource};
+use crate::rpm::{count_reverts, recent_edits};
+use crate::wiki::api_get;
+
+/// How long whether a user has the rollback right is remembered.
+const RIGHTS_TTL_HOURS: i64 = 24;
+/// Users the API looks up per request.
+const USERS_PER_REQUEST: usize = 50;
+
+lazy_static! {
+    /// Whether a user can roll back and when that was looked up, by wiki and
+    /// username.
+    static ref CAN_ROLLBACK: Mutex<HashMap<(String, String), (bool, i64)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Which of `users` have the rollback right, looking up those not seen in
+/// the last `RIGHTS_TTL_HOURS`. IPs and unknown users don't.
+async fn rollbackers(
+    ctx: &Context<'_>,
+    users: &HashSet<&str>,
+) -> color_eyre::Result<HashSet<String>> {
+    let wiki = &ctx.settings.api_url;
+    let now = ctx.now.timestamp();
+    let expired = now - Duration::hours(RIGHTS_TTL_HOURS).num_seconds();
+    let stale: Vec<&str> = {
+        let cache = CAN_ROLLBACK.lock().unwrap();
+        users
+            .iter()
+            .copied()
+            .filter(|user| {
+                cache
+                    .get(&(wiki.clone(), (*user).to_owned()))
+                    .is_none_or(|(_, fetched)| *fetched < expired)
+            })
+            .collect()
+    };
+    for chunk in stale.chunks(USERS_PER_REQUEST) {
+        let names = chunk.join("|");
+        let query = [
+            ("action", "query"),
+            ("list", "users"),
+            ("ususers", &names),
+            ("usprop", "rights"),
+        ];
+        let res = api_get(ctx.client, &query).await?;
+        let mut cache = CAN_ROLLBACK.lock().unwrap();
+        // users missing from the response, e.g. IPs, can't roll back
+        for user in chunk {
+            cache.insert((wiki.clone(), (*user).to_owned()), (false, now));
+        }
+        for user in res["query"]["users"].as_array().into_iter().flatten() {
+            let name = match user["name"].as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            let can_rollback = user["rights"]
+                .as_array()
+                .is_some_and(|rights| rights.iter().any(|right| right == "rollback"));
+            cache.insert((wiki.clone(), name.to_owned()), (can_rollback, now));
+        }
+    }
+    let cache = CAN_ROLLBACK.lock().unwrap();
+    Ok(users
+        .iter()
+        .filter(|user| {
+            cache
+                .get(&(wiki.clone(), (**user).to_owned()))
+                .is_some_and(|(can_rollback, _)| *can_rollback)
+        })
+        .map(|user| (*user).to_owned())
+        .collect())
+}
+
+/// Reverts per minute over the last interval by users who have the rollback
+/// right (`by_rollbackers`) or don't.
+async fn reverts_per_minute_by(ctx: &Context<'_>, by_rollbackers: bool) -> color_eyre::Result<f64> {
+    let recent = recent_edits(ctx).await?;
+    let users = recent
+        .reverts
+        .iter()
+        .map(|revert| revert.user.as_str())
+        .collect();
+    let rollbackers = rollbackers(ctx, &users).await?;
+    let reverts = count_reverts(
+        recent
+            .reverts
+            .iter()
+            .filter(|revert| rollbackers.contains(&revert.user) == by_rollbackers)
+            .map(|revert| (revert.title.as_str(), revert.weight)),
+        ctx.settings.max_reverts_per_page,
+    );
+    Ok(f64::from(reverts / recent.minutes))
+}
+
+/// Reverts of vandalism per minute by users with the rollback right, i.e.
+/// experienced patrollers.
+pub struct RollbackerRate;
+
+#[async_trait]
+impl MetricSource for RollbackerRate {
+    fn name(&self) -> &str {
+        "rollbacker_rpm"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        reverts_per_minute_by(ctx, true).await
+    }
+}
+
+/// Reverts of vandalism per minute by users without the rollback right.
+/// Next to `rollbacker_rpm`, it shows whether experienced patrollers are
+/// keeping up or others are stepping in.
+pub struct NonRollbackerRate;
+
+#[async_trait]
+impl MetricSource for NonRollbackerRate {
+    fn name(&self) -> &str {
+        "non_rollbacker_rpm"
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        reverts_per_minute_by(ctx, false).await
+    }
+}
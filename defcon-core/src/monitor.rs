@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+
+/// What we know about one wiki's runs so far.
+#[derive(Default, Clone)]
+pub struct WikiStatus {
+    /// The level last computed, or 0 before the first run.
+    pub level: u8,
+    pub rpm: f64,
+    /// The level's input on a 0–100 scale.
+    pub severity: f64,
+    /// Seconds the last run spent collecting metrics from the APIs.
+    pub api_latency_secs: f64,
+    pub runs: u64,
+    pub errors: u64,
+    pub last_success: Option<DateTime<Utc>>,
+    /// When the next run is due, in `--daemon` and `--live` mode.
+    pub next_run: Option<DateTime<Utc>>,
+    /// Why the API refuses our edits, if it does.
+    pub write_refusal: Option<WriteRefusal>,
+    /// The error code of the last API response, or `None` if it succeeded.
+    pub last_api_code: Option<String>,
+}
+
+/// Settings of the `[sentry]` table, reporting panics and failed runs to
+/// Sentry; the same for every profile, since there's only one per process.
+#[derive(serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct SentrySettings {
+    pub dsn: String,
+    /// e.g. `production`; Sentry's default if unset.
+    pub environment: Option<String>,
+}
+
+/// The API refusing our edits, e.g. because we're blocked.
+#[derive(Clone)]
+pub struct WriteRefusal {
+    pub reason: String,
+    /// Whether the operator has been told.
+    pub notified: bool,
+}
+
+/// Level changes kept for the Atom feed.
+const MAX_CHANGES: usize = 50;
+
+/// A published level change.
+#[derive(Clone)]
+pub struct LevelChange {
+    pub wiki: String,
+    /// The level before the change, or 0 if there was none.
+    pub previous_level: u8,
+    pub level: u8,
+    pub rpm: f64,
+    pub time: DateTime<Utc>,
+    /// Where to read more, e.g. the report page.
+    pub link: String,
+}
+
+lazy_static! {
+    static ref STATUS: Mutex<BTreeMap<String, WikiStatus>> = Mutex::new(BTreeMap::new());
+    static ref CHANGES: Mutex<VecDeque<LevelChange>> = Mutex::new(VecDeque::new());
+}
+
+fn update(wiki: &str, f: impl FnOnce(&mut WikiStatus)) {
+    let mut status = STATUS.lock().unwrap();
+    f(status.entry(wiki.to_owned()).or_default());
+}
+
+/// Record the level computed for `wiki`.
+pub fn record_level(wiki: &str, level: u8, rpm: f64, severity: f64) {
+    update(wiki, |status| {
+        status.level = level;
+        status.rpm = rpm;
+        status.severity = severity;
+    });
+}
+
+pub fn record_api_latency(wiki: &str, secs: f64) {
+    update(wiki, |status| status.api_latency_secs = secs);
+}
+
+/// Record the outcome of a run for `wiki`.
+pub fn record_run(wiki: &str, ok: bool) {
+    update(wiki, |status| {
+        status.runs += 1;
+        if ok {
+            status.last_success = Some(Utc::now());
+        } else {
+            status.errors += 1;
+        }
+    });
+}
+
+/// Record the error code of the last API response for `wiki`.
+pub fn record_api_code(wiki: &str, code: Option<&str>) {
+    update(wiki, |status| {
+        status.last_api_code = code.map(str::to_owned)
+    });
+}
+
+pub fn last_api_code(wiki: &str) -> Option<String> {
+    STATUS
+        .lock()
+        .unwrap()
+        .get(wiki)
+        .and_then(|status| status.last_api_code.clone())
+}
+
+pub fn record_next_run(wiki: &str, time: DateTime<Utc>) {
+    update(wiki, |status| status.next_run = Some(time));
+}
+
+/// Record that edits to `wiki` are refused, keeping track of whether the
+/// operator was already told about an earlier refusal.
+pub fn record_write_refused(wiki: &str, reason: String) {
+    update(wiki, |status| {
+        let notified = status
+            .write_refusal
+            .as_ref()
+            .is_some_and(|refusal| refusal.notified);
+        status.write_refusal = Some(WriteRefusal { reason, notified });
+    });
+}
+
+pub fn mark_write_refusal_notified(wiki: &str) {
+    update(wiki, |status| {
+        if let Some(refusal) = &mut status.write_refusal {
+            refusal.notified = true;
+        }
+    });
+}
+
+pub fn clear_write_refused(wiki: &str) {
+    update(wiki, |status| status.write_refusal = None);
+}
+
+pub fn write_refusal(wiki: &str) -> Option<WriteRefusal> {
+    STATUS
+        .lock()
+        .unwrap()
+        .get(wiki)
+        .and_then(|status| status.write_refusal.clone())
+}
+
+/// Record a published level change, forgetting the oldest one once there are
+/// more than `MAX_CHANGES`.
+pub fn record_change(change: LevelChange) {
+    let mut changes = CHANGES.lock().unwrap();
+    changes.push_back(change);
+    if changes.len() > MAX_CHANGES {
+        changes.pop_front();
+    }
+}
+
+/// A snapshot of every wiki's status.
+pub fn snapshot() -> BTreeMap<String, WikiStatus> {
+    STATUS.lock().unwrap().clone()
+}
+
+/// The status in the Prometheus text format.
+pub fn prometheus() -> String {
+    let status = snapshot();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(&WikiStatus) -> f64| {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (wiki, status) in &status {
+            let _ = writeln!(out, "{name}{{wiki=\"{wiki}\"}} {}", value(status));
+        }
+    };
+    metric(
+        "defcon_level",
+        "gauge",
+        "The vandalism level last computed.",
+        &|s: &WikiStatus| f64::from(s.level),
+    );
+    metric(
+        "defcon_rpm",
+        "gauge",
+        "Reverts of vandalism per minute.",
+        &|s: &WikiStatus| s.rpm,
+    );
+    metric(
+        "defcon_severity",
+        "gauge",
+        "What the level is computed from, on a scale of 0 to 100.",
+        &|s: &WikiStatus| s.severity,
+    );
+    metric(
+        "defcon_api_latency_seconds",
+        "gauge",
+        "Time the last run spent collecting metrics.",
+        &|s: &WikiStatus| s.api_latency_secs,
+    );
+    metric(
+        "defcon_runs_total",
+        "counter",
+        "Runs so far.",
+        &|s: &WikiStatus| s.runs as f64,
+    );
+    metric(
+        "defcon_errors_total",
+        "counter",
+        "Failed runs so far.",
+        &|s: &WikiStatus| s.errors as f64,
+    );
+    metric(
+        "defcon_last_success_timestamp_seconds",
+        "gauge",
+        "Unix time of the last successful run.",
+        &|s: &WikiStatus| s.last_success.map_or(0.0, |time| time.timestamp() as f64),
+    );
+    metric(
+        "defcon_edits_refused",
+        "gauge",
+        "Whether the API refuses our edits, e.g. because we're blocked.",
+        &|s: &WikiStatus| f64::from(u8::from(s.write_refusal.is_some())),
+    );
+    out
+}
+
+fn timestamp(time: Option<DateTime<Utc>>) -> Value {
+    time.map_or(Value::Null, |time| {
+        Value::from(time.to_rfc3339_opts(SecondsFormat::Secs, true))
+    })
+}
+
+/// Liveness: we're running, and when each wiki last had a successful run.
+pub fn health() -> Value {
+    let last_success: Map<String, Value> = snapshot()
+        .into_iter()
+        .map(|(wiki, status)| (wiki, timestamp(status.last_success)))
+        .collect();
+    json!({ "status": "ok", "last_success": last_success })
+}
+
+/// The current level, RPM, severity and schedule of every wiki.
+pub fn status() -> Value {
+    snapshot()
+        .into_iter()
+        .map(|(wiki, status)| {
+            let value = json!({
+                "level": status.level,
+                "rpm": status.rpm,
+                "severity": status.severity,
+                "last_success": timestamp(status.last_success),
+                "next_run": timestamp(status.next_run),
+                "edits_refused": status.write_refusal.map(|refusal| refusal.reason),
+            });
+            (wiki, value)
+        })
+        .collect::<Map<String, Value>>()
+        .into()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The level changes published since the process started, newest first, as
+/// an Atom feed.
+pub fn atom_feed() -> String {
+    let changes = CHANGES.lock().unwrap().clone();
+    let updated = changes
+        .back()
+        .map_or_else(Utc::now, |change| change.time)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    let _ = writeln!(out, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    let _ = writeln!(out, "<title>Vandalism level changes</title>");
+    let _ = writeln!(out, "<id>tag:defcon,2020:changes</id>");
+    let _ = writeln!(out, "<updated>{updated}</updated>");
+    let _ = writeln!(out, "<author><name>defcon</name></author>");
+    for change in changes.iter().rev() {
+        let time = change.time.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let previous = if change.previous_level != 0 {
+            format!(" (was {})", change.previous_level)
+        } else {
+            String::new()
+        };
+        let _ = writeln!(out, "<entry>");
+        let _ = writeln!(
+            out,
+            "<title>{} is now at level {}{previous}</title>",
+            escape_xml(&change.wiki),
+            change.level
+        );
+        let _ = writeln!(
+            out,
+            "<id>tag:defcon,2020:{}/{}</id>",
+            escape_xml(&change.wiki),
+            change.time.timestamp()
+        );
+        let _ = writeln!(out, "<updated>{time}</updated>");
+        let _ = writeln!(out, r#"<link href="{}"/>"#, escape_xml(&change.link));
+        let _ = writeln!(
+            out,
+            "<summary>Level {} at {:.2} reverts per minute.</summary>",
+            change.level, change.rpm
+        );
+        let _ = writeln!(out, "</entry>");
+    }
+    out.push_str("</feed>\n");
+    out
+}
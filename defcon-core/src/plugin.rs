@@ -0,0 +1,152 @@
+//! WASM plugins that supply a metric or classify edits, so that new signals
+//! can be tried without changing defcon. A plugin is a WASI (preview 1)
+//! module built as a reactor, e.g. for `wasm32-wasip1`, exporting its
+//! `memory` and
+//!
+//! - `defcon_alloc(len: i32) -> i32`, returning where `len` bytes of input
+//!   can be written, and optionally `defcon_free(ptr: i32, len: i32)`, called
+//!   once defcon is done with them;
+//! - for metric plugins, `defcon_metric(ptr: i32, len: i32) -> f64`, given
+//!   the edits of the window as JSON, e.g. `{"edits": 1200, "minutes": 60.0,
+//!   "reverts": [{"namespace": 0, "title": "Foo", "user": "Bar", "weight":
+//!   1.0, "reverted": "1.2.3.4"}]}`, where `reverted` is null if unknown;
+//! - for classifier plugins, `defcon_classify(ptr: i32, len: i32) -> i32`,
+//!   given an edit as JSON, e.g. `{"comment": "rv vandalism", "tags":
+//!   ["mw-undo"]}`, and returning 1 if it reverts vandalism and 0 otherwise.
+//!
+//! Plugins can't reach the filesystem or the network; what they print to
+//! stderr shows up in ours. Every call gets a fixed amount of fuel, so that a
+//! plugin stuck in a loop fails instead of hanging the run.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use wasmtime::{Config, Engine, Instance, Linker, Module, Store, WasmResults};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+
+use crate::metrics::{Context, MetricSource};
+use crate::rpm::recent_edits;
+
+/// Wasm instructions, roughly, one call may run.
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+
+/// The plugins that can be listed in the settings.
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginConfig {
+    /// Collect the metric `name` from the module at `path` every run.
+    Metric { name: String, path: PathBuf },
+    /// Classify edits with the module at `path`, with `detection = "plugin"`.
+    Classifier { path: PathBuf },
+}
+
+/// A loaded WASM module, called one at a time.
+pub struct Plugin {
+    path: PathBuf,
+    instance: Mutex<(Store<WasiP1Ctx>, Instance)>,
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let wasm = |e: wasmtime::Error| eyre!("could not load plugin {}: {e:#}", path.display());
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(wasm)?;
+        let module = Module::from_file(&engine, path).map_err(wasm)?;
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |ctx| ctx).map_err(wasm)?;
+        let wasi = WasiCtxBuilder::new().inherit_stderr().build_p1();
+        let mut store = Store::new(&engine, wasi);
+        store.set_fuel(FUEL_PER_CALL).map_err(wasm)?;
+        let instance = linker.instantiate(&mut store, &module).map_err(wasm)?;
+        // reactors set themselves up in `_initialize`
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            init.call(&mut store, ()).map_err(wasm)?;
+        }
+        Ok(Plugin {
+            path: path.to_owned(),
+            instance: Mutex::new((store, instance)),
+        })
+    }
+
+    /// Write `input` to the plugin's memory and call `export` on it.
+    fn call<R: WasmResults>(&self, export: &str, input: &[u8]) -> color_eyre::Result<R> {
+        let path = self.path.display();
+        let wasm = |e: wasmtime::Error| eyre!("plugin {path} failed in `{export}`: {e:#}");
+        let mut instance = self.instance.lock().unwrap();
+        let (store, instance) = &mut *instance;
+        store.set_fuel(FUEL_PER_CALL).map_err(wasm)?;
+        let memory = match instance.get_memory(&mut *store, "memory") {
+            Some(memory) => memory,
+            None => bail!("plugin {path} doesn't export its memory"),
+        };
+        let len = i32::try_from(input.len()).wrap_err("plugin input too long")?;
+        let ptr = instance
+            .get_typed_func::<i32, i32>(&mut *store, "defcon_alloc")
+            .map_err(wasm)?
+            .call(&mut *store, len)
+            .map_err(wasm)?;
+        memory
+            .write(&mut *store, ptr as usize, input)
+            .wrap_err_with(|| format!("plugin {path} allocated memory out of bounds"))?;
+        let result = instance
+            .get_typed_func::<(i32, i32), R>(&mut *store, export)
+            .map_err(wasm)?
+            .call(&mut *store, (ptr, len))
+            .map_err(wasm)?;
+        if let Ok(free) = instance.get_typed_func::<(i32, i32), ()>(&mut *store, "defcon_free") {
+            free.call(&mut *store, (ptr, len)).map_err(wasm)?;
+        }
+        Ok(result)
+    }
+
+    /// Whether the edit with `edit_summary` and `tags` reverts vandalism.
+    pub fn classify(&self, edit_summary: &str, tags: &[String]) -> color_eyre::Result<bool> {
+        let input = serde_json::json!({ "comment": edit_summary, "tags": tags });
+        let verdict: i32 = self.call("defcon_classify", input.to_string().as_bytes())?;
+        Ok(verdict != 0)
+    }
+}
+
+/// A metric supplied by a plugin.
+pub struct PluginMetric {
+    name: String,
+    plugin: Plugin,
+}
+
+impl PluginMetric {
+    pub fn new(name: String, path: &Path) -> color_eyre::Result<Self> {
+        Ok(PluginMetric {
+            name,
+            plugin: Plugin::load(path)?,
+        })
+    }
+}
+
+#[async_trait]
+impl MetricSource for PluginMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self, ctx: &Context<'_>) -> color_eyre::Result<f64> {
+        let recent = recent_edits(ctx).await?;
+        let input = serde_json::to_vec(&recent)?;
+        self.plugin.call("defcon_metric", &input)
+    }
+}
+
+/// The classifier plugin in `plugins`, if there is one.
+pub fn classifier(plugins: &[PluginConfig]) -> color_eyre::Result<Option<Arc<Plugin>>> {
+    plugins
+        .iter()
+        .find_map(|plugin| match plugin {
+            PluginConfig::Classifier { path } => Some(path),
+            PluginConfig::Metric { .. } => None,
+        })
+        .map(|path| Plugin::load(path).map(Arc::new))
+        .transpose()
+}
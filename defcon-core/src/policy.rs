@@ -0,0 +1,89 @@
+//! A level formula written in Lua in the settings (`level_expression`), so
+//! that operators can combine metrics without a new release, e.g.
+//! `if rpm > 8 or abusefilter > 3 then return 1 elseif rpm > 4 then return 3
+//! else return 5 end`.
+
+use color_eyre::eyre::{bail, eyre};
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib};
+
+use crate::level::Levels;
+use crate::metrics::Metrics;
+
+/// Instructions an evaluation may run: far more than any formula needs, and
+/// few enough that an endless loop fails within a second.
+const MAX_INSTRUCTIONS: u32 = 10_000_000;
+
+/// Bytes a Lua state may allocate.
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// A Lua state without `io`, `os` and the other libraries that reach outside
+/// of it, and with its instructions and memory limited.
+fn sandbox() -> color_eyre::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::MATH | StdLib::STRING | StdLib::TABLE,
+        LuaOptions::default(),
+    )
+    .map_err(|e| eyre!("could not start Lua: {e}"))?;
+    lua.set_memory_limit(MAX_MEMORY)
+        .map_err(|e| eyre!("could not limit the memory of Lua: {e}"))?;
+    // every evaluation gets a state of its own, so the first time the hook
+    // runs is the limit
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(MAX_INSTRUCTIONS),
+        |_, _| Err(mlua::Error::runtime("too many instructions")),
+    );
+    Ok(lua)
+}
+
+/// `expression` as a function: a bare expression is returned, the way the
+/// Lua REPL does, and anything else runs as a chunk.
+fn load(lua: &Lua, expression: &str) -> color_eyre::Result<mlua::Function> {
+    lua.load(format!("return {expression}"))
+        .set_name("level_expression")
+        .into_function()
+        .or_else(|_| {
+            lua.load(expression)
+                .set_name("level_expression")
+                .into_function()
+        })
+        .map_err(|e| eyre!("invalid `level_expression`: {e}"))
+}
+
+/// Check that `expression` compiles.
+pub fn validate(expression: &str) -> color_eyre::Result<()> {
+    load(&sandbox()?, expression).map(drop)
+}
+
+/// The level `expression` computes. Every metric is a global of the same
+/// name (and a field of the `metrics` table), and `current` is the level on
+/// the report page, 0 if there is none.
+pub fn evaluate(
+    expression: &str,
+    levels: &Levels,
+    metrics: &Metrics,
+    current: u8,
+) -> color_eyre::Result<u8> {
+    let lua = sandbox()?;
+    let set_globals = || -> mlua::Result<()> {
+        let globals = lua.globals();
+        let table = lua.create_table()?;
+        for (name, value) in metrics {
+            globals.set(name.as_str(), *value)?;
+            table.set(name.as_str(), *value)?;
+        }
+        globals.set("metrics", table)?;
+        globals.set("current", current)
+    };
+    set_globals().map_err(|e| eyre!("could not set up `level_expression`: {e}"))?;
+    let level: Option<f64> = load(&lua, expression)?
+        .call(())
+        .map_err(|e| eyre!("`level_expression` failed: {e}"))?;
+    let level = match level {
+        Some(level) => level,
+        None => bail!("`level_expression` didn't return a level"),
+    };
+    if level.fract() != 0.0 || !(0.0..=255.0).contains(&level) || !levels.contains(level as u8) {
+        bail!("`level_expression` returned {level}, which isn't one of `levels`");
+    }
+    Ok(level as u8)
+}
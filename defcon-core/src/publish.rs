@@ -0,0 +1,380 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use color_eyre::eyre::bail;
+use tracing::{info, warn, Instrument};
+
+use crate::concentration::Concentration;
+use crate::email;
+use crate::error::DefconError;
+use crate::level::Trend;
+use crate::metrics::Metrics;
+use crate::report::{ReportFormat, ReportPreset};
+use crate::run::{dwelling, stand_down_reason};
+use crate::scoring::Signal;
+use crate::settings::Settings;
+use crate::wiki::{
+    current_report, edit_page, edit_summary, is_edit_conflict, latest_revid, purge, report_text,
+    transclusions, uneditable_reason,
+};
+
+pub mod discord;
+pub mod irc;
+pub mod json;
+pub mod logpage;
+pub mod lua;
+pub mod mastodon;
+pub mod matrix;
+pub mod push;
+
+/// A level change to be published.
+#[derive(Clone, Copy)]
+pub struct LevelUpdate<'a> {
+    /// The level on the report page before this change, or 0 if there was
+    /// none on the scale.
+    pub previous_level: u8,
+    pub level: u8,
+    pub rpm: f32,
+    /// The value the level is computed from on a 0–100 scale, see
+    /// `level::severity`.
+    pub severity: f32,
+    /// How `rpm` compares to the RPM last published, or without a
+    /// `state_file` to the one of the last run in the `database`.
+    pub trend: Trend,
+    /// The RPM expected an hour from now, if forecasting.
+    pub forecast: Option<f32>,
+    /// The account or IP range most reverts were against, if they were
+    /// concentrated.
+    pub concentration: Option<&'a Concentration>,
+    pub metrics: &'a Metrics,
+    pub time: DateTime<Utc>,
+    /// The revision of the report page the previous level was read from.
+    pub base_revid: u64,
+}
+
+/// Somewhere level changes are written to.
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// A description of what `publish` would do, for dry runs.
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String;
+
+    async fn publish(
+        &self,
+        client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()>;
+}
+
+/// The publishers that can be listed in the settings.
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PublisherConfig {
+    /// Edit `page`, by default `report_page`, in the format of `preset` or
+    /// `template`, by default the profile's.
+    Wiki {
+        page: Option<String>,
+        preset: Option<ReportPreset>,
+        template: Option<PathBuf>,
+    },
+    /// Append every level change to `page`, archiving it monthly.
+    Log { page: String },
+    /// Write `{level, rpm, severity, timestamp, metrics}` to a JSON page, by
+    /// default `report_page.json`.
+    Json { page: Option<String> },
+    /// Write a Lua data module with the level, RPM, severity and metrics to `page`.
+    Lua { page: String },
+    /// Post level changes to a Discord webhook.
+    Discord { url: String },
+    /// Say level changes in an IRC channel.
+    Irc(irc::IrcSettings),
+    /// Send level changes to a Matrix room.
+    Matrix(matrix::MatrixSettings),
+    /// Push severe level changes to an ntfy topic.
+    Ntfy(push::NtfySettings),
+    /// Push severe level changes to Pushover.
+    Pushover(push::PushoverSettings),
+    /// Toot severe level changes from a Mastodon account.
+    Mastodon(mastodon::MastodonSettings),
+}
+
+fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().user_agent(user_agent!()).build()
+}
+
+/// A page showing the level, by default `report_page`. Only `report_page` is
+/// purged after an edit, and the level decided again when someone else edited
+/// it in the meantime; any other page is edited again on its newest revision.
+pub struct WikiPage {
+    page: Option<String>,
+    format: Option<ReportFormat>,
+}
+
+impl WikiPage {
+    pub fn new(page: Option<String>, format: Option<ReportFormat>) -> Self {
+        WikiPage { page, format }
+    }
+
+    /// `page`, unless it's `report_page`.
+    fn other_page<'a>(&'a self, settings: &Settings) -> Option<&'a str> {
+        self.page
+            .as_deref()
+            .filter(|page| *page != settings.report_page)
+    }
+
+    fn text(&self, settings: &Settings, update: &LevelUpdate<'_>) -> color_eyre::Result<String> {
+        let format = self.format.as_ref().unwrap_or(&settings.report_format);
+        report_text(settings, format, update)
+    }
+}
+
+#[async_trait]
+impl Publisher for WikiPage {
+    fn name(&self) -> &str {
+        "wiki"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        let text = self
+            .text(settings, update)
+            .unwrap_or_else(|e| format!("{e:?}"));
+        format!(
+            "edit {} with summary: {}\n{}",
+            self.other_page(settings).unwrap_or(&settings.report_page),
+            edit_summary(settings, update),
+            text
+        )
+    }
+
+    async fn publish(
+        &self,
+        client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        if let Some(page) = self.other_page(settings) {
+            let text = self.text(settings, update)?;
+            let summary = edit_summary(settings, update);
+            let mut conflicts = 0;
+            loop {
+                let base_revid = latest_revid(client, page).await?;
+                match edit_page(client, page, &text, &summary, base_revid).await {
+                    Ok(()) => break,
+                    Err(e)
+                        if is_edit_conflict(&e) && conflicts < settings.edit_conflict_retries =>
+                    {
+                        conflicts += 1;
+                        info!("edit conflict on {page}, retrying");
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            info!("edited {page}");
+            return Ok(());
+        }
+        let mut current = *update;
+        let mut conflicts = 0;
+        loop {
+            let text = self.text(settings, &current)?;
+            let summary = edit_summary(settings, &current);
+            let result = edit_page(
+                client,
+                &settings.report_page,
+                &text,
+                &summary,
+                Some(current.base_revid),
+            )
+            .await;
+            match result {
+                Ok(()) => break,
+                Err(e) if is_edit_conflict(&e) && conflicts < settings.edit_conflict_retries => {
+                    conflicts += 1;
+                    // decide again from the edit that got in the way, as
+                    // `update_level` would have
+                    let rev = current_report(client, settings, update.time).await?;
+                    let previous_level = if settings.levels.contains(rev.level) {
+                        rev.level
+                    } else {
+                        0
+                    };
+                    let level = Signal::new(settings, update.metrics)?.level(
+                        settings,
+                        update.metrics,
+                        previous_level,
+                    )?;
+                    if level == previous_level {
+                        return Err(DefconError::Declined(format!(
+                            "{} set level {} in the meantime",
+                            rev.user, rev.level
+                        ))
+                        .into());
+                    }
+                    // it changed the level just now, if it's another one
+                    let changed = if previous_level != current.previous_level {
+                        rev.timestamp
+                    } else {
+                        None
+                    };
+                    if level != update.level
+                        || dwelling(settings, changed, update.time, previous_level, level)
+                    {
+                        // the publishers after this one are skipped, see
+                        // `publish_all`
+                        return Err(DefconError::Declined(format!(
+                            "not editing after an edit conflict: {} set level {}, leaving it \
+                             to the next run",
+                            rev.user, rev.level
+                        ))
+                        .into());
+                    }
+                    if let Some(reason) =
+                        stand_down_reason(client, settings, &rev, update.time).await?
+                    {
+                        return Err(DefconError::Declined(format!(
+                            "not editing after an edit conflict: {reason}"
+                        ))
+                        .into());
+                    }
+                    info!("edit conflict, retrying on revision {}", rev.revid);
+                    current = LevelUpdate {
+                        previous_level,
+                        base_revid: rev.revid,
+                        ..current
+                    };
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        info!("edited");
+
+        let mut titles = settings.purge_pages.clone();
+        if settings.purge_transclusions {
+            match transclusions(client, &settings.report_page).await {
+                Ok(transcluding) => titles.extend(transcluding),
+                Err(e) => warn!("could not list the pages transcluding the report page: {e:?}"),
+            }
+        }
+        titles.sort();
+        titles.dedup();
+        if !titles.is_empty() {
+            // they show the old level for a while otherwise, but that's no
+            // reason to fail the update
+            match purge(client, &titles).await {
+                Ok(()) => info!("purged {} pages", titles.len()),
+                Err(e) => warn!("could not purge {}: {e:?}", titles.join(", ")),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build the publishers listed in `settings.publishers`.
+pub fn publishers(settings: &Settings) -> color_eyre::Result<Vec<Box<dyn Publisher>>> {
+    settings
+        .publishers
+        .iter()
+        .map(|config| -> color_eyre::Result<Box<dyn Publisher>> {
+            Ok(match config {
+                PublisherConfig::Wiki {
+                    page,
+                    preset,
+                    template,
+                } => {
+                    let format = match (preset, template) {
+                        (Some(_), Some(_)) => {
+                            bail!("a `wiki` publisher takes `preset` or `template`, not both")
+                        }
+                        (Some(preset), None) => Some(ReportFormat::from(*preset)),
+                        (None, Some(template)) => Some(ReportFormat::load(template)?),
+                        (None, None) => None,
+                    };
+                    Box::new(WikiPage::new(page.clone(), format))
+                }
+                PublisherConfig::Log { page } => Box::new(logpage::LogPage::new(page.clone())),
+                PublisherConfig::Json { page } => Box::new(json::JsonPage::new(page.clone())),
+                PublisherConfig::Lua { page } => Box::new(lua::LuaModule::new(page.clone())),
+                PublisherConfig::Discord { url } => Box::new(discord::Discord::new(url.clone())?),
+                PublisherConfig::Irc(irc) => Box::new(irc::Irc::new(irc.clone())),
+                PublisherConfig::Matrix(matrix) => Box::new(matrix::Matrix::new(matrix.clone())?),
+                PublisherConfig::Ntfy(ntfy) => Box::new(push::Ntfy::new(ntfy.clone())?),
+                PublisherConfig::Pushover(pushover) => {
+                    Box::new(push::Pushover::new(pushover.clone())?)
+                }
+                PublisherConfig::Mastodon(mastodon) => {
+                    Box::new(mastodon::Mastodon::new(mastodon.clone())?)
+                }
+            })
+        })
+        .collect()
+}
+
+/// Whether `e` is a publisher declining the level, see `DefconError::Declined`.
+pub fn is_declined(e: &color_eyre::Report) -> bool {
+    matches!(DefconError::find(e), Some(DefconError::Declined(_)))
+}
+
+/// Hand `update` to every publisher. One failing publisher doesn't stop the
+/// others; the first error is returned once all of them have run. A page that
+/// can't be edited as it stands, being protected or the wiki read-only, isn't
+/// an error, but gets logged (and emailed about with `notify_uneditable`).
+/// A publisher declining the level stops the ones after it, and that is what
+/// gets returned, so list the `report_page` one first.
+pub async fn publish_all(
+    client: &mw::Client,
+    settings: &Settings,
+    publishers: &[Box<dyn Publisher>],
+    update: &LevelUpdate<'_>,
+) -> color_eyre::Result<()> {
+    let mut result = Ok(());
+    for publisher in publishers {
+        if settings.dry_run {
+            println!(
+                "would publish to {}: {}",
+                publisher.name(),
+                publisher.preview(settings, update)
+            );
+            continue;
+        }
+        let span = tracing::info_span!("publish", publisher = publisher.name());
+        let published = publisher
+            .publish(client, settings, update)
+            .instrument(span)
+            .await;
+        if let Err(e) = published {
+            if is_declined(&e) {
+                return Err(e);
+            }
+            if let Some(reason) = uneditable_reason(&e) {
+                tracing::error!("can't publish to {}: {reason}", publisher.name());
+                let notify = settings
+                    .email
+                    .as_ref()
+                    .is_some_and(|email| email.notify_uneditable);
+                // read-only is temporary, no need to tell anyone
+                let read_only = matches!(
+                    DefconError::find(&e),
+                    Some(DefconError::Api { code, .. }) if code == "readonly"
+                );
+                if notify && !read_only {
+                    let subject = format!("defcon can't publish to {}", publisher.name());
+                    let body = format!(
+                        "Level {} on {} couldn't be published to {}: {reason}.\n",
+                        update.level,
+                        settings.wiki,
+                        publisher.name()
+                    );
+                    email::notify(settings, &subject, body).await;
+                }
+                continue;
+            }
+            tracing::error!("publishing to {} failed: {e:?}", publisher.name());
+            if result.is_ok() {
+                result = Err(e.wrap_err(format!("publishing to {} failed", publisher.name())));
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use serde_json::json;
+use tracing::info;
+
+use super::{http_client, LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::index_url;
+
+/// A message on a Discord webhook for every level change.
+pub struct Discord {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Discord {
+    pub fn new(url: String) -> reqwest::Result<Self> {
+        Ok(Discord {
+            http: http_client()?,
+            url,
+        })
+    }
+
+    fn content(settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        let previous = if update.previous_level != 0 {
+            format!(" (was {})", update.previous_level)
+        } else {
+            String::new()
+        };
+        format!(
+            "Vandalism level on {} is now **{}**{previous}, at {:.2} RPM. [Recent changes](<{}>)",
+            settings.wiki,
+            update.level,
+            update.rpm,
+            index_url(settings, "Special:RecentChanges")
+        )
+    }
+}
+
+#[async_trait]
+impl Publisher for Discord {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        format!("post to Discord: {}", Self::content(settings, update))
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        self.http
+            .post(&self.url)
+            .json(&json!({
+                "content": Self::content(settings, update),
+                // don't ping anyone, whatever ends up in the message
+                "allowed_mentions": { "parse": [] },
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        info!("posted to Discord");
+        Ok(())
+    }
+}
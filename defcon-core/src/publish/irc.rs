@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+
+use super::{LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::index_url;
+
+/// Seconds to wait before reconnecting after losing the connection.
+const RECONNECT_SECS: u64 = 30;
+
+#[derive(serde::Deserialize, Clone)]
+pub struct IrcSettings {
+    /// Host name of the IRC server.
+    pub server: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    pub nick: String,
+    /// Server password, which most networks pass on to NickServ.
+    pub password: Option<String>,
+    pub channel: String,
+}
+
+fn default_port() -> u16 {
+    6697
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+async fn connect(settings: &IrcSettings) -> color_eyre::Result<Box<dyn Stream>> {
+    let tcp = TcpStream::connect((settings.server.as_str(), settings.port))
+        .await
+        .wrap_err_with(|| format!("could not connect to {}", settings.server))?;
+    if !settings.tls {
+        return Ok(Box::new(tcp));
+    }
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(settings.server.clone())?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(name, tcp)
+        .await?;
+    Ok(Box::new(tls))
+}
+
+/// One connection: register, join the channel and relay messages from `rx`
+/// until the connection drops or the publisher goes away.
+async fn session(
+    settings: &IrcSettings,
+    rx: &mut mpsc::Receiver<String>,
+) -> color_eyre::Result<()> {
+    let stream = connect(settings).await?;
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+
+    let mut nick = settings.nick.clone();
+    if let Some(password) = &settings.password {
+        write
+            .write_all(format!("PASS {password}\r\n").as_bytes())
+            .await?;
+    }
+    write
+        .write_all(format!("NICK {nick}\r\nUSER {nick} 0 * :{}\r\n", user_agent!()).as_bytes())
+        .await?;
+
+    let mut joined = false;
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = line?.ok_or_else(|| eyre!("connection closed"))?;
+                let mut parts = line.splitn(3, ' ');
+                let first = parts.next().unwrap_or("");
+                if first == "PING" {
+                    let token = line.trim_start_matches("PING");
+                    write.write_all(format!("PONG{token}\r\n").as_bytes()).await?;
+                    continue;
+                }
+                match parts.next().unwrap_or("") {
+                    // welcome
+                    "001" => {
+                        write.write_all(format!("JOIN {}\r\n", settings.channel).as_bytes()).await?;
+                    }
+                    // nickname in use
+                    "433" => {
+                        nick.push('_');
+                        write.write_all(format!("NICK {nick}\r\n").as_bytes()).await?;
+                    }
+                    "JOIN" if first.trim_start_matches(':').starts_with(&format!("{nick}!")) => {
+                        info!("joined {}", settings.channel);
+                        joined = true;
+                    }
+                    _ => {}
+                }
+            }
+            message = rx.recv(), if joined => {
+                match message {
+                    Some(message) => {
+                        let line = format!("PRIVMSG {} :{message}\r\n", settings.channel);
+                        write.write_all(line.as_bytes()).await?;
+                    }
+                    None => {
+                        write.write_all(b"QUIT\r\n").await?;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Messages in an IRC channel for every level change, from a connection kept
+/// open in the background.
+pub struct Irc {
+    channel: String,
+    tx: mpsc::Sender<String>,
+}
+
+impl Irc {
+    /// Start the connection task. Must be called within the runtime.
+    pub fn new(settings: IrcSettings) -> Self {
+        let (tx, mut rx) = mpsc::channel(16);
+        let channel = settings.channel.clone();
+        tokio::spawn(async move {
+            loop {
+                match session(&settings, &mut rx).await {
+                    Ok(()) => return,
+                    Err(e) => {
+                        warn!("IRC connection to {} failed: {e}", settings.server);
+                        tokio::time::sleep(std::time::Duration::from_secs(RECONNECT_SECS)).await;
+                    }
+                }
+            }
+        });
+        Irc { channel, tx }
+    }
+
+    fn message(settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        let previous = if update.previous_level != 0 {
+            format!(" (was {})", update.previous_level)
+        } else {
+            String::new()
+        };
+        format!(
+            "Vandalism level on {} is now {}{previous}, at {:.2} RPM: {}",
+            settings.wiki,
+            update.level,
+            update.rpm,
+            index_url(settings, "Special:RecentChanges")
+        )
+    }
+}
+
+#[async_trait]
+impl Publisher for Irc {
+    fn name(&self) -> &str {
+        "irc"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        format!(
+            "say in {}: {}",
+            self.channel,
+            Self::message(settings, update)
+        )
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        // queued until the connection is up
+        self.tx
+            .send(Self::message(settings, update))
+            .await
+            .map_err(|_| eyre!("the IRC connection task has stopped"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::{LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::{edit_json, edit_summary};
+
+/// A JSON page with the current level, for gadgets and other bots.
+pub struct JsonPage {
+    page: Option<String>,
+}
+
+impl JsonPage {
+    pub fn new(page: Option<String>) -> Self {
+        JsonPage { page }
+    }
+
+    /// The configured page, or `report_page.json`.
+    fn page(&self, settings: &Settings) -> String {
+        self.page
+            .clone()
+            .unwrap_or_else(|| format!("{}.json", settings.report_page))
+    }
+
+    fn content(update: &LevelUpdate<'_>) -> Value {
+        json!({
+            "level": update.level,
+            "rpm": update.rpm,
+            "severity": update.severity,
+            "timestamp": update.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "metrics": update.metrics,
+        })
+    }
+}
+
+#[async_trait]
+impl Publisher for JsonPage {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        format!("edit {}:\n{}", self.page(settings), Self::content(update))
+    }
+
+    async fn publish(
+        &self,
+        client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        let page = self.page(settings);
+        let summary = edit_summary(settings, update);
+        edit_json(client, &page, &Self::content(update), &summary).await?;
+        info!("edited {page}");
+        Ok(())
+    }
+}
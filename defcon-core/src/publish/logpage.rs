@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::info;
+
+use super::{LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::{append_text, edit_page, latest_revision};
+
+lazy_static! {
+    static ref MONTH_RE: Regex = Regex::new(r"<!-- defcon-log: (\d{4}-\d{2}) -->").unwrap();
+}
+
+/// An append-only list of level changes on `page`. At the start of each
+/// month, the previous month's entries are moved to `page/YYYY-MM`.
+pub struct LogPage {
+    page: String,
+}
+
+impl LogPage {
+    pub fn new(page: String) -> Self {
+        LogPage { page }
+    }
+
+    fn entry(update: &LevelUpdate<'_>) -> String {
+        let from = if update.previous_level != 0 {
+            format!("level {} → ", update.previous_level)
+        } else {
+            String::new()
+        };
+        format!(
+            "* {}: {from}level {} at {:.2} RPM",
+            update.time.format("%Y-%m-%d %H:%M UTC"),
+            update.level,
+            update.rpm
+        )
+    }
+
+    fn header(&self, settings: &Settings, month: &str) -> String {
+        format!(
+            "<!-- defcon-log: {month} -->\n{}",
+            settings.messages.get("defcon-log-header", &[&self.page])
+        )
+    }
+}
+
+#[async_trait]
+impl Publisher for LogPage {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn preview(&self, _settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        format!("append to {}: {}", self.page, Self::entry(update))
+    }
+
+    async fn publish(
+        &self,
+        client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        let month = update.time.format("%Y-%m").to_string();
+        let entry = Self::entry(update);
+        let summary = settings
+            .messages
+            .get("defcon-summary-log", &[&update.level.to_string()]);
+        let rev = latest_revision(client, &self.page, "ids|content").await?;
+        let text = rev["slots"]["main"]["content"].as_str().unwrap_or("");
+        let logged_month = MONTH_RE
+            .captures(text)
+            .map(|captures| captures[1].to_owned());
+
+        match logged_month {
+            Some(logged) if logged == month => {
+                append_text(client, &self.page, &format!("\n{entry}"), &summary).await?;
+            }
+            Some(logged) => {
+                let archive = format!("{}/{logged}", self.page);
+                edit_page(
+                    client,
+                    &archive,
+                    text,
+                    &settings
+                        .messages
+                        .get("defcon-summary-log-archive", &[&logged]),
+                    None,
+                )
+                .await?;
+                info!("archived {} to {archive}", self.page);
+                let text = format!("{}\n{entry}", self.header(settings, &month));
+                edit_page(client, &self.page, &text, &summary, None).await?;
+            }
+            None if text.trim().is_empty() => {
+                let text = format!("{}\n{entry}", self.header(settings, &month));
+                edit_page(client, &self.page, &text, &summary, None).await?;
+            }
+            None => {
+                // a page we didn't start; keep what's there
+                let text = format!(
+                    "{}\n{}\n{entry}",
+                    self.header(settings, &month),
+                    text.trim_end()
+                );
+                edit_page(client, &self.page, &text, &summary, None).await?;
+            }
+        }
+        info!("logged the level change to {}", self.page);
+        Ok(())
+    }
+}
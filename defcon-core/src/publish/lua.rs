@@ -0,0 +1,135 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use tracing::info;
+
+use super::{LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::{edit_page, edit_summary};
+
+/// A Scribunto data module returning a table with the current level, for
+/// templates to `mw.loadData`.
+pub struct LuaModule {
+    page: String,
+}
+
+/// A Lua string literal for `s`.
+fn lua_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                // decimal escapes are the same in every Lua version
+                let _ = write!(out, "\\{:03}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A Lua number literal for `x`. Lua has no literals for NaN or the
+/// infinities.
+fn lua_number(x: f64) -> color_eyre::Result<String> {
+    if !x.is_finite() {
+        bail!("{x} can't be written as a Lua number");
+    }
+    Ok(x.to_string())
+}
+
+impl LuaModule {
+    pub fn new(page: String) -> Self {
+        LuaModule { page }
+    }
+
+    fn content(update: &LevelUpdate<'_>) -> color_eyre::Result<String> {
+        let mut text =
+            String::from("-- Written by DeadbeefBot; manual changes will be overwritten.\n");
+        text.push_str("return {\n");
+        writeln!(text, "\tlevel = {},", update.level)?;
+        writeln!(text, "\trpm = {},", lua_number(f64::from(update.rpm))?)?;
+        writeln!(
+            text,
+            "\tseverity = {},",
+            lua_number(f64::from(update.severity))?
+        )?;
+        writeln!(
+            text,
+            "\ttimestamp = {},",
+            lua_string(&update.time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        )?;
+        text.push_str("\tmetrics = {\n");
+        for (name, value) in update.metrics {
+            writeln!(
+                text,
+                "\t\t[{}] = {},",
+                lua_string(name),
+                lua_number(*value)?
+            )?;
+        }
+        text.push_str("\t},\n}\n");
+        Ok(text)
+    }
+
+    /// Run `text` and check that it returns what we meant to write, so that a
+    /// broken module never makes it onto the wiki.
+    fn check(text: &str, update: &LevelUpdate<'_>) -> color_eyre::Result<()> {
+        let lua = mlua::Lua::new();
+        let table: mlua::Table = lua
+            .load(text)
+            .eval()
+            .map_err(|e| eyre!("generated module doesn't run: {e}"))?;
+        let level: u8 = table
+            .get("level")
+            .map_err(|e| eyre!("generated module has no level: {e}"))?;
+        if level != update.level {
+            bail!(
+                "generated module has level {level} instead of {}",
+                update.level
+            );
+        }
+        let metrics: mlua::Table = table
+            .get("metrics")
+            .map_err(|e| eyre!("generated module has no metrics: {e}"))?;
+        if metrics.pairs::<String, f64>().count() != update.metrics.len() {
+            bail!("generated module doesn't have all the metrics");
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Publisher for LuaModule {
+    fn name(&self) -> &str {
+        "lua"
+    }
+
+    fn preview(&self, _settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        match Self::content(update) {
+            Ok(text) => format!("edit {}:\n{text}", self.page),
+            Err(e) => format!("could not generate {}: {e}", self.page),
+        }
+    }
+
+    async fn publish(
+        &self,
+        client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        let text = Self::content(update)?;
+        Self::check(&text, update).wrap_err("not saving a broken data module")?;
+        let summary = edit_summary(settings, update);
+        edit_page(client, &self.page, &text, &summary, None).await?;
+        info!("edited {}", self.page);
+        Ok(())
+    }
+}
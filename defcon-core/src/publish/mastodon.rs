@@ -0,0 +1,147 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration};
+use tracing::info;
+
+use super::{http_client, LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::index_url;
+
+#[derive(serde::Deserialize, Clone)]
+pub struct MastodonSettings {
+    /// Base URL of the instance, e.g. `https://mastodon.social`.
+    pub instance: String,
+    pub access_token: String,
+    /// Only toot changes to this level or a more severe one, and only when
+    /// the level gets worse.
+    #[serde(default = "default_max_level")]
+    pub max_level: u8,
+    /// Minutes after a toot during which no other one is posted.
+    #[serde(default = "default_min_interval_mins")]
+    pub min_interval_mins: i64,
+    /// `public`, `unlisted`, `private` or `direct`.
+    #[serde(default = "default_visibility")]
+    pub visibility: String,
+    /// The toot, with `{wiki}`, `{level}`, `{levels}` (how many there are),
+    /// `{previous}`, `{rpm}` and `{url}` (of the recent changes) filled in.
+    #[serde(default = "default_template")]
+    pub template: String,
+}
+
+fn default_max_level() -> u8 {
+    2
+}
+
+fn default_min_interval_mins() -> i64 {
+    60
+}
+
+fn default_visibility() -> String {
+    "public".to_owned()
+}
+
+fn default_template() -> String {
+    "Vandalism on {wiki} is at level {level} of {levels}, with {rpm} reverts per minute. {url}"
+        .to_owned()
+}
+
+/// A toot for every level change that gets to `max_level` or worse, at most
+/// one every `min_interval_mins`.
+pub struct Mastodon {
+    http: reqwest::Client,
+    settings: MastodonSettings,
+    last_toot: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Mastodon {
+    pub fn new(settings: MastodonSettings) -> reqwest::Result<Self> {
+        Ok(Mastodon {
+            http: http_client()?,
+            settings,
+            last_toot: Mutex::new(None),
+        })
+    }
+
+    fn is_significant(&self, settings: &Settings, update: &LevelUpdate<'_>) -> bool {
+        settings
+            .levels
+            .is_as_severe(update.level, self.settings.max_level)
+            && !settings
+                .levels
+                .is_as_severe(update.previous_level, update.level)
+    }
+
+    fn is_rate_limited(&self, now: DateTime<Utc>) -> bool {
+        self.last_toot
+            .lock()
+            .unwrap()
+            .is_some_and(|last| now - last < Duration::minutes(self.settings.min_interval_mins))
+    }
+
+    fn status(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        self.settings
+            .template
+            .replace("{wiki}", &settings.wiki)
+            .replace("{level}", &update.level.to_string())
+            .replace("{levels}", &settings.levels.len().to_string())
+            .replace("{previous}", &update.previous_level.to_string())
+            .replace("{rpm}", &format!("{:.2}", update.rpm))
+            .replace("{url}", &index_url(settings, "Special:RecentChanges"))
+    }
+}
+
+#[async_trait]
+impl Publisher for Mastodon {
+    fn name(&self) -> &str {
+        "mastodon"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        if !self.is_significant(settings, update) {
+            format!("nothing, level {} isn't significant", update.level)
+        } else if self.is_rate_limited(update.time) {
+            "nothing, tooted too recently".to_owned()
+        } else {
+            format!("toot: {}", self.status(settings, update))
+        }
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        if !self.is_significant(settings, update) {
+            return Ok(());
+        }
+        if self.is_rate_limited(update.time) {
+            info!("not tooting level {}, tooted too recently", update.level);
+            return Ok(());
+        }
+        let url = format!(
+            "{}/api/v1/statuses",
+            self.settings.instance.trim_end_matches('/')
+        );
+        let status = self.status(settings, update);
+        self.http
+            .post(&url)
+            .bearer_auth(&self.settings.access_token)
+            // retried requests don't post twice
+            .header(
+                "Idempotency-Key",
+                format!("defcon-{}-{}", settings.wiki, update.base_revid),
+            )
+            .form(&[
+                ("status", status.as_str()),
+                ("visibility", &self.settings.visibility),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+        *self.last_toot.lock().unwrap() = Some(update.time);
+        info!("tooted level {}", update.level);
+        Ok(())
+    }
+}
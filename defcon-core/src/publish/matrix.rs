@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use serde_json::json;
+use tracing::info;
+
+use super::{http_client, LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::index_url;
+
+#[derive(serde::Deserialize, Clone)]
+pub struct MatrixSettings {
+    /// Base URL of the homeserver, e.g. `https://matrix.org`.
+    pub homeserver: String,
+    pub access_token: String,
+    /// Room ID (not alias) to post to, e.g. `!abc123:matrix.org`.
+    pub room_id: String,
+}
+
+/// A message in a Matrix room for every level change.
+pub struct Matrix {
+    http: reqwest::Client,
+    settings: MatrixSettings,
+}
+
+/// Percent-encode `s` for use as a URL path segment.
+fn path_segment(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+impl Matrix {
+    pub fn new(settings: MatrixSettings) -> reqwest::Result<Self> {
+        Ok(Matrix {
+            http: http_client()?,
+            settings,
+        })
+    }
+
+    /// The plain and HTML bodies of the message.
+    fn bodies(settings: &Settings, update: &LevelUpdate<'_>) -> (String, String) {
+        let previous = if update.previous_level != 0 {
+            format!(" (was {})", update.previous_level)
+        } else {
+            String::new()
+        };
+        let rc = index_url(settings, "Special:RecentChanges");
+        let plain = format!(
+            "Vandalism level on {} is now {}{previous}, at {:.2} RPM: {rc}",
+            settings.wiki, update.level, update.rpm
+        );
+        let html = format!(
+            "Vandalism level on {} is now <strong>{}</strong>{previous}, at {:.2} RPM \
+             (<a href=\"{rc}\">recent changes</a>)",
+            settings.wiki, update.level, update.rpm
+        );
+        (plain, html)
+    }
+}
+
+#[async_trait]
+impl Publisher for Matrix {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        let (plain, _) = Self::bodies(settings, update);
+        format!("send to {}: {plain}", self.settings.room_id)
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        let (plain, html) = Self::bodies(settings, update);
+        // the transaction ID makes retries of the same update idempotent
+        let txn_id = format!(
+            "defcon-{}-{}",
+            settings.wiki,
+            update.time.timestamp_millis()
+        );
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.settings.homeserver.trim_end_matches('/'),
+            path_segment(&self.settings.room_id),
+            path_segment(&txn_id)
+        );
+        self.http
+            .put(url)
+            .bearer_auth(&self.settings.access_token)
+            .json(&json!({
+                "msgtype": "m.notice",
+                "body": plain,
+                "format": "org.matrix.custom.html",
+                "formatted_body": html,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        info!("sent to {}", self.settings.room_id);
+        Ok(())
+    }
+}
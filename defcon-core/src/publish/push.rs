@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use tracing::info;
+
+use super::{http_client, LevelUpdate, Publisher};
+use crate::settings::Settings;
+use crate::wiki::index_url;
+
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+#[derive(serde::Deserialize, Clone)]
+pub struct NtfySettings {
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    /// Access token for protected topics.
+    pub token: Option<String>,
+    /// Only push changes to or from this level or a more severe one.
+    #[serde(default = "default_max_level")]
+    pub max_level: u8,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct PushoverSettings {
+    /// API token of the Pushover application.
+    pub token: String,
+    /// User or group key to notify.
+    pub user: String,
+    /// Only push changes to or from this level or a more severe one.
+    #[serde(default = "default_max_level")]
+    pub max_level: u8,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_owned()
+}
+
+fn default_max_level() -> u8 {
+    2
+}
+
+/// Whether `update` enters, leaves or moves within the levels at least as
+/// severe as `max_level`.
+fn is_severe(settings: &Settings, update: &LevelUpdate<'_>, max_level: u8) -> bool {
+    settings.levels.is_as_severe(update.level, max_level)
+        || settings
+            .levels
+            .is_as_severe(update.previous_level, max_level)
+}
+
+fn title(settings: &Settings, update: &LevelUpdate<'_>) -> String {
+    format!("{} is at vandalism level {}", settings.wiki, update.level)
+}
+
+fn message(update: &LevelUpdate<'_>) -> String {
+    if update.previous_level != 0 {
+        format!(
+            "Was level {}, now at {:.2} RPM.",
+            update.previous_level, update.rpm
+        )
+    } else {
+        format!("Now at {:.2} RPM.", update.rpm)
+    }
+}
+
+/// How many steps `level` is below the most severe level.
+fn steps_from_worst(settings: &Settings, level: u8) -> usize {
+    let worst = settings.levels.len() - 1;
+    settings
+        .levels
+        .rank(level)
+        .map_or(worst, |rank| worst - rank)
+}
+
+/// Priority from 1 (lowest) to 5 (highest) for a change to `level`.
+fn priority(settings: &Settings, level: u8) -> u8 {
+    match steps_from_worst(settings, level) {
+        0 => 5,
+        1 => 4,
+        _ => 3,
+    }
+}
+
+/// A push notification on an ntfy topic for every severe level change.
+pub struct Ntfy {
+    http: reqwest::Client,
+    settings: NtfySettings,
+}
+
+impl Ntfy {
+    pub fn new(settings: NtfySettings) -> reqwest::Result<Self> {
+        Ok(Ntfy {
+            http: http_client()?,
+            settings,
+        })
+    }
+}
+
+#[async_trait]
+impl Publisher for Ntfy {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        if !is_severe(settings, update, self.settings.max_level) {
+            return format!("nothing, level {} isn't severe enough", update.level);
+        }
+        format!(
+            "push to ntfy topic {}: {}: {}",
+            self.settings.topic,
+            title(settings, update),
+            message(update)
+        )
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        if !is_severe(settings, update, self.settings.max_level) {
+            return Ok(());
+        }
+        let url = format!(
+            "{}/{}",
+            self.settings.server.trim_end_matches('/'),
+            self.settings.topic
+        );
+        let mut request = self
+            .http
+            .post(&url)
+            .header("Title", title(settings, update))
+            .header("Priority", priority(settings, update.level).to_string())
+            .header("Tags", "rotating_light")
+            .header("Click", index_url(settings, "Special:RecentChanges"))
+            .body(message(update));
+        if let Some(token) = &self.settings.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        info!("pushed to ntfy topic {}", self.settings.topic);
+        Ok(())
+    }
+}
+
+/// A Pushover notification for every severe level change.
+pub struct Pushover {
+    http: reqwest::Client,
+    settings: PushoverSettings,
+}
+
+impl Pushover {
+    pub fn new(settings: PushoverSettings) -> reqwest::Result<Self> {
+        Ok(Pushover {
+            http: http_client()?,
+            settings,
+        })
+    }
+}
+
+#[async_trait]
+impl Publisher for Pushover {
+    fn name(&self) -> &str {
+        "pushover"
+    }
+
+    fn preview(&self, settings: &Settings, update: &LevelUpdate<'_>) -> String {
+        if !is_severe(settings, update, self.settings.max_level) {
+            return format!("nothing, level {} isn't severe enough", update.level);
+        }
+        format!(
+            "push to Pushover: {}: {}",
+            title(settings, update),
+            message(update)
+        )
+    }
+
+    async fn publish(
+        &self,
+        _client: &mw::Client,
+        settings: &Settings,
+        update: &LevelUpdate<'_>,
+    ) -> color_eyre::Result<()> {
+        if !is_severe(settings, update, self.settings.max_level) {
+            return Ok(());
+        }
+        // Pushover priorities go from -2 to 2; 2 needs acknowledging, so
+        // stop at 1
+        let priority = match steps_from_worst(settings, update.level) {
+            0 | 1 => "1",
+            _ => "0",
+        };
+        let title = title(settings, update);
+        let message = message(update);
+        let url = index_url(settings, "Special:RecentChanges");
+        self.http
+            .post(PUSHOVER_URL)
+            .form(&[
+                ("token", self.settings.token.as_str()),
+                ("user", &self.settings.user),
+                ("title", &title),
+                ("message", &message),
+                ("priority", priority),
+                ("url", &url),
+                ("url_title", "Recent changes"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+        info!("pushed to Pushover");
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! The wikitext written to the report page: one of the built-in presets, or
+//! a Tera template file from the settings.
+
+use std::path::Path;
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use tera::Tera;
+
+use crate::level::{Levels, Trend};
+use crate::metrics::Metrics;
+use crate::publish::LevelUpdate;
+use crate::wiki::read_level;
+
+const TEMPLATE_NAME: &str = "report";
+
+/// How the report page is written.
+#[derive(Default)]
+pub enum ReportFormat {
+    /// A `#switch` on the template's first parameter, with `level`, `sign`,
+    /// `info` and `trend` cases.
+    #[default]
+    Switch,
+    /// Exactly the `level`, `sign` and `info` cases enwiki's
+    /// `{{Vandalism information}}` templates read.
+    VandalismInformation,
+    /// Just the level, for gadgets reading a bare number.
+    Number,
+    /// A Tera template given the `ReportVars`.
+    Template(Tera),
+}
+
+/// The built-in formats, selectable by name in the settings.
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPreset {
+    #[default]
+    Switch,
+    VandalismInformation,
+    Number,
+}
+
+impl From<ReportPreset> for ReportFormat {
+    fn from(preset: ReportPreset) -> Self {
+        match preset {
+            ReportPreset::Switch => ReportFormat::Switch,
+            ReportPreset::VandalismInformation => ReportFormat::VandalismInformation,
+            ReportPreset::Number => ReportFormat::Number,
+        }
+    }
+}
+
+/// What goes into the report page.
+#[derive(serde::Serialize)]
+pub struct ReportVars<'a> {
+    pub level: u8,
+    /// The level before this change, or 0 if there was none.
+    pub previous_level: u8,
+    /// Rounded to two decimals.
+    pub rpm: f64,
+    /// The level's input on a 0–100 scale, rounded to one decimal.
+    pub severity: f64,
+    pub trend: String,
+    /// The RPM expected an hour from now, if forecasting.
+    pub forecast: Option<f64>,
+    /// The localized info text.
+    pub info: String,
+    /// RFC 3339 time of the run.
+    pub timestamp: String,
+    pub metrics: &'a Metrics,
+}
+
+fn round(value: f32) -> f64 {
+    (f64::from(value) * 100.0).round() / 100.0
+}
+
+impl<'a> ReportVars<'a> {
+    pub fn new(update: &LevelUpdate<'a>, info: String) -> Self {
+        ReportVars {
+            level: update.level,
+            previous_level: update.previous_level,
+            rpm: round(update.rpm),
+            severity: (f64::from(update.severity) * 10.0).round() / 10.0,
+            trend: update.trend.to_string(),
+            forecast: update.forecast.map(round),
+            info,
+            timestamp: update.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            metrics: update.metrics,
+        }
+    }
+
+    /// For a new report page, without a level until the first run sets one.
+    pub fn initial(info: String, time: DateTime<Utc>, metrics: &'a Metrics) -> Self {
+        ReportVars {
+            level: 0,
+            previous_level: 0,
+            rpm: 0.0,
+            severity: 0.0,
+            trend: Trend::Stable.to_string(),
+            forecast: None,
+            info,
+            timestamp: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            metrics,
+        }
+    }
+}
+
+impl ReportFormat {
+    /// The Tera template in the file at `path`.
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_template_file(path, Some(TEMPLATE_NAME))
+            .wrap_err_with(|| format!("invalid report template {}", path.display()))?;
+        Ok(ReportFormat::Template(tera))
+    }
+
+    /// Whether every one of `levels` can be read back from the page written
+    /// in this format.
+    pub fn reads_back(&self, levels: &Levels) -> bool {
+        let metrics = Metrics::new();
+        levels.iter().all(|level| {
+            let vars = ReportVars {
+                level,
+                ..ReportVars::initial(String::new(), DateTime::default(), &metrics)
+            };
+            self.render(&vars)
+                .is_ok_and(|text| read_level(&text) == Some(level))
+        })
+    }
+
+    pub fn render(&self, vars: &ReportVars<'_>) -> color_eyre::Result<String> {
+        match self {
+            ReportFormat::Switch => Ok(format!(
+                "{{{{#switch: {{{{{{1}}}}}}
+              | level = {}
+              | sign = ~~~~~
+              | info = {}
+              | trend = {}
+            }}}}",
+                vars.level, vars.info, vars.trend
+            )),
+            ReportFormat::VandalismInformation => Ok(format!(
+                "{{{{#switch: {{{{{{1}}}}}}
+  | level = {}
+  | sign = ~~~~~
+  | info = {}
+}}}}",
+                vars.level, vars.info
+            )),
+            ReportFormat::Number => Ok(vars.level.to_string()),
+            ReportFormat::Template(tera) => {
+                let context = tera::Context::from_serialize(vars)?;
+                tera.render(TEMPLATE_NAME, &context)
+                    .wrap_err("could not render the report template")
+            }
+        }
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::{bail, WrapErr};
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::debug;
+
+use crate::classifier::{is_revert, Classifier};
+use crate::metrics::Context;
+use crate::settings::Settings;
+use crate::wiki::{api_get, on_tape, query_all};
+
+/// Revisions the API looks up per request.
+const REVISIONS_PER_REQUEST: usize = 50;
+
+lazy_static! {
+    /// A link to a user's contributions in an edit summary.
+    static ref CONTRIBUTIONS_LINK_RE: Regex =
+        Regex::new(r"(?i)\[\[\s*Special:Contrib(?:utions|s)/([^|\]]+)").unwrap();
+}
+
+/// Count the reverts, given as their page and weight, counting at most
+/// `max_per_page` of them (the heaviest) for any one page so a single edit
+/// war doesn't dominate.
+pub fn count_reverts<'a>(
+    reverts: impl IntoIterator<Item = (&'a str, f32)>,
+    max_per_page: Option<usize>,
+) -> f32 {
+    let mut per_page: HashMap<&str, Vec<f32>> = HashMap::new();
+    for (title, weight) in reverts {
+        per_page.entry(title).or_default().push(weight);
+    }
+    per_page
+        .into_values()
+        .map(|mut weights| {
+            weights.sort_by(|a, b| b.total_cmp(a));
+            weights.truncate(max_per_page.unwrap_or(usize::MAX));
+            weights.iter().sum::<f32>()
+        })
+        .sum()
+}
+
+/// Settings of the `[revert_weights]` table: how much a revert counts, by the
+/// change tag saying how it was made. Rollback is used almost only against
+/// clear vandalism, so it can be made to count more.
+#[derive(serde::Deserialize, Clone)]
+pub struct RevertWeights {
+    /// Reverts tagged `mw-rollback`.
+    #[serde(default = "default_weight")]
+    pub rollback: f32,
+    /// Reverts tagged `mw-undo`.
+    #[serde(default = "default_weight")]
+    pub undo: f32,
+    /// Reverts tagged `mw-manual-revert`.
+    #[serde(default = "default_weight")]
+    pub manual: f32,
+    /// Reverts without any of these tags, e.g. found by their summary only.
+    #[serde(default = "default_weight")]
+    pub other: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl Default for RevertWeights {
+    fn default() -> Self {
+        RevertWeights {
+            rollback: 1.0,
+            undo: 1.0,
+            manual: 1.0,
+            other: 1.0,
+        }
+    }
+}
+
+impl RevertWeights {
+    pub fn validate(&self) -> color_eyre::Result<()> {
+        for (key, weight) in [
+            ("rollback", self.rollback),
+            ("undo", self.undo),
+            ("manual", self.manual),
+            ("other", self.other),
+        ] {
+            if !weight.is_finite() || weight < 0.0 {
+                bail!("`revert_weights.{key}` must be a non-negative number, found {weight}");
+            }
+        }
+        Ok(())
+    }
+
+    /// The weight of a revert with `tags`.
+    pub fn of(&self, tags: &[String]) -> f32 {
+        let has = |tag: &str| tags.iter().any(|t| t == tag);
+        if has("mw-rollback") {
+            self.rollback
+        } else if has("mw-undo") {
+            self.undo
+        } else if has("mw-manual-revert") {
+            self.manual
+        } else {
+            self.other
+        }
+    }
+}
+
+/// A revert of vandalism found in the recent changes.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Revert {
+    /// Namespace of the page, 0 in caches from before it was kept.
+    #[serde(default)]
+    pub namespace: i64,
+    pub title: String,
+    pub user: String,
+    /// How much it counts, from `revert_weights`.
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// The user whose edit it reverted, if known.
+    #[serde(default)]
+    pub reverted: Option<String>,
+}
+
+/// The edits of the last interval, as counted by `settings`.
+#[derive(Clone, serde::Serialize)]
+pub struct RecentEdits {
+    /// Number of edits, reverts included.
+    pub edits: usize,
+    /// The edits that reverted vandalism.
+    pub reverts: Vec<Revert>,
+    /// Minutes the edits were made in.
+    pub minutes: f32,
+}
+
+impl RecentEdits {
+    /// The weighted number of reverts, capped per page by
+    /// `max_reverts_per_page`.
+    pub fn count_reverts(&self, settings: &Settings) -> f32 {
+        count_reverts(
+            self.reverts
+                .iter()
+                .map(|revert| (revert.title.as_str(), revert.weight)),
+            settings.max_reverts_per_page,
+        )
+    }
+
+    /// Reverts per minute.
+    pub fn rpm(&self, settings: &Settings) -> f32 {
+        self.count_reverts(settings) / self.minutes
+    }
+
+    /// Reverts per minute of pages in `namespace`.
+    pub fn rpm_in(&self, settings: &Settings, namespace: i64) -> f32 {
+        let reverts = count_reverts(
+            self.reverts
+                .iter()
+                .filter(|revert| revert.namespace == namespace)
+                .map(|revert| (revert.title.as_str(), revert.weight)),
+            settings.max_reverts_per_page,
+        );
+        reverts / self.minutes
+    }
+
+    /// Reverts as a fraction of all edits.
+    pub fn ratio(&self, settings: &Settings) -> f32 {
+        if self.edits == 0 {
+            return 0.0;
+        }
+        self.count_reverts(settings) / self.edits as f32
+    }
+
+    /// Number of distinct users who reverted.
+    pub fn reverters(&self) -> usize {
+        let users: HashSet<&str> = self
+            .reverts
+            .iter()
+            .map(|revert| revert.user.as_str())
+            .collect();
+        users.len()
+    }
+}
+
+/// A recent change, as fetched.
+pub(crate) struct Change {
+    pub(crate) rcid: u64,
+    /// The revision before this one, 0 for page creations.
+    pub(crate) old_revid: u64,
+    /// Unix timestamp.
+    pub(crate) timestamp: i64,
+    pub(crate) namespace: i64,
+    pub(crate) title: String,
+    pub(crate) user: String,
+    pub(crate) comment: String,
+    pub(crate) tags: Vec<String>,
+}
+
+/// A change of `list=recentchanges`, with `rcprop=ids|timestamp|title|user|comment|tags`.
+#[derive(serde::Deserialize)]
+struct RcEntry {
+    rcid: u64,
+    #[serde(default)]
+    old_revid: u64,
+    timestamp: String,
+    #[serde(default)]
+    ns: i64,
+    title: String,
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    comment: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl From<RcEntry> for Change {
+    fn from(entry: RcEntry) -> Self {
+        Change {
+            rcid: entry.rcid,
+            old_revid: entry.old_revid,
+            timestamp: DateTime::parse_from_rfc3339(&entry.timestamp)
+                .map_or(0, |ts| ts.timestamp()),
+            namespace: entry.ns,
+            title: entry.title,
+            user: entry.user,
+            comment: entry.comment,
+            tags: entry.tags,
+        }
+    }
+}
+
+/// The edits made in `bucket_mins` starting at `start`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Bucket {
+    /// Unix timestamp, a multiple of the bucket length.
+    start: i64,
+    edits: usize,
+    reverts: Vec<Revert>,
+}
+
+/// The edits of the last `window_mins`, in buckets of `bucket_mins`. Each run
+/// only fetches the changes newer than the highest `rcid` seen, which mostly
+/// land in the newest bucket; older buckets are kept as they are, so rule
+/// changes only apply to edits made from then on.
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+struct Window {
+    /// The `window_key` of the settings it was counted with.
+    #[serde(default)]
+    key: String,
+    bucket_secs: i64,
+    /// Oldest first.
+    buckets: VecDeque<Bucket>,
+    last_rcid: Option<u64>,
+    /// Unix timestamp of the newest change seen.
+    newest: Option<i64>,
+}
+
+impl Window {
+    fn new(key: String, bucket_secs: i64) -> Self {
+        Window {
+            key,
+            bucket_secs,
+            ..Window::default()
+        }
+    }
+
+    /// Add the `changes` newer than any seen before to their buckets, with
+    /// the users the reverts reverted from `reverted_users`.
+    fn merge(
+        &mut self,
+        changes: Vec<Change>,
+        settings: &Settings,
+        classifier: &Classifier,
+        reverted: &HashMap<u64, String>,
+    ) {
+        let last_rcid = self.last_rcid;
+        for change in changes {
+            if last_rcid.is_some_and(|last| change.rcid <= last) {
+                continue;
+            }
+            self.last_rcid = self.last_rcid.max(Some(change.rcid));
+            self.newest = self.newest.max(Some(change.timestamp));
+            if settings.excluded_users.contains(&change.user) {
+                continue;
+            }
+            let start = change.timestamp - change.timestamp.rem_euclid(self.bucket_secs);
+            let index = match self.buckets.iter().position(|bucket| bucket.start == start) {
+                Some(index) => index,
+                None => {
+                    // changes come oldest first, so a new bucket is the newest
+                    self.buckets.push_back(Bucket {
+                        start,
+                        edits: 0,
+                        reverts: Vec::new(),
+                    });
+                    self.buckets.len() - 1
+                }
+            };
+            let bucket = &mut self.buckets[index];
+            bucket.edits += 1;
+            if is_revert(
+                classifier,
+                settings.detection,
+                &change.comment,
+                &change.tags,
+            ) && !is_self_revert(settings, &change, reverted)
+            {
+                bucket.reverts.push(Revert {
+                    namespace: change.namespace,
+                    weight: settings.revert_weights.of(&change.tags),
+                    reverted: reverted.get(&change.rcid).cloned(),
+                    title: change.title,
+                    user: change.user,
+                });
+            }
+        }
+    }
+
+    /// Drop the buckets starting before `oldest`.
+    fn expire(&mut self, oldest: i64) {
+        self.buckets.retain(|bucket| bucket.start >= oldest);
+    }
+}
+
+lazy_static! {
+    /// The windows of the profiles run by this process, by `window_key`.
+    static ref WINDOWS: Mutex<HashMap<String, Window>> = Mutex::new(HashMap::new());
+}
+
+/// Every profile has its own window, since what counts as a revert differs.
+/// So does a profile whose settings for that changed on a reload.
+pub(crate) fn window_key(settings: &Settings) -> String {
+    format!(
+        "{}|{}|{:?}|{}|{}|{}|{}",
+        settings.api_url,
+        settings.report_page,
+        settings.detection,
+        settings.namespaces,
+        settings.exclude_bots,
+        settings.exclude_self_reverts,
+        settings.excluded_users.join(",")
+    )
+}
+
+fn load_window(path: &Path) -> color_eyre::Result<Window> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text)
+            .wrap_err_with(|| format!("invalid recent changes cache {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Window::default()),
+        Err(e) => Err(e)
+            .wrap_err_with(|| format!("could not read recent changes cache {}", path.display())),
+    }
+}
+
+/// The recent changes from `since` on, up to `until` if given, oldest first.
+pub(crate) async fn fetch_changes(
+    client: &mw::Client,
+    settings: &Settings,
+    since: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+) -> color_eyre::Result<Vec<Change>> {
+    let start_str = since.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let end_str = until.map(|until| until.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let namespaces = settings
+        .namespace_ids()?
+        .iter()
+        .map(|ns| ns.to_string())
+        .collect::<Vec<_>>()
+        .join("|");
+    let mut query = vec![
+        ("action", "query"),
+        ("list", "recentchanges"),
+        ("rctype", "edit"),
+        ("rcdir", "newer"),
+        ("rcstart", &start_str),
+        ("rcprop", "ids|timestamp|title|user|comment|tags"),
+        ("rclimit", "max"),
+    ];
+    if let Some(end_str) = &end_str {
+        query.push(("rcend", end_str));
+    }
+    if !namespaces.is_empty() {
+        query.push(("rcnamespace", &namespaces));
+    }
+    if settings.exclude_bots {
+        query.push(("rcshow", "!bot"));
+    }
+    #[derive(serde::Deserialize)]
+    struct RecentChanges {
+        recentchanges: Vec<RcEntry>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Res {
+        query: RecentChanges,
+    }
+    query_all(client, &query, |res: Res| {
+        Ok(res
+            .query
+            .recentchanges
+            .into_iter()
+            .map(Change::from)
+            .collect())
+    })
+    .await
+}
+
+/// The user whose edit a revert with `summary` reverted, if the summary
+/// links to their contributions as the ones MediaWiki writes for undo and
+/// rollback do.
+pub(crate) fn named_user(summary: &str) -> Option<String> {
+    CONTRIBUTIONS_LINK_RE
+        .captures(summary)
+        .map(|caps| caps[1].trim().replace('_', " "))
+}
+
+/// The user each of the `changes` counted as reverts reverted, by rcid. It's
+/// the one the summary names, or else the author of the revision before the
+/// revert, which is only looked up when `exclude_self_reverts` or
+/// `[concentration]` needs it. Deleted and hidden authors are left out.
+pub(crate) async fn reverted_users(
+    client: &mw::Client,
+    settings: &Settings,
+    classifier: &Classifier,
+    changes: &[Change],
+) -> color_eyre::Result<HashMap<u64, String>> {
+    let look_up = settings.exclude_self_reverts || settings.concentration.is_some();
+    let mut found = HashMap::new();
+    let mut unnamed: HashMap<u64, Vec<u64>> = HashMap::new();
+    for change in changes {
+        if !is_revert(
+            classifier,
+            settings.detection,
+            &change.comment,
+            &change.tags,
+        ) {
+            continue;
+        }
+        match named_user(&change.comment) {
+            Some(user) => {
+                found.insert(change.rcid, user);
+            }
+            None if look_up && change.old_revid != 0 => {
+                unnamed
+                    .entry(change.old_revid)
+                    .or_default()
+                    .push(change.rcid);
+            }
+            None => {}
+        }
+    }
+    let revids: Vec<String> = unnamed.keys().map(u64::to_string).collect();
+    for chunk in revids.chunks(REVISIONS_PER_REQUEST) {
+        let revids = chunk.join("|");
+        let query = [
+            ("action", "query"),
+            ("prop", "revisions"),
+            ("revids", &revids),
+            ("rvprop", "ids|user"),
+        ];
+        let res = api_get(client, &query).await?;
+        let revisions = res["query"]["pages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|page| page["revisions"].as_array().into_iter().flatten());
+        for revision in revisions {
+            let (revid, user) = match (revision["revid"].as_u64(), revision["user"].as_str()) {
+                (Some(revid), Some(user)) => (revid, user),
+                // deleted or hidden
+                _ => continue,
+            };
+            for rcid in unnamed.get(&revid).into_iter().flatten() {
+                found.insert(*rcid, user.to_owned());
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Whether `change` reverted its own author's edit, given `reverted_users`,
+/// and so isn't counted with `exclude_self_reverts`.
+pub(crate) fn is_self_revert(
+    settings: &Settings,
+    change: &Change,
+    reverted: &HashMap<u64, String>,
+) -> bool {
+    settings.exclude_self_reverts && reverted.get(&change.rcid) == Some(&change.user)
+}
+
+/// The changes in the dump at `path`: one `list=recentchanges` entry as JSON
+/// per line, e.g. collected from the API while they were recent.
+pub(crate) fn read_changes(path: &Path) -> color_eyre::Result<Vec<Change>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("could not read recent changes dump {}", path.display()))?;
+    let mut changes = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str::<RcEntry>(line)
+                .map(Change::from)
+                .wrap_err_with(|| format!("invalid change on line {} of {}", i + 1, path.display()))
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()?;
+    changes.sort_by_key(|change| (change.timestamp, change.rcid));
+    Ok(changes)
+}
+
+/// Unix timestamp of the start of the window of the run at `now`: the
+/// `window_mins` of full buckets before the one `now` is in, which is still
+/// filling up, so that the window is never shorter than `window_mins`.
+pub(crate) fn window_start(settings: &Settings, now: i64) -> i64 {
+    let bucket_secs = settings.bucket_mins as i64 * 60;
+    now - now.rem_euclid(bucket_secs) - settings.window_mins as i64 * 60
+}
+
+/// The edits of the `window_mins` up to `ctx.now`, fetched once per run and
+/// shared by everything counting them. Only changes newer than the last ones
+/// seen by this process (or recorded in `rc_cache_file`) are fetched, except
+/// by runs on tape, which fetch the whole window.
+pub async fn recent_edits<'c>(ctx: &'c Context<'_>) -> color_eyre::Result<&'c RecentEdits> {
+    ctx.recent.get_or_try_init(|| fetch_recent_edits(ctx)).await
+}
+
+async fn fetch_recent_edits(ctx: &Context<'_>) -> color_eyre::Result<RecentEdits> {
+    let Context {
+        client,
+        settings,
+        classifier,
+        now,
+        ..
+    } = *ctx;
+    let now = now.timestamp();
+    let bucket_secs = settings.bucket_mins as i64 * 60;
+    let oldest = window_start(settings, now);
+    let key = window_key(settings);
+    let taped = on_tape();
+    let cached = if taped {
+        None
+    } else {
+        WINDOWS.lock().unwrap().get(&key).cloned()
+    };
+    let cache_file = settings.rc_cache_file.as_deref().filter(|_| !taped);
+    let cached = match (cached, cache_file) {
+        (Some(window), _) => window,
+        (None, Some(path)) => load_window(path)?,
+        (None, None) => Window::new(key.clone(), bucket_secs),
+    };
+    // a cache file counted with other settings is of no use
+    let cached = if cached.key == key && cached.bucket_secs == bucket_secs {
+        cached
+    } else {
+        Window::new(key.clone(), bucket_secs)
+    };
+    // from the newest change seen, which the rcid check keeps from being
+    // counted twice, unless that's too long ago to be of use
+    let start = cached
+        .newest
+        .filter(|newest| *newest >= oldest)
+        .unwrap_or(oldest);
+    let start = Utc.timestamp_opt(start, 0).single().unwrap_or(ctx.now);
+    let changes = fetch_changes(client, settings, start, None).await?;
+    debug!(
+        fetched = changes.len(),
+        since = %start,
+        "fetched recent changes"
+    );
+    let reverted = reverted_users(client, settings, classifier, &changes).await?;
+    let mut window = cached;
+    window.merge(changes, settings, classifier, &reverted);
+    window.expire(oldest);
+    // a dry run, replaying included, leaves the cache as it was
+    if !taped && !settings.dry_run {
+        WINDOWS.lock().unwrap().insert(key, window.clone());
+        if let Some(path) = cache_file {
+            std::fs::write(path, serde_json::to_string(&window)?).wrap_err_with(|| {
+                format!("could not write recent changes cache {}", path.display())
+            })?;
+        }
+    }
+    Ok(RecentEdits {
+        edits: window.buckets.iter().map(|bucket| bucket.edits).sum(),
+        reverts: window
+            .buckets
+            .into_iter()
+            .flat_map(|bucket| bucket.reverts)
+            .collect(),
+        minutes: (now - oldest).max(60) as f32 / 60.0,
+    })
+}
+
+pub async fn reverts_per_minute(ctx: &Context<'_>) -> color_eyre::Result<f32> {
+    let recent = recent_edits(ctx).await?;
+    let reverts = recent.count_reverts(ctx.settings);
+    let rpm = recent.rpm(ctx.settings);
+    debug!(edits = recent.edits, reverts, rpm, "computed rpm");
+    Ok(rpm)
+}
+
+/// Reverts of vandalism as a fraction of all edits in the last interval,
+/// which unlike the RPM doesn't depend on how busy the wiki is.
+pub async fn revert_ratio(ctx: &Context<'_>) -> color_eyre::Result<f32> {
+    let recent = recent_edits(ctx).await?;
+    Ok(recent.ratio(ctx.settings))
+}
+
+/// Number of distinct users who reverted vandalism in the last interval.
+pub async fn distinct_reverters(ctx: &Context<'_>) -> color_eyre::Result<usize> {
+    let recent = recent_edits(ctx).await?;
+    Ok(recent.reverters())
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{bail, eyre};
+use serde_json::Value;
+use tracing::info;
+
+use crate::classifier::{Classifier, Keywords};
+use crate::model::Model;
+use crate::plugin::{self, Plugin};
+use crate::settings::Settings;
+use crate::wiki::latest_revision;
+
+/// The classifier in use, optionally loaded from a JSON page on the wiki
+/// (`rules_page`) so that rule changes don't need a redeploy. The page is
+/// only re-parsed when its revision changes; if it can't be fetched or fails
+/// validation, the last good rules (or those from the settings) stay in use.
+/// The classifier plugin and model, if any, are loaded once and shared by all
+/// of them.
+pub struct Rules {
+    page: Option<String>,
+    fallback: Classifier,
+    cached: Option<(u64, Classifier)>,
+    plugin: Option<Arc<Plugin>>,
+    model: Option<Arc<Model>>,
+}
+
+impl Rules {
+    pub fn new(settings: &Settings) -> color_eyre::Result<Self> {
+        let plugin = plugin::classifier(&settings.plugins)?;
+        let model = match &settings.model {
+            Some(path) => Some(Arc::new(Model::load(path)?)),
+            None => None,
+        };
+        Ok(Rules {
+            page: settings.rules_page.clone(),
+            fallback: Classifier::new(&settings.keywords)?
+                .with_plugin(plugin.clone())
+                .with_model(model.clone()),
+            cached: None,
+            plugin,
+            model,
+        })
+    }
+
+    pub fn classifier(&self) -> &Classifier {
+        match &self.cached {
+            Some((_, classifier)) => classifier,
+            None => &self.fallback,
+        }
+    }
+
+    pub async fn refresh(&mut self, client: &mw::Client) {
+        let page = match &self.page {
+            Some(page) => page,
+            None => return,
+        };
+        let rev = match latest_revision(client, page, "ids|content|contentmodel").await {
+            Ok(rev) => rev,
+            Err(e) => {
+                tracing::warn!("could not fetch rules from {page}: {e}");
+                return;
+            }
+        };
+        let revid = rev["revid"].as_u64();
+        if revid.is_some() && revid == self.cached.as_ref().map(|(revid, _)| *revid) {
+            return;
+        }
+        match Self::parse(&rev) {
+            Ok(classifier) => {
+                info!("loaded rules from {page}");
+                let classifier = classifier
+                    .with_plugin(self.plugin.clone())
+                    .with_model(self.model.clone());
+                self.cached = Some((revid.unwrap_or(0), classifier));
+            }
+            Err(e) => tracing::warn!("ignoring invalid rules on {page}: {e}"),
+        }
+    }
+
+    fn parse(rev: &Value) -> color_eyre::Result<Classifier> {
+        let slot = &rev["slots"]["main"];
+        if slot["contentmodel"] != "json" {
+            bail!("page does not have the json content model");
+        }
+        let content = slot["content"]
+            .as_str()
+            .ok_or_else(|| eyre!("revision has no content"))?;
+        let keywords: Keywords = serde_json::from_str(content)?;
+        if keywords.vandalism.is_empty() {
+            bail!("no vandalism keywords given");
+        }
+        Ok(Classifier::new(&keywords)?)
+    }
+}
@@ -0,0 +1,420 @@
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::eyre;
+use futures_util::future::join;
+use tracing::{info, warn};
+
+use crate::baseline;
+use crate::clock::Clock;
+use crate::concentration::{self, Concentration};
+use crate::email;
+use crate::forecast::forecast_rpm;
+use crate::history::{History, Sample};
+use crate::hotspots;
+use crate::jobs::run_jobs;
+use crate::level::Trend;
+use crate::metrics::{collect_all, Context, MetricSource, Metrics};
+use crate::monitor;
+use crate::publish::{is_declined, publish_all, LevelUpdate, Publisher};
+use crate::rules::Rules;
+use crate::schedule::Timer;
+use crate::scoring::Signal;
+use crate::settings::Settings;
+use crate::smoothing;
+use crate::spike;
+use crate::state::RunState;
+use crate::stop;
+use crate::systemd;
+use crate::wiki::{
+    bot_excluded, current_report, has_bots_template, index_url, level_changed_at, on_tape,
+    username, writes_enabled, ReportRevision,
+};
+
+/// Why the report page should be left as `rev` has it, if it should: someone
+/// set the level by hand, a cooperating bot just updated it, or it excludes
+/// us with `{{bots}}`/`{{nobots}}`.
+pub async fn stand_down_reason(
+    client: &mw::Client,
+    settings: &Settings,
+    rev: &ReportRevision,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<Option<String>> {
+    let edited_within = |mins: u64| {
+        rev.timestamp
+            .is_some_and(|ts| now - ts < Duration::minutes(mins as i64))
+    };
+    if settings.cooperating_bots.contains(&rev.user) {
+        // another bot keeping the same page up to date had its turn
+        if edited_within(settings.run_interval_mins) {
+            return Ok(Some(format!(
+                "{} updated it less than {} minutes ago",
+                rev.user, settings.run_interval_mins
+            )));
+        }
+    } else if edited_within(settings.override_mins) && rev.user != username(client).await? {
+        // a level set by hand stands for `override_mins`
+        return Ok(Some(format!(
+            "{} set it less than {} minutes ago",
+            rev.user, settings.override_mins
+        )));
+    }
+    if has_bots_template(&rev.text) {
+        let user = username(client).await?;
+        if bot_excluded(&rev.text, &user) {
+            warn!(
+                "{} excludes {user} with {{{{bots}}}} or {{{{nobots}}}}",
+                settings.report_page
+            );
+            return Ok(Some(format!("{} excludes {user}", settings.report_page)));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the move from `curr_level` to `level` has to wait: the level last
+/// changed at `changed`, less than `min_dwell_mins` ago, and it's only one
+/// step. Bigger jumps always go through.
+pub fn dwelling(
+    settings: &Settings,
+    changed: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    curr_level: u8,
+    level: u8,
+) -> bool {
+    let cooling_down =
+        changed.is_some_and(|ts| now - ts < Duration::minutes(settings.min_dwell_mins as i64));
+    let small_step = settings
+        .levels
+        .distance(curr_level, level)
+        .is_some_and(|distance| distance < 2);
+    curr_level != level && cooling_down && small_step
+}
+
+/// Work out the level from `metrics` and publish it if it differs from the
+/// one on the report page, mentioning the `concentration` of the reverts if
+/// any. With a `state_file`, the report page is only read when it changed
+/// since the last run.
+pub async fn update_level(
+    client: &mw::Client,
+    settings: &Settings,
+    metrics: &Metrics,
+    concentration: Option<&Concentration>,
+    publishers: &[Box<dyn Publisher>],
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    // a run on tape reads the report page as recorded
+    let path = match settings.state_file.as_deref().filter(|_| !on_tape()) {
+        Some(path) => path,
+        None => {
+            return update_level_with(
+                client,
+                settings,
+                metrics,
+                concentration,
+                publishers,
+                now,
+                None,
+            )
+            .await
+        }
+    };
+    let mut state = RunState::load(path)?;
+    let res = update_level_with(
+        client,
+        settings,
+        metrics,
+        concentration,
+        publishers,
+        now,
+        Some(&mut state),
+    )
+    .await;
+    if !settings.dry_run {
+        state.save(path)?;
+    }
+    res
+}
+
+async fn update_level_with(
+    client: &mw::Client,
+    settings: &Settings,
+    metrics: &Metrics,
+    concentration: Option<&Concentration>,
+    publishers: &[Box<dyn Publisher>],
+    now: DateTime<Utc>,
+    mut state: Option<&mut RunState>,
+) -> color_eyre::Result<()> {
+    let rpm = *metrics
+        .get("rpm")
+        .ok_or_else(|| eyre!("the rpm metric was not collected"))? as f32;
+    let mut metrics = metrics.clone();
+    if let Some(baseline) = &settings.baseline {
+        baseline::apply(baseline, &mut metrics, now, !settings.dry_run)?;
+    }
+    if let Some(smoothing) = &settings.smoothing {
+        smoothing::apply(smoothing, &mut metrics, !settings.dry_run)?;
+    }
+    let metrics = &metrics;
+    let signal = Signal::new(settings, metrics)?;
+    if let Some(score) = &signal.score {
+        info!("score {score}");
+    }
+
+    // get current on-wiki defcon level
+    let rev = match &mut state {
+        Some(state) => state.current_report(client, settings, now).await?,
+        None => current_report(client, settings, now).await?,
+    };
+    // a level that isn't on the scale counts as none
+    let curr_level = if settings.levels.contains(rev.level) {
+        rev.level
+    } else {
+        0
+    };
+
+    // compute current defcon level
+    let level = signal.level(settings, metrics, curr_level)?;
+    let severity = signal.severity();
+    info!(rpm, severity, current = curr_level, level, "computed level");
+    systemd::status(&format!(
+        "level {level} on {} ({rpm:.2} RPM)",
+        settings.wiki
+    ));
+    monitor::record_level(&settings.wiki, level, f64::from(rpm), f64::from(severity));
+    if settings.dry_run {
+        println!(
+            "{rpm:.2} RPM, severity {severity:.1}, level {curr_level} on {}, computed level {level}",
+            settings.report_page
+        );
+        if let Some(score) = &signal.score {
+            println!("score {score}");
+        }
+    }
+    if let Some(spikes) = &settings.spikes {
+        spike::detect(client, settings, spikes, f64::from(rpm), level, now).await?;
+    }
+    if let (Some(settings_concentration), Some(found)) = (&settings.concentration, concentration) {
+        concentration::alert(client, settings, settings_concentration, found, level, now).await?;
+    }
+    if let Some(email) = &settings.email {
+        email::check_sustained(settings, email, &rev, level, now).await?;
+    }
+
+    let history = match &settings.database {
+        Some(path) => Some(History::open(path, &settings.wiki)?),
+        None => None,
+    };
+    let forecast = match &history {
+        Some(history) if settings.forecast => forecast_rpm(history, settings, now, rpm)?,
+        _ => None,
+    };
+    // the RPM last published, or else the one of the last run
+    let previous_rpm = match state.as_deref().and_then(|state| state.published) {
+        Some(published) => Some(published.rpm),
+        None => match &history {
+            Some(history) => history.latest(1)?.first().map(|sample| sample.rpm as f32),
+            None => None,
+        },
+    };
+
+    // when the level last changed, not just the page, as edits that leave
+    // the level alone don't count; looked up from the page history when the
+    // state doesn't say
+    let known = state
+        .as_deref_mut()
+        .and_then(|state| state.level_changed(&rev));
+    let changed = match known {
+        Some(changed) => Some(changed),
+        None if settings.min_dwell_mins > 0 && curr_level != level => {
+            let changed = level_changed_at(client, settings).await?;
+            if let (Some(state), Some(changed)) = (&mut state, changed) {
+                state.record_level_change(rev.level, changed);
+            }
+            changed
+        }
+        None => None,
+    };
+
+    // the level to record in the history: `level` if it was published, none
+    // if a publisher declined it
+    let published = async {
+        if dwelling(settings, changed, now, curr_level, level) {
+            info!(
+                "not moving from level {curr_level} to {level}: level changed less than {} \
+                 minutes ago",
+                settings.min_dwell_mins
+            );
+            return Ok(Some(curr_level));
+        }
+
+        if curr_level == level {
+            // No edit necessary
+            info!(level, "level unchanged, not going to edit");
+            return Ok(Some(curr_level));
+        }
+
+        if let Some(reason) = stand_down_reason(client, settings, &rev, now).await? {
+            info!("not moving from level {curr_level} to {level}: {reason}");
+            return Ok(Some(curr_level));
+        }
+        if !settings.dry_run && !writes_enabled(client, settings).await? {
+            return Ok(Some(curr_level));
+        }
+
+        let update = LevelUpdate {
+            previous_level: curr_level,
+            level,
+            rpm,
+            severity,
+            trend: Trend::between(previous_rpm, rpm, settings.trend_tolerance),
+            forecast,
+            concentration,
+            metrics,
+            time: now,
+            base_revid: rev.revid,
+        };
+        let published = publish_all(client, settings, publishers, &update).await;
+        if let Err(e) = &published {
+            if is_declined(e) {
+                info!("{e}");
+                return Ok(None);
+            }
+        }
+        if let Some(state) = &mut state {
+            // even if some publisher failed, the report page may have changed
+            state
+                .record_published(client, settings, level, rpm, now)
+                .await;
+        }
+        published?;
+        info!(from = curr_level, to = level, "published level change");
+        if !settings.dry_run {
+            monitor::record_change(monitor::LevelChange {
+                wiki: settings.wiki.clone(),
+                previous_level: curr_level,
+                level,
+                rpm: f64::from(rpm),
+                time: now,
+                link: index_url(settings, &settings.report_page),
+            });
+        }
+        Ok::<_, color_eyre::Report>(Some(level))
+    }
+    .await;
+
+    if let Some(history) = &history {
+        let recorded = match &published {
+            Ok(recorded) => *recorded,
+            Err(_) => Some(curr_level),
+        };
+        if let (Some(level), false) = (recorded, settings.dry_run) {
+            history.record(&Sample {
+                time: now.timestamp(),
+                rpm: f64::from(rpm),
+                level,
+                metrics: metrics.clone(),
+            })?;
+        }
+    }
+    published.map(|_| ())
+}
+
+/// Compute the current level and update the report page if it changed.
+#[tracing::instrument(name = "run", skip_all)]
+pub async fn run_once(
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+    publishers: &[Box<dyn Publisher>],
+) -> color_eyre::Result<()> {
+    rules.refresh(client).await;
+    let now = clock.now();
+    let ctx = Context::new(client, settings, rules.classifier(), now);
+    let started = std::time::Instant::now();
+    let metrics = collect_all(sources, &ctx).await?;
+    monitor::record_api_latency(&settings.wiki, started.elapsed().as_secs_f64());
+    info!("collected metrics: {metrics:?}");
+    let concentration = concentration::check(&ctx).await?;
+    update_level(
+        client,
+        settings,
+        &metrics,
+        concentration.as_ref(),
+        publishers,
+        now,
+    )
+    .await?;
+    update_hotspots(&ctx).await;
+    Ok(())
+}
+
+/// Update the `[hotspots]` page, if any. Failures are only logged, since
+/// the level is what matters.
+pub async fn update_hotspots(ctx: &Context<'_>) {
+    if let Some(hotspots) = &ctx.settings.hotspots {
+        if let Err(e) = hotspots::update(ctx, hotspots).await {
+            tracing::error!("could not update {}: {e:?}", hotspots.page);
+        }
+    }
+}
+
+/// Keep the client alive and recompute the level every `run_interval_mins`,
+/// running the other periodic jobs alongside, until a stop is requested (see
+/// `stop`).
+pub async fn run_daemon(
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+    publishers: &[Box<dyn Publisher>],
+) -> color_eyre::Result<()> {
+    systemd::ready();
+    let (_, res) = join(
+        run_jobs(client, settings, clock),
+        run_every_interval(client, settings, clock, rules, sources, publishers),
+    )
+    .await;
+    res
+}
+
+async fn run_every_interval(
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+    publishers: &[Box<dyn Publisher>],
+) -> color_eyre::Result<()> {
+    let mut timer = Timer::new(settings)?;
+    match &settings.schedule {
+        Some(schedule) => info!("running as a daemon on `{schedule}`"),
+        None => info!(
+            "running as a daemon every {} minutes",
+            settings.run_interval_mins
+        ),
+    }
+    let mut stops = stop::subscribe();
+    loop {
+        tokio::select! {
+            res = timer.tick(clock) => res?,
+            // only between runs, so that none is cut short
+            _ = stops.changed() => return Ok(()),
+        }
+        if let Some(next) = timer.next_after(clock.now()) {
+            monitor::record_next_run(&settings.wiki, next);
+        }
+        // a failed cycle shouldn't bring the whole daemon down
+        let res = run_once(client, settings, clock, rules, sources, publishers).await;
+        // even a failed run shows the daemon isn't stuck
+        systemd::watchdog();
+        monitor::record_run(&settings.wiki, res.is_ok());
+        if let Err(e) = res {
+            tracing::error!(
+                last_api_code = monitor::last_api_code(&settings.wiki).as_deref(),
+                "run failed: {e:?}"
+            );
+        }
+    }
+}
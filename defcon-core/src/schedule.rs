@@ -0,0 +1,274 @@
+//! Cron expressions for `schedule`, which runs the daemon at fixed times of
+//! the clock instead of every `run_interval_mins` from when it started: e.g.
+//! `*/5 * * * *` runs on the minutes divisible by 5, and another profile
+//! with `2-59/5 * * * *` two minutes later.
+//!
+//! The five fields are the minute, hour, day of the month, month and day of
+//! the week (0 or 7 for Sunday), all in UTC and as numbers. Each one is `*`
+//! or a comma-separated list of values and `a-b` ranges, any of which can
+//! have a `/step`. As in cron, a day matches either day field when both are
+//! restricted.
+//!
+//! Either way, every run can start up to `jitter_secs` late, at random.
+
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::{bail, eyre, WrapErr};
+
+use crate::clock::Clock;
+use crate::settings::Settings;
+
+/// How far ahead the next time is looked for, past which an expression like
+/// `0 0 30 2 *` is taken to never match.
+const MAX_YEARS_AHEAD: i64 = 5;
+
+/// One field of an expression: whether each value in its range matches, and
+/// whether it starts with `*`.
+struct Field {
+    matches: Vec<bool>,
+    any: bool,
+}
+
+impl Field {
+    fn parse(name: &str, field: &str, min: u32, max: u32) -> color_eyre::Result<Self> {
+        let mut matches = vec![false; max as usize + 1];
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step
+                        .parse()
+                        .wrap_err_with(|| format!("invalid step `{step}` in the {name}"))?;
+                    if step == 0 {
+                        bail!("the step in the {name} must be at least 1");
+                    }
+                    (range, step)
+                }
+                None => (item, 1),
+            };
+            let value = |value: &str| -> color_eyre::Result<u32> {
+                let value: u32 = value
+                    .parse()
+                    .wrap_err_with(|| format!("invalid value `{value}` in the {name}"))?;
+                if !(min..=max).contains(&value) {
+                    bail!("the {name} must be between {min} and {max}, found {value}");
+                }
+                Ok(value)
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // like cron, `a/n` goes on to the end of the range
+                    None if item.contains('/') => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                },
+            };
+            if start > end {
+                bail!("the range `{range}` in the {name} is backwards");
+            }
+            for value in (start..=end).step_by(step as usize) {
+                matches[value as usize] = true;
+            }
+        }
+        Ok(Field {
+            matches,
+            // as in cron, `*/2` counts as unrestricted too
+            any: field.starts_with('*'),
+        })
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.matches.get(value as usize).copied().unwrap_or(false)
+    }
+}
+
+/// A parsed cron expression.
+pub struct Schedule {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> color_eyre::Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            bail!(
+                "`{expression}` must have 5 fields (minute, hour, day, month, weekday), \
+                 found {}",
+                fields.len()
+            );
+        };
+        let mut weekdays = Field::parse("day of the week", weekdays, 0, 7)?;
+        // 7 is Sunday as well
+        weekdays.matches[0] |= weekdays.matches[7];
+        Ok(Schedule {
+            minutes: Field::parse("minute", minutes, 0, 59)?,
+            hours: Field::parse("hour", hours, 0, 23)?,
+            days: Field::parse("day of the month", days, 1, 31)?,
+            months: Field::parse("month", months, 1, 12)?,
+            weekdays,
+        })
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days.contains(date.day());
+        let weekday = self
+            .weekdays
+            .contains(date.weekday().num_days_from_sunday());
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first time after `time` that matches, if any does in the next few
+    /// years.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let limit = start + Duration::days(366 * MAX_YEARS_AHEAD);
+        let mut time = start;
+        while time < limit {
+            let date = time.date_naive();
+            if !self.months.contains(time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = Utc.from_utc_datetime(
+                    &NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?,
+                );
+            } else if !self.day_matches(date) {
+                time = Utc.from_utc_datetime(&date.succ_opt()?.and_hms_opt(0, 0, 0)?);
+            } else if !self.hours.contains(time.hour()) {
+                time = time.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+            } else if !self.minutes.contains(time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    /// The shortest time between two consecutive times that match, going by
+    /// the `MAX_YEARS_AHEAD` years from the start of a leap year, or `None`
+    /// if fewer than two of them do.
+    pub fn min_gap(&self) -> Option<Duration> {
+        // no two times are closer than the closest minutes of an hour
+        let minutes: Vec<i64> = (0..60)
+            .filter(|&minute| self.minutes.contains(minute))
+            .map(i64::from)
+            .collect();
+        let wrap = match (minutes.first(), minutes.last()) {
+            (Some(first), Some(last)) => first + 60 - last,
+            _ => return None,
+        };
+        let floor = minutes
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .fold(wrap, i64::min);
+        let floor = Duration::minutes(floor);
+
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).single()?;
+        let end = start + Duration::days(366 * MAX_YEARS_AHEAD);
+        let mut last = self.next_after(start - Duration::minutes(1))?;
+        let mut min: Option<Duration> = None;
+        while last < end {
+            let next = match self.next_after(last) {
+                Some(next) => next,
+                None => break,
+            };
+            let gap = next - last;
+            min = Some(min.map_or(gap, |min| min.min(gap)));
+            if gap <= floor {
+                break;
+            }
+            last = next;
+        }
+        min
+    }
+}
+
+/// A random delay of up to `max_secs` seconds, added to the start of each
+/// run so that profiles and instances on the same cadence don't all hit the
+/// API at once.
+pub(crate) fn jitter(max_secs: u64) -> std::time::Duration {
+    if max_secs == 0 {
+        return std::time::Duration::ZERO;
+    }
+    // every `RandomState` is keyed differently, which is random enough here
+    let random = RandomState::new().build_hasher().finish();
+    std::time::Duration::from_millis(random % (max_secs * 1000 + 1))
+}
+
+enum When {
+    Interval(tokio::time::Interval, Duration),
+    Cron {
+        schedule: Schedule,
+        /// The time of the last run, so that none runs twice.
+        last: Option<DateTime<Utc>>,
+    },
+}
+
+/// When the daemon runs: every `run_interval_mins`, or at the times of the
+/// `schedule`, plus up to `jitter_secs`.
+pub(crate) struct Timer {
+    when: When,
+    jitter_secs: u64,
+}
+
+impl Timer {
+    pub(crate) fn new(settings: &Settings) -> color_eyre::Result<Self> {
+        let when = match &settings.schedule {
+            Some(expression) => When::Cron {
+                schedule: Schedule::parse(expression)?,
+                last: None,
+            },
+            None => {
+                let period = std::time::Duration::from_secs(settings.run_interval_mins.max(1) * 60);
+                let mut interval = tokio::time::interval(period);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                When::Interval(interval, Duration::from_std(period)?)
+            }
+        };
+        Ok(Timer {
+            when,
+            jitter_secs: settings.jitter_secs,
+        })
+    }
+
+    /// The next time after the run at `now` that `self` would run at, leaving
+    /// out the jitter.
+    pub(crate) fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.when {
+            When::Interval(_, period) => Some(now + *period),
+            When::Cron { schedule, last } => {
+                schedule.next_after(last.map_or(now, |last| last.max(now)))
+            }
+        }
+    }
+
+    /// Wait until it's time for the next run.
+    pub(crate) async fn tick(&mut self, clock: &dyn Clock) -> color_eyre::Result<()> {
+        match &mut self.when {
+            When::Interval(interval, _) => {
+                interval.tick().await;
+            }
+            When::Cron { schedule, last } => {
+                let now = clock.now();
+                let next = schedule
+                    .next_after(last.map_or(now, |last| last.max(now)))
+                    .ok_or_else(|| eyre!("`schedule` doesn't match any time"))?;
+                let wait = (next - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                *last = Some(next);
+            }
+        }
+        tokio::time::sleep(jitter(self.jitter_secs)).await;
+        Ok(())
+    }
+}
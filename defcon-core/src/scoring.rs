@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use color_eyre::eyre::{bail, eyre};
+
+use crate::level::{next_level, severity};
+use crate::metrics::Metrics;
+use crate::policy;
+use crate::settings::Settings;
+
+static DEFAULT_SCORE_THRESHOLDS: [f32; 4] = [0.2, 0.4, 0.6, 0.8];
+
+/// How much a metric counts towards the combined score.
+#[derive(serde::Deserialize)]
+pub struct MetricWeight {
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Value of the metric that counts as fully saturated; the metric is
+    /// divided by this and capped at 1.
+    pub scale: f64,
+}
+
+/// Settings of the `[scoring]` table. With it, the level is computed from a
+/// weighted combination of the metrics instead of the RPM alone.
+#[derive(serde::Deserialize)]
+pub struct ScoringSettings {
+    pub metrics: BTreeMap<String, MetricWeight>,
+    /// Upper score bounds of every level but the most severe one.
+    #[serde(default = "default_score_thresholds")]
+    pub thresholds: Vec<f32>,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_score_thresholds() -> Vec<f32> {
+    DEFAULT_SCORE_THRESHOLDS.to_vec()
+}
+
+impl ScoringSettings {
+    pub fn validate(&self, available: &[String]) -> color_eyre::Result<()> {
+        if self.metrics.is_empty() {
+            bail!("`scoring.metrics` must weight at least one metric");
+        }
+        for (name, weight) in &self.metrics {
+            if !available.contains(name) {
+                bail!("`scoring.metrics.{name}` is weighted but not listed in `metrics`");
+            }
+            if !weight.weight.is_finite() || weight.weight < 0.0 {
+                bail!(
+                    "`scoring.metrics.{name}.weight` must be a non-negative number, found {}",
+                    weight.weight
+                );
+            }
+            if !weight.scale.is_finite() || weight.scale <= 0.0 {
+                bail!(
+                    "`scoring.metrics.{name}.scale` must be a positive number, found {}",
+                    weight.scale
+                );
+            }
+        }
+        if self.metrics.values().map(|w| w.weight).sum::<f64>() <= 0.0 {
+            bail!("`scoring.metrics` weights must not all be zero");
+        }
+        Ok(())
+    }
+
+    /// Combine `metrics` into a score between 0 and 1.
+    pub fn score(&self, metrics: &Metrics) -> color_eyre::Result<Score> {
+        let total_weight: f64 = self.metrics.values().map(|w| w.weight).sum();
+        let mut contributions = BTreeMap::new();
+        for (name, weight) in &self.metrics {
+            let value = metrics
+                .get(name)
+                .ok_or_else(|| eyre!("the {name} metric was not collected"))?;
+            let normalized = (value / weight.scale).clamp(0.0, 1.0);
+            contributions.insert(name.clone(), normalized * weight.weight / total_weight);
+        }
+        Ok(Score {
+            total: contributions.values().sum(),
+            contributions,
+        })
+    }
+}
+
+/// A combined score and how much each metric added to it.
+pub struct Score {
+    pub total: f64,
+    pub contributions: BTreeMap<String, f64>,
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}", self.total)?;
+        let mut sep = " (";
+        for (name, contribution) in &self.contributions {
+            write!(f, "{sep}{name} {contribution:.3}")?;
+            sep = ", ";
+        }
+        if !self.contributions.is_empty() {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+/// What the level is computed from: the combined score and its thresholds
+/// with `[scoring]`, `level_metric` and `thresholds` otherwise.
+pub struct Signal<'s> {
+    pub value: f32,
+    pub thresholds: &'s [f32],
+    pub score: Option<Score>,
+}
+
+impl<'s> Signal<'s> {
+    /// The level to move to from `curr_level`: what `level_expression`
+    /// returns, or where `value` falls among the thresholds.
+    pub fn level(
+        &self,
+        settings: &Settings,
+        metrics: &Metrics,
+        curr_level: u8,
+    ) -> color_eyre::Result<u8> {
+        match &settings.level_expression {
+            Some(expression) => policy::evaluate(expression, &settings.levels, metrics, curr_level),
+            None => Ok(next_level(
+                curr_level,
+                self.value,
+                self.thresholds,
+                &settings.levels,
+                settings.hysteresis_margin,
+            )),
+        }
+    }
+
+    /// `value` on a 0–100 scale; see `level::severity`.
+    pub fn severity(&self) -> f32 {
+        severity(self.value, self.thresholds)
+    }
+
+    pub fn new(settings: &'s Settings, metrics: &Metrics) -> color_eyre::Result<Self> {
+        match &settings.scoring {
+            Some(scoring) => {
+                let score = scoring.score(metrics)?;
+                Ok(Signal {
+                    value: score.total as f32,
+                    thresholds: &scoring.thresholds,
+                    score: Some(score),
+                })
+            }
+            None => {
+                let name = &settings.level_metric;
+                let value = metrics
+                    .get(name)
+                    .ok_or_else(|| eyre!("the {name} metric was not collected"))?;
+                Ok(Signal {
+                    value: *value as f32,
+                    thresholds: &settings.thresholds,
+                    score: None,
+                })
+            }
+        }
+    }
+}
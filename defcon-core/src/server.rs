@@ -0,0 +1,114 @@
+use color_eyre::eyre::WrapErr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::info;
+
+use crate::monitor;
+
+/// Settings of the `[server]` table, an HTTP server for monitoring that runs
+/// alongside `run --daemon` and `run --live`.
+#[derive(serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct ServerSettings {
+    /// Address to listen on, e.g. `127.0.0.1:9100`.
+    pub addr: String,
+    /// Serve Prometheus metrics on `/metrics`.
+    #[serde(default)]
+    pub metrics: bool,
+}
+
+/// The longest request head we bother reading.
+const MAX_REQUEST_LEN: usize = 8192;
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+fn route(settings: &ServerSettings, method: &str, path: &str) -> Response {
+    let not_found = Response {
+        status: "404 Not Found",
+        content_type: "text/plain",
+        body: "not found\n".to_owned(),
+    };
+    if method != "GET" {
+        return Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain",
+            body: "method not allowed\n".to_owned(),
+        };
+    }
+    match path {
+        "/healthz" => Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: monitor::health().to_string(),
+        },
+        "/status" => Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: monitor::status().to_string(),
+        },
+        "/feed.atom" => Response {
+            status: "200 OK",
+            content_type: "application/atom+xml",
+            body: monitor::atom_feed(),
+        },
+        "/metrics" if settings.metrics => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body: monitor::prometheus(),
+        },
+        _ => not_found,
+    }
+}
+
+async fn handle(settings: &ServerSettings, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_REQUEST_LEN {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let head = String::from_utf8_lossy(&buf);
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    // ignore any query string
+    let path = request_line
+        .next()
+        .unwrap_or("")
+        .split('?')
+        .next()
+        .unwrap_or("");
+    let response = route(settings, method, path);
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serve requests until the process exits.
+pub async fn serve(settings: ServerSettings) -> color_eyre::Result<()> {
+    let listener = TcpListener::bind(&settings.addr)
+        .await
+        .wrap_err_with(|| format!("could not listen on {}", settings.addr))?;
+    info!("listening on {}", settings.addr);
+    let settings = std::sync::Arc::new(settings);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let settings = settings.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(&settings, stream).await {
+                tracing::debug!("request failed: {e}");
+            }
+        });
+    }
+}
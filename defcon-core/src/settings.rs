@@ -0,0 +1,726 @@
+use std::path::PathBuf;
+
+use color_eyre::eyre::{bail, WrapErr};
+use serde_json::Value;
+
+use crate::baseline::BaselineSettings;
+use crate::chart::ChartSettings;
+use crate::classifier::{Detection, Keywords};
+use crate::concentration::ConcentrationSettings;
+use crate::email::EmailSettings;
+use crate::error::DefconError;
+use crate::hotspots::HotspotSettings;
+use crate::i18n::Messages;
+use crate::level::{Levels, DEFAULT_THRESHOLDS};
+use crate::metrics::aiv::default_aiv_page;
+use crate::metrics::blocks::default_block_reasons;
+use crate::metrics::liftwing::LiftWingSettings;
+use crate::metrics::NamespaceRate;
+use crate::monitor::SentrySettings;
+use crate::plugin::PluginConfig;
+use crate::policy;
+use crate::publish::PublisherConfig;
+use crate::report::{ReportFormat, ReportPreset};
+use crate::rpm::RevertWeights;
+use crate::schedule::Schedule;
+use crate::scoring::ScoringSettings;
+use crate::server::ServerSettings;
+use crate::smoothing::SmoothingSettings;
+use crate::spike::SpikeSettings;
+use crate::stats::StatsSettings;
+use crate::wiki::EditSettings;
+
+const DEFAULT_RUN_INTERVAL_MINS: u64 = 5;
+const EVENTSTREAMS_URL: &str = "https://stream.wikimedia.org/v2/stream/recentchange";
+
+#[derive(serde::Deserialize)]
+pub struct Settings {
+    pub oauth_token: String,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    pub report_page: String,
+    /// Summary of the edits publishing a level, to the report page or a JSON
+    /// or Lua page, with `{level}`, `{rpm}`, `{severity}` and `{trend}` filled
+    /// in; the `defcon-summary` message if unset.
+    pub summary_template: Option<String>,
+    /// Language of the summaries and info text the bot writes.
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Directory of `<language>.json` message files overriding the built-in
+    /// ones.
+    pub messages_dir: Option<PathBuf>,
+    /// The messages of `language`, loaded along with the settings.
+    #[serde(skip)]
+    pub messages: Messages,
+    /// Built-in format the report page is written in.
+    #[serde(default)]
+    pub report_preset: ReportPreset,
+    /// Tera template file the report page is written from, instead of
+    /// `report_preset`.
+    pub report_template: Option<PathBuf>,
+    /// `report_template` or `report_preset`, loaded along with the settings.
+    #[serde(skip)]
+    pub report_format: ReportFormat,
+    /// The profile's settings as they were loaded, to tell whether a reload
+    /// changed them.
+    #[serde(skip)]
+    pub fingerprint: String,
+    /// How often to recompute the level when running with `--daemon` or `--live`.
+    #[serde(default = "default_run_interval_mins")]
+    pub run_interval_mins: u64,
+    /// Cron expression the `--daemon` runs at instead of every
+    /// `run_interval_mins`, see `schedule`.
+    pub schedule: Option<String>,
+    /// Upper bound on a random delay in seconds added to the start of every
+    /// run, so that profiles and instances on the same cadence don't query
+    /// the API at the same moment.
+    #[serde(default)]
+    pub jitter_secs: u64,
+    /// Database name of the wiki, used to pick our events out of EventStreams.
+    #[serde(default = "default_wiki")]
+    pub wiki: String,
+    #[serde(default = "default_eventstreams_url")]
+    pub eventstreams_url: String,
+    #[serde(default)]
+    pub detection: Detection,
+    #[serde(default)]
+    pub keywords: Keywords,
+    /// JSON page on the wiki to load the keyword lists from.
+    pub rules_page: Option<String>,
+    /// Comma-separated namespace numbers whose reverts are counted, or empty
+    /// for all namespaces.
+    #[serde(default = "default_namespaces")]
+    pub namespaces: String,
+    /// Leave edits flagged as bot edits out of the revert count.
+    #[serde(default = "default_exclude_bots")]
+    pub exclude_bots: bool,
+    /// Leave reverts of the reverting user's own edits out of the revert
+    /// count, as they aren't responses to vandalism.
+    #[serde(default = "default_exclude_self_reverts")]
+    pub exclude_self_reverts: bool,
+    /// Users whose reverts aren't counted, e.g. unflagged anti-vandalism bots.
+    #[serde(default)]
+    pub excluded_users: Vec<String>,
+    /// Minutes of recent changes (and abuse log entries and blocks) the
+    /// metrics are computed over, independently of `run_interval_mins`, but
+    /// at least the time between runs.
+    #[serde(default = "default_window_mins")]
+    pub window_mins: u64,
+    /// Length of the buckets the window is kept in; a divisor of
+    /// `window_mins`. The window is `window_mins` of full buckets plus the
+    /// one still filling up.
+    #[serde(default = "default_bucket_mins")]
+    pub bucket_mins: u64,
+    /// JSON file the recent changes of the window are cached in, so that
+    /// runs only fetch the changes made since the previous one. Each profile
+    /// needs its own.
+    pub rc_cache_file: Option<PathBuf>,
+    /// Reverts counted per page and interval at most; unlimited if unset.
+    pub max_reverts_per_page: Option<usize>,
+    /// How much reverts count by how they were made.
+    #[serde(default)]
+    pub revert_weights: RevertWeights,
+    /// The metric compared against `thresholds`, unless `scoring` is set.
+    #[serde(default = "default_level_metric")]
+    pub level_metric: String,
+    /// The levels, from the calmest to the most severe.
+    #[serde(default)]
+    pub levels: Levels,
+    /// Upper bounds of `level_metric` for every level in `levels` but the
+    /// most severe one.
+    #[serde(default = "default_thresholds")]
+    pub thresholds: Vec<f32>,
+    /// Lua computing the level from the metrics, instead of comparing
+    /// `level_metric` or the score to thresholds; see `policy`.
+    pub level_expression: Option<String>,
+    /// How far past a threshold (in units of `level_metric`, or score with
+    /// `scoring`) the rate must be to change level.
+    #[serde(default)]
+    pub hysteresis_margin: f32,
+    /// Minutes after a level change during which only jumps of two or more
+    /// levels are published.
+    #[serde(default)]
+    pub min_dwell_mins: u64,
+    /// Minutes after someone else edits the report page during which their
+    /// level is left alone.
+    #[serde(default = "default_override_mins")]
+    pub override_mins: u64,
+    /// Other bots updating the report page; we skip a run when one of them
+    /// edited it within `run_interval_mins`.
+    #[serde(default)]
+    pub cooperating_bots: Vec<String>,
+    /// Attempts at an API request failing with a timeout, connection error or
+    /// server error before giving up. POSTs are only retried when they
+    /// couldn't connect.
+    #[serde(default = "default_api_attempts")]
+    pub api_attempts: u32,
+    /// Times an edit of the report page is retried after an edit conflict.
+    #[serde(default = "default_edit_conflict_retries")]
+    pub edit_conflict_retries: u32,
+    /// Relative RPM change from the last published one (or the last run's)
+    /// below which the trend is `stable`.
+    #[serde(default = "default_trend_tolerance")]
+    pub trend_tolerance: f32,
+    /// JSON file keeping the last published level and RPM and the report
+    /// page as last read between runs. Each profile needs its own.
+    pub state_file: Option<PathBuf>,
+    /// File locked for as long as a `run` goes on, so that a run that
+    /// overruns and the next one can't both edit. Profiles sharing it take
+    /// it once.
+    pub lock_file: Option<PathBuf>,
+    /// SQLite database every sample is recorded to.
+    pub database: Option<PathBuf>,
+    /// Add a one-hour-ahead RPM forecast from `database` to the report.
+    #[serde(default)]
+    pub forecast: bool,
+    /// Pages purged after every edit of `report_page`.
+    #[serde(default)]
+    pub purge_pages: Vec<String>,
+    /// Also purge every page transcluding `report_page`.
+    #[serde(default)]
+    pub purge_transclusions: bool,
+    /// Create `report_page` if it doesn't exist.
+    #[serde(default)]
+    pub create_report_page: bool,
+    /// Page that has to say `enabled` for the bot to edit anything.
+    pub shutoff_page: Option<String>,
+    /// Do everything except saving the edit, printing what would be saved.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Metrics to collect on every run.
+    #[serde(default = "default_metrics")]
+    pub metrics: Vec<String>,
+    /// WASM modules supplying metrics or classifying edits.
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// N-gram model file classifying edit summaries with
+    /// `detection = "model"`.
+    pub model: Option<PathBuf>,
+    /// Where level changes are published.
+    #[serde(default = "default_publishers")]
+    pub publishers: Vec<PublisherConfig>,
+    /// Block reasons counted by the `blocks` metric, matched as
+    /// case-insensitive substrings.
+    #[serde(default = "default_block_reasons")]
+    pub block_reasons: Vec<String>,
+    /// Page whose open reports the `aiv` metric counts.
+    #[serde(default = "default_aiv_page")]
+    pub aiv_page: String,
+    /// Flags and change tags every edit is made with.
+    #[serde(default)]
+    pub edit: EditSettings,
+    /// Settings of the `damaging` and `revertrisk` metrics.
+    #[serde(default)]
+    pub liftwing: LiftWingSettings,
+    /// Keep hour-of-week baselines of the RPM, adding the `relative` metric.
+    pub baseline: Option<BaselineSettings>,
+    /// Keep a moving average of the RPM, adding the `smoothed` metric.
+    pub smoothing: Option<SmoothingSettings>,
+    /// Monitoring HTTP server; the same for every profile, since there's only
+    /// one per process.
+    pub server: Option<ServerSettings>,
+    /// Sentry error reporting; the same for every profile, too.
+    pub sentry: Option<SentrySettings>,
+    /// Draw and upload an RPM chart from `database`.
+    pub chart: Option<ChartSettings>,
+    /// Post weekly statistics from `database`.
+    pub stats: Option<StatsSettings>,
+    /// List the pages with the most reverts after every run.
+    pub hotspots: Option<HotspotSettings>,
+    /// Alert when the RPM suddenly jumps.
+    pub spikes: Option<SpikeSettings>,
+    /// Notice when most reverts are against one account or IP range.
+    pub concentration: Option<ConcentrationSettings>,
+    /// Email the operator when the level stays high.
+    pub email: Option<EmailSettings>,
+    /// Base the level on a weighted score of all metrics instead of `level_metric`.
+    pub scoring: Option<ScoringSettings>,
+}
+
+fn validate_thresholds(key: &str, thresholds: &[f32], levels: &Levels) -> color_eyre::Result<()> {
+    if thresholds.len() + 1 != levels.len() {
+        bail!(
+            "`{key}` must have {} entries (one fewer than `levels`), found {}",
+            levels.len() - 1,
+            thresholds.len()
+        );
+    }
+    if let Some(t) = thresholds.iter().find(|t| !t.is_finite() || **t < 0.0) {
+        bail!("`{key}` must be non-negative numbers, found {t}");
+    }
+    if let Some(pair) = thresholds.windows(2).find(|pair| pair[0] >= pair[1]) {
+        bail!(
+            "`{key}` must be strictly increasing, but {} is followed by {}",
+            pair[0],
+            pair[1]
+        );
+    }
+    Ok(())
+}
+
+impl Settings {
+    /// Names of the metrics available to the level: the collected ones and
+    /// the ones derived from them.
+    pub fn available_metrics(&self) -> Vec<String> {
+        let mut metrics = self.metrics.clone();
+        for plugin in &self.plugins {
+            if let PluginConfig::Metric { name, .. } = plugin {
+                metrics.push(name.clone());
+            }
+        }
+        if self.baseline.is_some() {
+            metrics.push("relative".to_owned());
+        }
+        if self.smoothing.is_some() {
+            metrics.push("smoothed".to_owned());
+        }
+        metrics
+    }
+
+    /// The namespace numbers in `namespaces`; empty means all of them.
+    pub fn namespace_ids(&self) -> color_eyre::Result<Vec<i64>> {
+        self.namespaces
+            .split(',')
+            .map(str::trim)
+            .filter(|ns| !ns.is_empty())
+            .map(|ns| {
+                ns.parse().wrap_err_with(|| {
+                    format!("`namespaces` must be namespace numbers, found `{ns}`")
+                })
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> color_eyre::Result<()> {
+        if self.levels.len() < 2 {
+            bail!("`levels` must have at least two levels");
+        }
+        for (rank, level) in self.levels.iter().enumerate() {
+            if level == 0 {
+                bail!("`levels` can't contain 0, which stands for no level");
+            }
+            if self.levels.rank(level) != Some(rank) {
+                bail!("`levels` contains {level} more than once");
+            }
+        }
+        validate_thresholds("thresholds", &self.thresholds, &self.levels)?;
+        let classifiers = self
+            .plugins
+            .iter()
+            .filter(|plugin| matches!(plugin, PluginConfig::Classifier { .. }))
+            .count();
+        if classifiers > 1 {
+            bail!("only one classifier plugin can be used at a time");
+        }
+        if (self.detection == Detection::Plugin) != (classifiers == 1) {
+            bail!("`detection = \"plugin\"` and a classifier plugin must be set together");
+        }
+        if (self.detection == Detection::Model) != self.model.is_some() {
+            bail!("`detection = \"model\"` and `model` must be set together");
+        }
+        for plugin in &self.plugins {
+            if let PluginConfig::Metric { name, .. } = plugin {
+                if self.metrics.contains(name) || ["relative", "smoothed"].contains(&name.as_str())
+                {
+                    bail!("plugin metric `{name}` clashes with a built-in metric");
+                }
+            }
+        }
+        for publisher in &self.publishers {
+            let max_level = match publisher {
+                PublisherConfig::Ntfy(ntfy) => ntfy.max_level,
+                PublisherConfig::Pushover(pushover) => pushover.max_level,
+                PublisherConfig::Mastodon(mastodon) => mastodon.max_level,
+                _ => continue,
+            };
+            if !self.levels.contains(max_level) {
+                bail!("`max_level` of a publisher must be one of `levels`, found {max_level}");
+            }
+        }
+        self.edit.validate()?;
+        self.revert_weights.validate()?;
+        self.namespace_ids()?;
+        if self.api_attempts == 0 {
+            bail!("`api_attempts` must be at least 1");
+        }
+        if self.window_mins < self.run_interval_mins.max(1) {
+            bail!(
+                "`window_mins` ({}) must be at least `run_interval_mins` ({}), or some changes \
+                 would never be counted",
+                self.window_mins,
+                self.run_interval_mins
+            );
+        }
+        // the time between two runs of `run --daemon`
+        let cadence = match &self.schedule {
+            Some(schedule) => Schedule::parse(schedule)
+                .wrap_err("invalid `schedule`")?
+                .min_gap()
+                .map(|gap| gap.num_seconds() as u64),
+            None => Some(self.run_interval_mins.max(1) * 60),
+        };
+        if let Some(cadence) = cadence {
+            if self.window_mins * 60 < cadence {
+                bail!(
+                    "`window_mins` ({}) must cover the {cadence} seconds between runs, or some \
+                     changes would never be counted",
+                    self.window_mins
+                );
+            }
+            if self.jitter_secs >= cadence {
+                bail!(
+                    "`jitter_secs` ({}) must be less than the {cadence} seconds between runs",
+                    self.jitter_secs
+                );
+            }
+        }
+        if self.bucket_mins == 0 || !self.window_mins.is_multiple_of(self.bucket_mins) {
+            bail!(
+                "`bucket_mins` must be a positive divisor of `window_mins` ({}), found {}",
+                self.window_mins,
+                self.bucket_mins
+            );
+        }
+        if self.max_reverts_per_page == Some(0) {
+            bail!("`max_reverts_per_page` must be at least 1");
+        }
+        if !self.hysteresis_margin.is_finite() || self.hysteresis_margin < 0.0 {
+            bail!(
+                "`hysteresis_margin` must be a non-negative number, found {}",
+                self.hysteresis_margin
+            );
+        }
+        if !self.metrics.iter().any(|name| name == "rpm") {
+            bail!("`metrics` must include `rpm`, which the report page shows");
+        }
+        let namespaces = self.namespace_ids()?;
+        for name in &self.metrics {
+            if let Some(source) = NamespaceRate::parse(name) {
+                if !namespaces.is_empty() && !namespaces.contains(&source.namespace()) {
+                    bail!(
+                        "`{name}` needs namespace {} in `namespaces`",
+                        source.namespace()
+                    );
+                }
+            }
+        }
+        let available = self.available_metrics();
+        if !available.contains(&self.level_metric) {
+            bail!(
+                "`level_metric` `{}` must be listed in `metrics`",
+                self.level_metric
+            );
+        }
+        if self.forecast && self.database.is_none() {
+            bail!("`forecast` needs a `database` to forecast from");
+        }
+        if self.chart.is_some() && self.database.is_none() {
+            bail!("`chart` needs a `database` to draw from");
+        }
+        if self.stats.is_some() && self.database.is_none() {
+            bail!("`stats` needs a `database` to summarize");
+        }
+        if !self.trend_tolerance.is_finite() || self.trend_tolerance < 0.0 {
+            bail!(
+                "`trend_tolerance` must be a non-negative number, found {}",
+                self.trend_tolerance
+            );
+        }
+        if let Some(baseline) = &self.baseline {
+            if !(baseline.alpha > 0.0 && baseline.alpha <= 1.0) {
+                bail!(
+                    "`baseline.alpha` must be in (0, 1], found {}",
+                    baseline.alpha
+                );
+            }
+        }
+        if let Some(smoothing) = &self.smoothing {
+            if !(smoothing.alpha > 0.0 && smoothing.alpha <= 1.0) {
+                bail!(
+                    "`smoothing.alpha` must be in (0, 1], found {}",
+                    smoothing.alpha
+                );
+            }
+        }
+        if let Some(spikes) = &self.spikes {
+            if spikes.samples < 3 {
+                bail!(
+                    "`spikes.samples` must be at least 3, found {}",
+                    spikes.samples
+                );
+            }
+            if !spikes.sigma.is_finite() || spikes.sigma <= 0.0 {
+                bail!(
+                    "`spikes.sigma` must be a positive number, found {}",
+                    spikes.sigma
+                );
+            }
+        }
+        if let Some(concentration) = &self.concentration {
+            if !(concentration.min_share > 0.0 && concentration.min_share <= 1.0) {
+                bail!(
+                    "`concentration.min_share` must be in (0, 1], found {}",
+                    concentration.min_share
+                );
+            }
+            if concentration.ipv4_prefix > 32 || concentration.ipv6_prefix > 128 {
+                bail!("`concentration.ipv4_prefix` and `ipv6_prefix` must be at most 32 and 128");
+            }
+            if (concentration.noticeboard.is_some() || concentration.webhook.is_some())
+                && concentration.file.is_none()
+            {
+                bail!("`concentration.file` is needed to alert");
+            }
+        }
+        if let Some(email) = &self.email {
+            if !self.levels.contains(email.level) {
+                bail!(
+                    "`email.level` must be one of `levels`, found {}",
+                    email.level
+                );
+            }
+            if email.to.is_empty() {
+                bail!("`email.to` must list at least one address");
+            }
+            if email.username.is_some() != email.password.is_some() {
+                bail!("`email.username` and `email.password` must be set together");
+            }
+        }
+        if let Some(scoring) = &self.scoring {
+            validate_thresholds("scoring.thresholds", &scoring.thresholds, &self.levels)?;
+            scoring.validate(&available)?;
+        }
+        if let Some(expression) = &self.level_expression {
+            policy::validate(expression)?;
+        }
+        Ok(())
+    }
+
+    /// Check that the level on `report_page` can be read back: some `wiki`
+    /// publisher writes it in a format showing the level, or the
+    /// `state_file` remembers what was published. Otherwise every run would
+    /// find no level and publish again.
+    fn validate_report(&self) -> color_eyre::Result<()> {
+        if self.state_file.is_some() {
+            return Ok(());
+        }
+        let readable = self.publishers.iter().any(|publisher| match publisher {
+            PublisherConfig::Wiki {
+                page,
+                preset,
+                template,
+            } if page.as_deref().is_none_or(|page| page == self.report_page) => {
+                match (preset, template) {
+                    (Some(preset), _) => ReportFormat::from(*preset).reads_back(&self.levels),
+                    (None, Some(template)) => ReportFormat::load(template)
+                        .is_ok_and(|format| format.reads_back(&self.levels)),
+                    (None, None) => self.report_format.reads_back(&self.levels),
+                }
+            }
+            _ => false,
+        });
+        if !readable {
+            bail!(
+                "no `wiki` publisher writes `report_page` showing the level as `level = <n>` \
+                 or a bare number, so it couldn't be read back; add one or set `state_file`"
+            );
+        }
+        Ok(())
+    }
+
+    /// Check that the settings can be followed live, with `run --live`.
+    /// EventStreams events carry no change tags, so detecting reverts by
+    /// their tags alone would count none of them, and the level is updated
+    /// every `run_interval_mins`, which `jitter_secs` has to fit in.
+    pub fn validate_live(&self) -> color_eyre::Result<()> {
+        if self.detection == Detection::Tags {
+            bail!(
+                "`detection = \"tags\"` can't be used with `run --live`, as the event stream \
+                 doesn't carry change tags"
+            );
+        }
+        if self.jitter_secs >= self.run_interval_mins.max(1) * 60 {
+            bail!(
+                "`jitter_secs` ({}) must be less than `run_interval_mins` ({}) in seconds",
+                self.jitter_secs,
+                self.run_interval_mins
+            );
+        }
+        Ok(())
+    }
+}
+
+fn default_publishers() -> Vec<PublisherConfig> {
+    vec![PublisherConfig::Wiki {
+        page: None,
+        preset: None,
+        template: None,
+    }]
+}
+
+fn default_namespaces() -> String {
+    "0".to_owned()
+}
+
+fn default_exclude_bots() -> bool {
+    true
+}
+
+fn default_exclude_self_reverts() -> bool {
+    true
+}
+
+fn default_api_attempts() -> u32 {
+    4
+}
+
+fn default_edit_conflict_retries() -> u32 {
+    3
+}
+
+fn default_window_mins() -> u64 {
+    60
+}
+
+fn default_bucket_mins() -> u64 {
+    5
+}
+
+fn default_override_mins() -> u64 {
+    60
+}
+
+fn default_trend_tolerance() -> f32 {
+    0.1
+}
+
+fn default_level_metric() -> String {
+    "rpm".to_owned()
+}
+
+fn default_metrics() -> Vec<String> {
+    vec!["rpm".to_owned()]
+}
+
+fn default_thresholds() -> Vec<f32> {
+    DEFAULT_THRESHOLDS.to_vec()
+}
+
+fn default_language() -> String {
+    "en".to_owned()
+}
+
+fn default_api_url() -> String {
+    "https://en.wikipedia.org/w/api.php".to_owned()
+}
+
+fn default_run_interval_mins() -> u64 {
+    DEFAULT_RUN_INTERVAL_MINS
+}
+
+fn default_wiki() -> String {
+    "enwiki".to_owned()
+}
+
+fn default_eventstreams_url() -> String {
+    EVENTSTREAMS_URL.to_owned()
+}
+
+/// Deep-merge `overlay` into `base`. Tables are merged key by key, anything
+/// else (including arrays) is replaced.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Split the settings into named profiles. Each `[profiles.<name>]` table
+/// overrides the top-level settings for that profile; without any profiles
+/// the top-level settings are used as the single `default` profile.
+pub fn load_profiles(config: config::Config) -> color_eyre::Result<Vec<(String, Settings)>> {
+    let mut root: Value = config.try_deserialize()?;
+    let profiles = match root
+        .as_object_mut()
+        .and_then(|root| root.remove("profiles"))
+    {
+        None => vec![("default".to_owned(), root)],
+        Some(Value::Object(profiles)) => profiles
+            .into_iter()
+            .map(|(name, overlay)| {
+                let mut settings = root.clone();
+                merge(&mut settings, overlay);
+                (name, settings)
+            })
+            .collect(),
+        Some(_) => {
+            return Err(DefconError::Config(
+                "`profiles` must be a table of named profiles".to_owned(),
+            )
+            .into())
+        }
+    };
+    profiles
+        .into_iter()
+        .map(|(name, settings)| {
+            let invalid = |e: color_eyre::Report| {
+                DefconError::Config(format!("invalid settings for profile `{name}`: {e:#}"))
+            };
+            let fingerprint = settings.to_string();
+            let mut settings: Settings =
+                serde_json::from_value(settings).map_err(|e| invalid(e.into()))?;
+            settings.fingerprint = fingerprint;
+            settings.validate().map_err(invalid)?;
+            settings.messages =
+                Messages::load(&settings.language, settings.messages_dir.as_deref())
+                    .map_err(invalid)?;
+            settings.report_format = match &settings.report_template {
+                Some(path) => ReportFormat::load(path).map_err(invalid)?,
+                None => settings.report_preset.into(),
+            };
+            settings.validate_report().map_err(invalid)?;
+            Ok((name, settings))
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()
+        .and_then(|profiles| {
+            validate_profiles(&profiles)?;
+            Ok(profiles)
+        })
+}
+
+/// Reject files that profiles would overwrite each other's contents of.
+fn validate_profiles(profiles: &[(String, Settings)]) -> color_eyre::Result<()> {
+    for (i, (name, settings)) in profiles.iter().enumerate() {
+        for (other, other_settings) in &profiles[..i] {
+            let files = [
+                (
+                    "state_file",
+                    &settings.state_file,
+                    &other_settings.state_file,
+                ),
+                (
+                    "rc_cache_file",
+                    &settings.rc_cache_file,
+                    &other_settings.rc_cache_file,
+                ),
+            ];
+            for (key, path, other_path) in files {
+                if let (Some(path), Some(other_path)) = (path, other_path) {
+                    if path == other_path {
+                        return Err(DefconError::Config(format!(
+                            "profiles `{other}` and `{name}` share the `{key}` {}; give each \
+                             its own",
+                            path.display()
+                        ))
+                        .into());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
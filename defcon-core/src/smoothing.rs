@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::WrapErr;
+use tracing::info;
+
+use crate::metrics::Metrics;
+
+/// Settings of the `[smoothing]` table. With it, every run adds a `smoothed`
+/// metric: an exponentially weighted moving average of the RPM, which damps
+/// single-minute noise without averaging over a whole hour.
+#[derive(serde::Deserialize)]
+pub struct SmoothingSettings {
+    /// JSON file the average is kept in between runs.
+    pub file: PathBuf,
+    /// Weight of a new sample in the average; 1 is no smoothing at all.
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+}
+
+fn default_alpha() -> f64 {
+    0.3
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct SmoothingState {
+    average: Option<f64>,
+}
+
+impl SmoothingState {
+    fn load(path: &Path) -> color_eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("invalid smoothing state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SmoothingState::default()),
+            Err(e) => Err(e)
+                .wrap_err_with(|| format!("could not read smoothing state {}", path.display())),
+        }
+    }
+
+    fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write smoothing state {}", path.display()))
+    }
+}
+
+/// Add the `smoothed` metric to `metrics`, and with `save`, keep it as the
+/// average the next run starts from.
+pub fn apply(
+    settings: &SmoothingSettings,
+    metrics: &mut Metrics,
+    save: bool,
+) -> color_eyre::Result<()> {
+    let rpm = metrics.get("rpm").copied().unwrap_or(0.0);
+    let mut state = SmoothingState::load(&settings.file)?;
+    let smoothed = state
+        .average
+        .map_or(rpm, |average| average + settings.alpha * (rpm - average));
+    info!("smoothed RPM {smoothed:.2}");
+    metrics.insert("smoothed".to_owned(), smoothed);
+    if save {
+        state.average = Some(smoothed);
+        state.save(&settings.file)?;
+    }
+    Ok(())
+}
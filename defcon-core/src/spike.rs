@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use tracing::{info, warn};
+
+use crate::settings::Settings;
+use crate::wiki::{add_section, writes_enabled};
+
+/// A flat history has no spread at all, which would make the smallest bump
+/// look like a spike.
+const MIN_STDDEV: f64 = 0.5;
+
+/// Settings of the `[spikes]` table, which raises an alert when the RPM jumps
+/// well above its recent mean.
+#[derive(serde::Deserialize)]
+pub struct SpikeSettings {
+    /// JSON file the recent samples are kept in.
+    pub file: PathBuf,
+    /// Number of past samples the mean and spread are taken over.
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+    /// Standard deviations above the mean from which the RPM is a spike.
+    #[serde(default = "default_sigma")]
+    pub sigma: f64,
+    /// RPM below which nothing is a spike.
+    #[serde(default = "default_min_rpm")]
+    pub min_rpm: f64,
+    /// Minutes after an alert during which no further alerts are sent.
+    #[serde(default = "default_cooldown_mins")]
+    pub cooldown_mins: i64,
+    /// Page to add a new section to.
+    pub noticeboard: Option<String>,
+    /// URL to POST `{"text": ...}` to.
+    pub webhook: Option<String>,
+}
+
+fn default_samples() -> usize {
+    12
+}
+
+fn default_sigma() -> f64 {
+    3.0
+}
+
+fn default_min_rpm() -> f64 {
+    2.0
+}
+
+fn default_cooldown_mins() -> i64 {
+    60
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct SpikeState {
+    samples: VecDeque<f64>,
+    /// Unix timestamp of the last alert.
+    last_alert: Option<i64>,
+}
+
+impl SpikeState {
+    fn load(path: &Path) -> color_eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("invalid spike state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SpikeState::default()),
+            Err(e) => {
+                Err(e).wrap_err_with(|| format!("could not read spike state {}", path.display()))
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write spike state {}", path.display()))
+    }
+}
+
+fn mean_and_stddev(samples: &VecDeque<f64>) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+/// Whether `rpm` is a spike compared to `samples`. Needs a few samples to go
+/// on before anything counts.
+fn is_spike(spikes: &SpikeSettings, samples: &VecDeque<f64>, rpm: f64) -> Option<f64> {
+    if samples.len() < 3 || rpm < spikes.min_rpm {
+        return None;
+    }
+    let (mean, stddev) = mean_and_stddev(samples);
+    (rpm > mean + spikes.sigma * stddev.max(MIN_STDDEV)).then(|| mean)
+}
+
+/// Record `rpm` and alert if it is a spike. Alerting failures are logged but
+/// don't fail the run, so that the level still gets updated.
+pub async fn detect(
+    client: &mw::Client,
+    settings: &Settings,
+    spikes: &SpikeSettings,
+    rpm: f64,
+    level: u8,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    let mut state = SpikeState::load(&spikes.file)?;
+    let spike = is_spike(spikes, &state.samples, rpm);
+    state.samples.push_back(rpm);
+    while state.samples.len() > spikes.samples {
+        state.samples.pop_front();
+    }
+
+    let cooling_down = state
+        .last_alert
+        .is_some_and(|last| now.timestamp() - last < spikes.cooldown_mins * 60);
+    if let Some(mean) = spike {
+        let message = format!(
+            "Revert rate spike on {}: {rpm:.2} RPM against a recent mean of {mean:.2} RPM \
+             (level {level})",
+            settings.wiki
+        );
+        if settings.dry_run {
+            println!("would alert: {message}");
+        } else if cooling_down {
+            info!("not alerting again so soon: {message}");
+        } else {
+            warn!("{message}");
+            send_alert(
+                client,
+                settings,
+                spikes.noticeboard.as_deref(),
+                spikes.webhook.as_deref(),
+                "Revert rate spike",
+                &settings.messages.get("defcon-summary-spike", &[]),
+                &message,
+            )
+            .await;
+            state.last_alert = Some(now.timestamp());
+        }
+    }
+
+    if !settings.dry_run {
+        state.save(&spikes.file)?;
+    }
+    Ok(())
+}
+
+/// Post `message` to the `noticeboard` under `heading` and to the `webhook`,
+/// whichever are given. Failures are only logged.
+pub(crate) async fn send_alert(
+    client: &mw::Client,
+    settings: &Settings,
+    noticeboard: Option<&str>,
+    webhook: Option<&str>,
+    heading: &str,
+    summary: &str,
+    message: &str,
+) {
+    if let Some(page) = noticeboard {
+        match writes_enabled(client, settings).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                tracing::error!("could not check the shutoff page: {e:?}");
+                return;
+            }
+        }
+        let text = format!("{message}. ~~~~");
+        if let Err(e) = add_section(client, page, heading, &text, summary).await {
+            tracing::error!("could not post the alert to {page}: {e:?}");
+        }
+    }
+    if let Some(url) = webhook {
+        let res = async {
+            reqwest::Client::builder()
+                .user_agent(user_agent!())
+                .build()?
+                .post(url)
+                .json(&serde_json::json!({ "text": message }))
+                .send()
+                .await?
+                .error_for_status()
+        }
+        .await;
+        if let Err(e) = res {
+            tracing::error!("could not send the alert to the webhook: {e}");
+        }
+    }
+}
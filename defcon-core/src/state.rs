@@ -0,0 +1,181 @@
+use std::path::Path;
+
+use chrono::prelude::*;
+use color_eyre::eyre::WrapErr;
+use tracing::warn;
+
+use crate::settings::Settings;
+use crate::wiki::{current_report, latest_revid, latest_revision, username, ReportRevision};
+
+/// The report page as last read, so that it only has to be read again once
+/// someone edits it.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SeenReport {
+    revid: u64,
+    /// Unix timestamp of the revision.
+    timestamp: Option<i64>,
+    user: String,
+    level: u8,
+    rpm: Option<f32>,
+    text: String,
+}
+
+impl From<&ReportRevision> for SeenReport {
+    fn from(rev: &ReportRevision) -> Self {
+        SeenReport {
+            revid: rev.revid,
+            timestamp: rev.timestamp.map(|ts| ts.timestamp()),
+            user: rev.user.clone(),
+            level: rev.level,
+            rpm: rev.rpm,
+            text: rev.text.clone(),
+        }
+    }
+}
+
+impl From<&SeenReport> for ReportRevision {
+    fn from(seen: &SeenReport) -> Self {
+        ReportRevision {
+            revid: seen.revid,
+            timestamp: seen
+                .timestamp
+                .and_then(|ts| Utc.timestamp_opt(ts, 0).single()),
+            user: seen.user.clone(),
+            level: seen.level,
+            rpm: seen.rpm,
+            text: seen.text.clone(),
+        }
+    }
+}
+
+/// The level we last published.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
+pub struct Published {
+    pub level: u8,
+    pub rpm: f32,
+    /// Unix timestamp of the run that published it.
+    pub time: i64,
+    /// The revision of the report page it made, if known.
+    #[serde(default)]
+    pub revid: Option<u64>,
+}
+
+/// The level on the report page and since when it's been there.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy)]
+struct LevelChange {
+    level: u8,
+    /// Unix timestamp.
+    time: i64,
+}
+
+/// What one run leaves for the next, in the JSON `state_file`.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct RunState {
+    pub published: Option<Published>,
+    report: Option<SeenReport>,
+    #[serde(default)]
+    level_change: Option<LevelChange>,
+}
+
+impl RunState {
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("invalid state file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RunState::default()),
+            Err(e) => {
+                Err(e).wrap_err_with(|| format!("could not read state file {}", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("could not write state file {}", path.display()))
+    }
+
+    /// The latest revision of the report page, read from the wiki only if it
+    /// changed since the last run. If it's the one we published, the level
+    /// and RPM are the ones we published rather than read from the page,
+    /// which a template may not show them on.
+    pub async fn current_report(
+        &mut self,
+        client: &mw::Client,
+        settings: &Settings,
+        now: DateTime<Utc>,
+    ) -> color_eyre::Result<ReportRevision> {
+        if let Some(seen) = &self.report {
+            if latest_revid(client, &settings.report_page).await? == Some(seen.revid) {
+                return Ok(seen.into());
+            }
+        }
+        let mut rev = current_report(client, settings, now).await?;
+        if let Some(published) = self.published {
+            if published.revid == Some(rev.revid) {
+                rev.level = published.level;
+                rev.rpm = Some(published.rpm);
+            }
+        }
+        self.report = Some((&rev).into());
+        Ok(rev)
+    }
+
+    /// Remember that `level` was published, which changes the report page,
+    /// along with the revision that made if the latest one is ours.
+    pub async fn record_published(
+        &mut self,
+        client: &mw::Client,
+        settings: &Settings,
+        level: u8,
+        rpm: f32,
+        time: DateTime<Utc>,
+    ) {
+        let revid = if settings.dry_run {
+            None
+        } else {
+            own_revid(client, settings).await.unwrap_or_else(|e| {
+                warn!("could not read back the published revision: {e:?}");
+                None
+            })
+        };
+        self.published = Some(Published {
+            level,
+            rpm,
+            time: time.timestamp(),
+            revid,
+        });
+        self.report = None;
+        self.record_level_change(level, time);
+    }
+
+    /// Remember that the report page changed to `level` at `time`.
+    pub fn record_level_change(&mut self, level: u8, time: DateTime<Utc>) {
+        self.level_change = Some(LevelChange {
+            level,
+            time: time.timestamp(),
+        });
+    }
+
+    /// When the level on the report page, as `rev` shows it, last changed,
+    /// if known. A level different from the one last seen was changed by
+    /// someone else, in `rev` as far as we can tell.
+    pub fn level_changed(&mut self, rev: &ReportRevision) -> Option<DateTime<Utc>> {
+        match self.level_change {
+            Some(change) if change.level == rev.level => {}
+            Some(_) => match rev.timestamp {
+                Some(time) => self.record_level_change(rev.level, time),
+                None => self.level_change = None,
+            },
+            None => return None,
+        }
+        self.level_change
+            .and_then(|change| Utc.timestamp_opt(change.time, 0).single())
+    }
+}
+
+/// The latest revision of the report page, if we made it.
+async fn own_revid(client: &mw::Client, settings: &Settings) -> color_eyre::Result<Option<u64>> {
+    let rev = latest_revision(client, &settings.report_page, "ids|user").await?;
+    let ours = rev["user"].as_str() == Some(username(client).await?.as_str());
+    Ok(rev["revid"].as_u64().filter(|_| ours))
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::bail;
+use tracing::info;
+
+use crate::history::{History, Sample};
+use crate::settings::Settings;
+use crate::wiki::{edit_page, latest_revision, writes_enabled};
+
+/// Settings of the `[stats]` table: a weekly summary page built from the
+/// database.
+#[derive(serde::Deserialize)]
+pub struct StatsSettings {
+    /// Each week's summary goes to a `page/YYYY-Www` subpage.
+    pub page: String,
+}
+
+/// What a week of samples adds up to.
+pub struct WeeklyStats {
+    pub samples: usize,
+    /// Hours spent at each level.
+    pub hours_at_level: BTreeMap<u8, f64>,
+    /// The highest RPM and when it was seen.
+    pub peak: Option<(i64, f64)>,
+    /// Mean RPM for each hour of the day (UTC), busiest first.
+    pub busiest_hours: Vec<(u32, f64)>,
+    pub level_changes: usize,
+}
+
+impl WeeklyStats {
+    /// Sum up `samples`, oldest first. Each sample counts until the next one,
+    /// but for no more than `max_gap_secs` so that downtime isn't counted.
+    pub fn new(samples: &[Sample], max_gap_secs: i64) -> Self {
+        let mut hours_at_level = BTreeMap::new();
+        for (sample, next) in samples.iter().zip(samples.iter().skip(1)) {
+            let secs = (next.time - sample.time).clamp(0, max_gap_secs);
+            *hours_at_level.entry(sample.level).or_insert(0.0) += secs as f64 / 3600.0;
+        }
+        let peak = samples.iter().map(|sample| (sample.time, sample.rpm)).fold(
+            None,
+            |peak: Option<(i64, f64)>, (time, rpm)| match peak {
+                Some((_, max)) if max >= rpm => peak,
+                _ => Some((time, rpm)),
+            },
+        );
+        let mut by_hour: BTreeMap<u32, (f64, usize)> = BTreeMap::new();
+        for sample in samples {
+            if let Some(time) = Utc.timestamp_opt(sample.time, 0).single() {
+                let entry = by_hour.entry(time.hour()).or_insert((0.0, 0));
+                entry.0 += sample.rpm;
+                entry.1 += 1;
+            }
+        }
+        let mut busiest_hours: Vec<(u32, f64)> = by_hour
+            .into_iter()
+            .map(|(hour, (sum, count))| (hour, sum / count as f64))
+            .collect();
+        busiest_hours.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let level_changes = samples
+            .windows(2)
+            .filter(|pair| pair[0].level != pair[1].level)
+            .count();
+        WeeklyStats {
+            samples: samples.len(),
+            hours_at_level,
+            peak,
+            busiest_hours,
+            level_changes,
+        }
+    }
+
+    pub fn wikitext(&self, settings: &Settings, week: &str, start: DateTime<Utc>) -> String {
+        let end = start + Duration::days(6);
+        let mut text = format!(
+            "Vandalism level statistics for {} in week {week} ({} to {}), from {} samples.\n\n",
+            settings.wiki,
+            start.format("%Y-%m-%d"),
+            end.format("%Y-%m-%d"),
+            self.samples
+        );
+        let total: f64 = self.hours_at_level.values().sum();
+        text.push_str("{| class=\"wikitable\"\n! Level !! Hours !! Share\n");
+        for (level, hours) in &self.hours_at_level {
+            let share = if total > 0.0 {
+                hours / total * 100.0
+            } else {
+                0.0
+            };
+            let _ = writeln!(text, "|-\n| {level} || {hours:.1} || {share:.1}%");
+        }
+        text.push_str("|}\n");
+        if let Some((time, rpm)) = self.peak {
+            let time = Utc
+                .timestamp_opt(time, 0)
+                .single()
+                .map_or_else(String::new, |time| {
+                    time.format(" at %Y-%m-%d %H:%M UTC").to_string()
+                });
+            let _ = writeln!(text, "* Peak: {rpm:.2} RPM{time}");
+        }
+        let busiest: Vec<String> = self
+            .busiest_hours
+            .iter()
+            .take(3)
+            .map(|(hour, rpm)| format!("{hour:02}:00 ({rpm:.2} RPM)"))
+            .collect();
+        if !busiest.is_empty() {
+            let _ = writeln!(text, "* Busiest hours (UTC): {}", busiest.join(", "));
+        }
+        let _ = writeln!(text, "* Level changes: {}", self.level_changes);
+        text
+    }
+}
+
+/// Monday 00:00 UTC of the last full week before `now`, and its ISO name.
+fn last_week(now: DateTime<Utc>) -> (DateTime<Utc>, String) {
+    let today = now.date_naive();
+    let this_monday = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    let monday = this_monday - Duration::days(7);
+    let week = monday.iso_week();
+    let start = Utc.from_utc_datetime(&monday.and_hms_opt(0, 0, 0).unwrap());
+    (start, format!("{}-W{:02}", week.year(), week.week()))
+}
+
+/// Post the summary of the last full week before `now`, unless it's already
+/// there.
+pub async fn post_weekly_stats(
+    client: &mw::Client,
+    settings: &Settings,
+    stats: &StatsSettings,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<()> {
+    let path = match &settings.database {
+        Some(path) => path,
+        None => bail!("the weekly statistics need a `database`"),
+    };
+    let (start, week) = last_week(now);
+    let page = format!("{}/{week}", stats.page);
+    let rev = latest_revision(client, &page, "ids").await?;
+    if rev["revid"].is_u64() {
+        return Ok(());
+    }
+
+    let history = History::open(path, &settings.wiki)?;
+    let samples = history.between(start.timestamp(), (start + Duration::weeks(1)).timestamp())?;
+    let max_gap_secs = settings.run_interval_mins.max(1) as i64 * 60 * 2;
+    let text = WeeklyStats::new(&samples, max_gap_secs).wikitext(settings, &week, start);
+    if settings.dry_run {
+        println!("would edit {page}:\n{text}");
+        return Ok(());
+    }
+    if !writes_enabled(client, settings).await? {
+        return Ok(());
+    }
+    edit_page(
+        client,
+        &page,
+        &text,
+        &settings.messages.get("defcon-summary-stats", &[&week]),
+        None,
+    )
+    .await?;
+    info!("posted the statistics of {week} to {page}");
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Stopping the daemon loops between runs: to reload the settings on SIGHUP
+//! or when the settings file changes, or to shut down on SIGTERM or SIGINT.
+//! Once a stop is requested, the loops return as soon as they're done with
+//! the run in progress, so that no edit or state file write is cut short.
+//!
+//! After a reload the loops are started again with the new settings; the
+//! recent changes window (polled, or the reverts seen live) and the other
+//! caches are kept, as they live outside of them.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::systemd;
+
+/// How often the settings file is checked for changes.
+const POLL_SECS: u64 = 10;
+
+/// Why the daemon loops were asked to return.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    Reload,
+    Shutdown,
+}
+
+lazy_static! {
+    /// The last stop requested, if any.
+    static ref STOPS: watch::Sender<Option<Stop>> = watch::channel(None).0;
+}
+
+/// Ask the daemon loops to return. A shutdown can't be turned into a reload.
+pub fn request(stop: Stop) {
+    STOPS.send_modify(|last| {
+        if *last != Some(Stop::Shutdown) {
+            *last = Some(stop);
+        }
+    });
+}
+
+/// A receiver whose `changed` resolves once a stop is requested after this
+/// call.
+pub fn subscribe() -> watch::Receiver<Option<Stop>> {
+    STOPS.subscribe()
+}
+
+/// The last stop requested, if any.
+pub fn requested() -> Option<Stop> {
+    *STOPS.borrow()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Request a reload on every SIGHUP and whenever the settings file at `path`
+/// is modified, and a shutdown on SIGTERM or SIGINT. A second SIGTERM or
+/// SIGINT exits right away.
+pub async fn watch(path: Option<PathBuf>) -> color_eyre::Result<()> {
+    #[cfg(unix)]
+    let (mut hangups, mut terminates) = {
+        use tokio::signal::unix::{signal, SignalKind};
+        (
+            signal(SignalKind::hangup())?,
+            signal(SignalKind::terminate())?,
+        )
+    };
+    let mut last_modified = path.as_deref().and_then(modified);
+    let mut poll = tokio::time::interval(std::time::Duration::from_secs(POLL_SECS));
+    loop {
+        #[cfg(unix)]
+        let (hangup, terminate) = (hangups.recv(), terminates.recv());
+        #[cfg(not(unix))]
+        let (hangup, terminate) = (
+            std::future::pending::<Option<()>>(),
+            std::future::pending::<Option<()>>(),
+        );
+        let stop = tokio::select! {
+            _ = terminate => Stop::Shutdown,
+            _ = tokio::signal::ctrl_c() => Stop::Shutdown,
+            _ = hangup => {
+                info!("reloading the settings on SIGHUP");
+                Stop::Reload
+            }
+            _ = poll.tick() => {
+                let path = match &path {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let modified = modified(path);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                info!("reloading the settings, {} changed", path.display());
+                Stop::Reload
+            }
+        };
+        if stop == Stop::Shutdown {
+            if requested() == Some(Stop::Shutdown) {
+                warn!("exiting without waiting for the runs in progress");
+                std::process::exit(1);
+            }
+            info!("shutting down once the runs in progress are done");
+        }
+        systemd::notify(match stop {
+            Stop::Reload => "RELOADING=1",
+            Stop::Shutdown => "STOPPING=1",
+        });
+        request(stop);
+    }
+}
@@ -0,0 +1,62 @@
+//! Notifying systemd of the daemon's state, for running as a `Type=notify`
+//! service: `READY=1` once logged in, `STATUS=` with the level, and
+//! `WATCHDOG=1` after every run, so that with `WatchdogSec=` (longer than
+//! `run_interval_mins`) a daemon that hangs gets restarted. Nothing is sent
+//! unless systemd set `NOTIFY_SOCKET`.
+
+use tracing::debug;
+
+/// Send `state`, e.g. `READY=1`, to the notification socket if there is
+/// one. Failures are only logged, as systemd doesn't need to be there.
+pub fn notify(state: &str) {
+    if let Err(e) = send(state) {
+        debug!("could not notify systemd of {state}: {e}");
+    }
+}
+
+pub fn ready() {
+    notify("READY=1");
+}
+
+pub fn watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// Show `text` as the status of the service.
+pub fn status(text: &str) {
+    notify(&format!("STATUS={text}"));
+}
+
+#[cfg(unix)]
+fn send(state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        // an abstract socket, which only Linux has
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {}
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_state: &str) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Recording the API responses of a run (`--record`) and running again from
+//! them (`--replay`), so that a level or a misclassification reported hours
+//! later can be looked into with exactly the edits the run saw.
+//!
+//! A recording is one JSON file per profile holding the time of the run and
+//! every response by the request it answered. Logging in isn't recorded, so
+//! replaying still needs the credentials and a reachable wiki for that.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+use color_eyre::eyre::{bail, WrapErr};
+use serde_json::Value;
+
+use crate::clock::Clock;
+
+/// Parameters that differ between otherwise identical requests.
+const VOLATILE_PARAMS: [&str; 2] = ["maxlag", "token"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct Recording {
+    /// When the run happened, in RFC 3339 to the nanosecond.
+    time: Option<String>,
+    /// Responses by request, in the order they came.
+    responses: BTreeMap<String, VecDeque<Value>>,
+}
+
+/// The recording of one profile's run, being made or played back. It is
+/// also the clock of the run: recording notes the time, and replaying goes
+/// back to it.
+pub struct Tape {
+    path: PathBuf,
+    mode: Mode,
+    recording: Mutex<Recording>,
+}
+
+impl Tape {
+    /// Record into `<dir>/<profile>.json`, replacing an earlier recording.
+    pub fn record(dir: &Path, profile: &str) -> color_eyre::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("could not create {}", dir.display()))?;
+        Ok(Tape {
+            path: dir.join(format!("{profile}.json")),
+            mode: Mode::Record,
+            recording: Mutex::new(Recording::default()),
+        })
+    }
+
+    /// Play back `<dir>/<profile>.json`.
+    pub fn replay(dir: &Path, profile: &str) -> color_eyre::Result<Self> {
+        let path = dir.join(format!("{profile}.json"));
+        let json = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("could not read the recording {}", path.display()))?;
+        let recording = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("invalid recording {}", path.display()))?;
+        Ok(Tape {
+            path,
+            mode: Mode::Replay,
+            recording: Mutex::new(recording),
+        })
+    }
+
+    /// The recorded response to the next such request, when replaying.
+    pub(crate) fn play(
+        &self,
+        post: bool,
+        query: &[(&str, &str)],
+    ) -> color_eyre::Result<Option<Value>> {
+        if self.mode != Mode::Replay {
+            return Ok(None);
+        }
+        let key = key(post, query);
+        let mut recording = self.recording.lock().unwrap();
+        match recording
+            .responses
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(res) => Ok(Some(res)),
+            None => bail!("no response to `{key}` in {}", self.path.display()),
+        }
+    }
+
+    /// Save `res`, the response to `query`, when recording. The file is
+    /// rewritten every time, so that a run that fails keeps what it got.
+    pub(crate) fn save(
+        &self,
+        post: bool,
+        query: &[(&str, &str)],
+        res: &Value,
+    ) -> color_eyre::Result<()> {
+        if self.mode != Mode::Record {
+            return Ok(());
+        }
+        let mut recording = self.recording.lock().unwrap();
+        recording
+            .responses
+            .entry(key(post, query))
+            .or_default()
+            .push_back(res.clone());
+        self.write(&recording)
+    }
+
+    fn write(&self, recording: &Recording) -> color_eyre::Result<()> {
+        let json = serde_json::to_string_pretty(recording)?;
+        std::fs::write(&self.path, json)
+            .wrap_err_with(|| format!("could not write {}", self.path.display()))
+    }
+}
+
+impl Clock for Tape {
+    fn now(&self) -> DateTime<Utc> {
+        let mut recording = self.recording.lock().unwrap();
+        match self.mode {
+            Mode::Record => {
+                let now = Utc::now();
+                recording.time = Some(now.to_rfc3339_opts(SecondsFormat::Nanos, true));
+                if let Err(e) = self.write(&recording) {
+                    tracing::warn!("{e:#}");
+                }
+                now
+            }
+            Mode::Replay => recording
+                .time
+                .as_deref()
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+/// What identifies a request: its method and its parameters, sorted and
+/// without the volatile ones.
+fn key(post: bool, query: &[(&str, &str)]) -> String {
+    let mut params: Vec<String> = query
+        .iter()
+        .filter(|(key, _)| !VOLATILE_PARAMS.contains(key))
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+    params.sort();
+    let method = if post { "POST" } else { "GET" };
+    format!("{method} {}", params.join("&"))
+}
@@ -0,0 +1,97 @@
+//! Suggesting thresholds from the samples in the database, so that each
+//! level is up for a chosen share of the time.
+
+use color_eyre::eyre::bail;
+
+use crate::history::Sample;
+use crate::level::Levels;
+use crate::scoring::Signal;
+use crate::settings::Settings;
+
+/// Shares of the time, in percent, for each of the default levels from the
+/// calmest to the most severe.
+pub static DEFAULT_SHARES: [f64; 5] = [70.0, 20.0, 6.0, 3.0, 1.0];
+
+/// Smallest gap between suggested thresholds, which have to be strictly
+/// increasing even where the history has many equal values.
+const MIN_GAP: f32 = 0.01;
+
+/// Thresholds suggested by `tune`.
+pub struct Tuning {
+    pub thresholds: Vec<f32>,
+    /// Share of the samples, in percent, that each level would have taken.
+    pub shares: Vec<f64>,
+    /// Number of samples the thresholds were computed from.
+    pub samples: usize,
+}
+
+/// Check that `shares` has one percentage per level and adds up to 100.
+pub fn validate_shares(shares: &[f64], levels: &Levels) -> color_eyre::Result<()> {
+    if shares.len() != levels.len() {
+        bail!(
+            "there must be a share for each of the {} levels, found {}",
+            levels.len(),
+            shares.len()
+        );
+    }
+    if let Some(share) = shares
+        .iter()
+        .find(|share| !share.is_finite() || **share <= 0.0)
+    {
+        bail!("shares must be positive percentages, found {share}");
+    }
+    let total: f64 = shares.iter().sum();
+    if (total - 100.0).abs() > 0.5 {
+        bail!("shares must add up to 100%, found {total}%");
+    }
+    Ok(())
+}
+
+/// Thresholds on the value the level is computed from (the `level_metric`,
+/// or the score with `[scoring]`) under which each level would have been
+/// up for its share of `samples`. The hysteresis and `min_dwell_mins` are
+/// ignored, so the shares are what `rpm_to_level` would give.
+pub fn tune(settings: &Settings, samples: &[Sample], shares: &[f64]) -> color_eyre::Result<Tuning> {
+    validate_shares(shares, &settings.levels)?;
+    let mut values = samples
+        .iter()
+        .map(|sample| {
+            // samples from before metrics were recorded only have the RPM
+            let mut metrics = sample.metrics.clone();
+            metrics.entry("rpm".to_owned()).or_insert(sample.rpm);
+            Signal::new(settings, &metrics).map(|signal| signal.value)
+        })
+        .collect::<color_eyre::Result<Vec<f32>>>()?;
+    if values.is_empty() {
+        bail!("there are no samples in the database to tune from");
+    }
+    values.sort_by(f32::total_cmp);
+
+    let total: f64 = shares.iter().sum();
+    let mut thresholds: Vec<f32> = Vec::with_capacity(shares.len() - 1);
+    let mut cumulative = 0.0;
+    for share in &shares[..shares.len() - 1] {
+        cumulative += share / total;
+        // the highest value still at this level or a calmer one
+        let below = (cumulative * values.len() as f64).round() as usize;
+        let index = below.clamp(1, values.len()) - 1;
+        let mut threshold = (values[index] * 100.0).ceil() / 100.0;
+        if let Some(&previous) = thresholds.last() {
+            threshold = threshold.max(previous + MIN_GAP);
+        }
+        thresholds.push(threshold.max(MIN_GAP));
+    }
+
+    let mut counts = vec![0usize; shares.len()];
+    for value in &values {
+        counts[thresholds.iter().filter(|t| value > *t).count()] += 1;
+    }
+    Ok(Tuning {
+        shares: counts
+            .iter()
+            .map(|&count| 100.0 * count as f64 / values.len() as f64)
+            .collect(),
+        thresholds,
+        samples: values.len(),
+    })
+}
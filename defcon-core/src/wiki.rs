@@ -0,0 +1,935 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{prelude::*, Duration};
+use color_eyre::eyre::bail;
+use lazy_static::lazy_static;
+use mw::ua;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::email;
+use crate::error::DefconError;
+use crate::metrics::Metrics;
+use crate::monitor;
+use crate::publish::LevelUpdate;
+use crate::report::{ReportFormat, ReportVars};
+use crate::settings::Settings;
+use crate::tape::Tape;
+
+/// Seconds of replication lag from which the API turns our requests away, as
+/// asked of bots on Wikimedia wikis.
+pub const MAXLAG: &str = "5";
+/// Times a request is retried while the servers are lagged.
+const MAXLAG_RETRIES: u32 = 10;
+/// Delay before the first retry of a failed request, doubled for every retry
+/// after it.
+const BACKOFF_BASE_MS: u64 = 1000;
+const BACKOFF_MAX_MS: u64 = 60_000;
+/// Attempts at a request when there's no `api_attempts` in scope.
+const DEFAULT_API_ATTEMPTS: u32 = 4;
+/// Revisions of the report page looked through for the last level change.
+const LEVEL_HISTORY_REVISIONS: u32 = 50;
+
+/// Errors meaning we may not edit at all until someone sorts it out.
+const WRITE_REFUSALS: [&str; 4] = [
+    "blocked",
+    "autoblocked",
+    "assertbotfailed",
+    "assertuserfailed",
+];
+
+/// What requests need to know about the profile they're made for.
+struct Profile {
+    wiki: String,
+    api_attempts: u32,
+    edit: EditSettings,
+    tape: Option<Arc<Tape>>,
+}
+
+tokio::task_local! {
+    /// The profile being run, set by `in_profile`.
+    static PROFILE: Profile;
+}
+
+lazy_static! {
+    static ref LEVEL_RE: Regex = Regex::new(r"level\s*=\s*(\d+)").unwrap();
+    static ref BOTS_RE: Regex = Regex::new(r"(?i)\{\{\s*(nobots|bots)\s*(\|[^}]*)?\}\}").unwrap();
+}
+
+/// Log in to the wiki of `settings`.
+pub async fn login(settings: &Settings) -> color_eyre::Result<mw::Client> {
+    connect(&settings.api_url, &settings.oauth_token).await
+}
+
+/// Log in to the API at `api_url`, which can be any MediaWiki's `api.php`,
+/// e.g. a local test wiki or a mock.
+pub async fn connect(api_url: &str, oauth_token: &str) -> color_eyre::Result<mw::Client> {
+    with_retries("login", true, || async {
+        let (client, _) = mw::ClientBuilder::new(api_url)
+            .user_agent(ua!(user_agent!()))
+            .login_oauth(oauth_token)
+            .await?;
+        Ok(client)
+    })
+    .await
+}
+
+/// Settings of the `[edit]` table: the flags and tags every edit is made
+/// with.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct EditSettings {
+    /// Mark edits as bot edits.
+    #[serde(default)]
+    pub bot: bool,
+    /// Mark edits as minor, or explicitly not; the user preference if unset.
+    pub minor: Option<bool>,
+    /// `watch`, `unwatch`, `preferences` or `nochange`; the API's default
+    /// (`preferences`) if unset.
+    pub watchlist: Option<String>,
+    /// Change tags applied to every edit, e.g. `defcon-bot`. They have to be
+    /// registered on the wiki.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl EditSettings {
+    pub fn validate(&self) -> color_eyre::Result<()> {
+        if let Some(watchlist) = &self.watchlist {
+            if !["watch", "unwatch", "preferences", "nochange"].contains(&watchlist.as_str()) {
+                bail!(
+                    "`edit.watchlist` must be watch, unwatch, preferences or nochange, \
+                     found `{watchlist}`"
+                );
+            }
+        }
+        if let Some(tag) = self
+            .tags
+            .iter()
+            .find(|tag| tag.is_empty() || tag.contains('|'))
+        {
+            bail!("`edit.tags` must be tag names without `|`, found `{tag}`");
+        }
+        Ok(())
+    }
+
+    /// The parameters these settings add to an edit request.
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if self.bot {
+            params.push(("bot", "1".to_owned()));
+        }
+        match self.minor {
+            Some(true) => params.push(("minor", "1".to_owned())),
+            Some(false) => params.push(("notminor", "1".to_owned())),
+            None => {}
+        }
+        if let Some(watchlist) = &self.watchlist {
+            params.push(("watchlist", watchlist.clone()));
+        }
+        if !self.tags.is_empty() {
+            params.push(("tags", self.tags.join("|")));
+        }
+        params
+    }
+}
+
+/// Run `fut` as the profile of `settings`: requests made in it are attempted
+/// up to `api_attempts` times, edits are made with the flags in `edit`, and
+/// refused edits are recorded for its wiki. With a `tape`, API responses are
+/// recorded to it or played back from it.
+pub async fn in_profile<F: Future>(
+    settings: &Settings,
+    tape: Option<Arc<Tape>>,
+    fut: F,
+) -> F::Output {
+    let profile = Profile {
+        wiki: settings.wiki.clone(),
+        api_attempts: settings.api_attempts,
+        edit: settings.edit.clone(),
+        tape,
+    };
+    PROFILE.scope(profile, fut).await
+}
+
+/// Whether the API responses are being recorded or played back, in which
+/// case a run mustn't depend on what earlier runs cached, or the recording
+/// couldn't be played back.
+pub(crate) fn on_tape() -> bool {
+    PROFILE
+        .try_with(|profile| profile.tape.is_some())
+        .unwrap_or(false)
+}
+
+/// Whether `e` is worth retrying: a failed connection, or for an
+/// `idempotent` request also a timeout, a reset connection or a server
+/// error. Those may come after the server carried the request out, so doing
+/// it again could e.g. append the same text twice.
+fn is_transient(e: &color_eyre::Report, idempotent: bool) -> bool {
+    e.chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| {
+            e.is_connect()
+                || idempotent
+                    && (e.is_timeout()
+                        || e.is_request()
+                        || e.status().is_some_and(|status| status.is_server_error()))
+        })
+}
+
+/// How long to wait before retry number `retry` (from 0): exponential, with
+/// jitter so that profiles failing together don't retry together.
+fn backoff(retry: u32) -> std::time::Duration {
+    let delay = BACKOFF_BASE_MS
+        .saturating_mul(1 << retry.min(16))
+        .min(BACKOFF_MAX_MS);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    // between half and all of `delay`
+    let jitter = f64::from(nanos % 1000) / 1000.0;
+    std::time::Duration::from_millis((delay as f64 * (0.5 + 0.5 * jitter)) as u64)
+}
+
+/// Run `f` until it succeeds, fails for good or runs out of `api_attempts`,
+/// backing off between attempts. Unless `f` is `idempotent`, it's only run
+/// again when it never reached the server.
+pub async fn with_retries<T, F, Fut>(
+    what: &str,
+    idempotent: bool,
+    mut f: F,
+) -> color_eyre::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = color_eyre::Result<T>>,
+{
+    let attempts = PROFILE
+        .try_with(|profile| profile.api_attempts)
+        .unwrap_or(DEFAULT_API_ATTEMPTS)
+        .max(1);
+    let mut retry = 0;
+    loop {
+        match f().await {
+            Err(e) if retry + 1 < attempts && is_transient(&e, idempotent) => {
+                let delay = backoff(retry);
+                warn!("{what} failed, retrying in {delay:?}: {e}");
+                tokio::time::sleep(delay).await;
+                retry += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Send `query` with `maxlag`, waiting as long as the API asks and retrying
+/// while the servers are lagged, which they answer without carrying out the
+/// request. A POST is only retried after other failures if it never reached
+/// the server. When replaying, the recorded response is returned instead.
+#[tracing::instrument(level = "debug", skip(client, query), fields(action, list))]
+async fn api_request(
+    client: &mw::Client,
+    post: bool,
+    query: &[(&str, &str)],
+) -> color_eyre::Result<Value> {
+    let span = tracing::Span::current();
+    for (key, value) in query {
+        if ["action", "list"].contains(key) {
+            span.record(*key, *value);
+        }
+    }
+    let tape = PROFILE
+        .try_with(|profile| profile.tape.clone())
+        .ok()
+        .flatten();
+    if let Some(tape) = &tape {
+        if let Some(res) = tape.play(post, query)? {
+            return Ok(res);
+        }
+    }
+    let started = std::time::Instant::now();
+    let mut query = query.to_vec();
+    query.push(("maxlag", MAXLAG));
+    let mut retries = 0;
+    loop {
+        let res = with_retries("API request", !post, || async {
+            let res = if post {
+                client.post(query.clone()).send().await?
+            } else {
+                client.get(query.clone()).send().await?
+            };
+            Ok(res.error_for_status()?)
+        })
+        .await?;
+        let retry_after = res
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(5);
+        let res: Value = res.json().await?;
+        if res["error"]["code"] != "maxlag" || retries == MAXLAG_RETRIES {
+            let code = res["error"]["code"].as_str();
+            debug!(
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                error = code,
+                "API response"
+            );
+            let _ = PROFILE.try_with(|profile| monitor::record_api_code(&profile.wiki, code));
+            if let Some(tape) = &tape {
+                tape.save(post, &query, &res)?;
+            }
+            return Ok(res);
+        }
+        retries += 1;
+        warn!(
+            "API lagged ({}), retrying in {retry_after} seconds",
+            res["error"]["info"].as_str().unwrap_or("")
+        );
+        tokio::time::sleep(std::time::Duration::from_secs(retry_after)).await;
+    }
+}
+
+/// GET `query` from the API.
+pub async fn api_get(client: &mw::Client, query: &[(&str, &str)]) -> color_eyre::Result<Value> {
+    api_request(client, false, query).await
+}
+
+/// POST `query` to the API, as a bot. Being blocked or not logged in as a bot
+/// turns off the profile's edits until `writes_enabled` finds it resolved.
+pub async fn api_post(client: &mw::Client, query: &[(&str, &str)]) -> color_eyre::Result<Value> {
+    let mut query = query.to_vec();
+    query.push(("assert", "bot"));
+    let res = api_request(client, true, &query).await?;
+    if let Some(code) = res["error"]["code"].as_str() {
+        if WRITE_REFUSALS.contains(&code) {
+            let reason = format!("{code}: {}", res["error"]["info"].as_str().unwrap_or(""));
+            tracing::error!("edits refused, not editing until resolved: {reason}");
+            let _ =
+                PROFILE.try_with(|profile| monitor::record_write_refused(&profile.wiki, reason));
+        }
+    }
+    Ok(res)
+}
+
+/// POST an `action=edit` `query` with the profile's `[edit]` flags and tags.
+async fn api_edit(client: &mw::Client, query: &[(&str, &str)]) -> color_eyre::Result<Value> {
+    let params = PROFILE
+        .try_with(|profile| profile.edit.params())
+        .unwrap_or_default();
+    let mut query = query.to_vec();
+    query.extend(params.iter().map(|(key, value)| (*key, value.as_str())));
+    api_post(client, &query).await
+}
+
+/// A CSRF token to make edits with, requested like any other query.
+pub async fn csrf_token(client: &mw::Client) -> color_eyre::Result<String> {
+    let q = [("action", "query"), ("meta", "tokens"), ("type", "csrf")];
+    let res = api_get(client, &q).await?;
+    match res["query"]["tokens"]["csrftoken"].as_str() {
+        Some(token) => Ok(token.to_owned()),
+        None => bail!("no CSRF token in the response: {res}"),
+    }
+}
+
+/// Whether our account is unblocked and has the bot right.
+async fn can_edit_as_bot(client: &mw::Client) -> color_eyre::Result<bool> {
+    let q = [
+        ("action", "query"),
+        ("meta", "userinfo"),
+        ("uiprop", "blockinfo|rights"),
+    ];
+    let res = api_get(client, &q).await?;
+    let userinfo = &res["query"]["userinfo"];
+    let is_bot = userinfo["rights"]
+        .as_array()
+        .is_some_and(|rights| rights.iter().any(|right| right == "bot"));
+    Ok(userinfo.get("blockid").is_none() && is_bot)
+}
+
+/// GET `query` and every continuation of it, handing each response to `f`
+/// and collecting what it returns.
+pub async fn query_all<T, U>(
+    client: &mw::Client,
+    query: &[(&str, &str)],
+    mut f: impl FnMut(T) -> color_eyre::Result<Vec<U>>,
+) -> color_eyre::Result<Vec<U>>
+where
+    T: DeserializeOwned,
+{
+    let mut out = Vec::new();
+    let mut continuation: Vec<(String, String)> = Vec::new();
+    loop {
+        let mut q = query.to_vec();
+        q.extend(
+            continuation
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        );
+        let res = api_get(client, &q).await?;
+        check_response(&res)?;
+        continuation = match res.get("continue").and_then(Value::as_object) {
+            Some(values) => values
+                .iter()
+                .map(|(key, value)| {
+                    let value = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_owned);
+                    (key.clone(), value)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        out.extend(f(serde_json::from_value(res)?)?);
+        if continuation.is_empty() {
+            return Ok(out);
+        }
+    }
+}
+
+/// Fetch the latest revision of `title` with the given `rvprop`s.
+pub async fn latest_revision(
+    client: &mw::Client,
+    title: &str,
+    rvprop: &str,
+) -> color_eyre::Result<Value> {
+    let q = [
+        ("action", "query"),
+        ("prop", "revisions"),
+        ("titles", title),
+        ("rvprop", rvprop),
+        ("rvslots", "main"),
+        ("rvlimit", "1"),
+    ];
+    let mut res = api_get(client, &q).await?;
+    Ok(res["query"]["pages"][0]["revisions"][0].take())
+}
+
+/// The id of the latest revision of `title`, without its content, or `None`
+/// if it doesn't exist.
+pub async fn latest_revid(client: &mw::Client, title: &str) -> color_eyre::Result<Option<u64>> {
+    let q = [("action", "query"), ("prop", "info"), ("titles", title)];
+    let res = api_get(client, &q).await?;
+    Ok(res["query"]["pages"][0]["lastrevid"].as_u64())
+}
+
+/// Whether the bot may edit: its edits haven't been refused, and
+/// `shutoff_page` is unset or says `enabled`. Anything else on it, or a
+/// missing page, turns the bot's edits off, which gets logged. The operator
+/// is emailed about refused edits, if there's an `[email]`.
+pub async fn writes_enabled(client: &mw::Client, settings: &Settings) -> color_eyre::Result<bool> {
+    if let Some(refusal) = monitor::write_refusal(&settings.wiki) {
+        if can_edit_as_bot(client).await? {
+            info!("edits are no longer refused ({})", refusal.reason);
+            monitor::clear_write_refused(&settings.wiki);
+        } else {
+            warn!("not editing: edits are refused ({})", refusal.reason);
+            if !refusal.notified {
+                notify_refusal(settings, &refusal.reason).await;
+                monitor::mark_write_refusal_notified(&settings.wiki);
+            }
+            return Ok(false);
+        }
+    }
+    let page = match &settings.shutoff_page {
+        Some(page) => page,
+        None => return Ok(true),
+    };
+    let rev = latest_revision(client, page, "content").await?;
+    match rev["slots"]["main"]["content"].as_str() {
+        Some(text) if text.trim() == "enabled" => Ok(true),
+        Some(text) => {
+            let text: String = text.trim().chars().take(100).collect();
+            warn!("not editing: the shutoff page {page} says \"{text}\"");
+            Ok(false)
+        }
+        None => {
+            warn!("not editing: the shutoff page {page} doesn't exist");
+            Ok(false)
+        }
+    }
+}
+
+async fn notify_refusal(settings: &Settings, reason: &str) {
+    let subject = format!("defcon can't edit {}", settings.wiki);
+    let body = format!(
+        "The API refused an edit to {} ({reason}), so the bot won't edit until that's \
+         resolved.\n",
+        settings.wiki
+    );
+    email::notify(settings, &subject, body).await;
+}
+
+/// Revision ids of the edits made in the `window_mins` minutes up to `now`,
+/// newest first.
+pub async fn recent_revids(
+    client: &mw::Client,
+    now: DateTime<Utc>,
+    window_mins: i64,
+) -> color_eyre::Result<Vec<u64>> {
+    let start = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let end = (now - Duration::minutes(window_mins)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let query = [
+        ("action", "query"),
+        ("list", "recentchanges"),
+        ("rctype", "edit"),
+        ("rcstart", &start),
+        ("rcend", &end),
+        ("rcprop", "ids"),
+        ("rclimit", "max"),
+    ];
+    #[derive(serde::Deserialize)]
+    struct Edit {
+        revid: u64,
+    }
+    #[derive(serde::Deserialize)]
+    struct RecentChanges {
+        recentchanges: Vec<Edit>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Res {
+        query: RecentChanges,
+    }
+    query_all(client, &query, |res: Res| {
+        Ok(res
+            .query
+            .recentchanges
+            .into_iter()
+            .map(|edit| edit.revid)
+            .collect())
+    })
+    .await
+}
+
+/// A revision of the report page, as far as we care about it.
+pub struct ReportRevision {
+    pub revid: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub user: String,
+    /// The level set by this revision, or 0 if there isn't one.
+    pub level: u8,
+    /// The RPM this revision was published with, if it's ours and the state
+    /// file says.
+    pub rpm: Option<f32>,
+    pub text: String,
+}
+
+impl ReportRevision {
+    pub fn from_json(rev: &Value) -> color_eyre::Result<Self> {
+        let revid = rev["revid"]
+            .as_u64()
+            .ok_or_else(|| DefconError::Parse("report page has no revisions".to_owned()))?;
+        let text = rev["slots"]["main"]["content"].as_str().unwrap_or("");
+        Ok(ReportRevision {
+            revid,
+            timestamp: rev["timestamp"]
+                .as_str()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+                .map(|ts| ts.with_timezone(&Utc)),
+            user: rev["user"].as_str().unwrap_or("").to_owned(),
+            level: read_level(text).unwrap_or(0),
+            rpm: None,
+            text: text.to_owned(),
+        })
+    }
+}
+
+/// The level set by report page text `text`: the first `level = <n>`, or a
+/// bare number as in the `number` format.
+pub(crate) fn read_level(text: &str) -> Option<u8> {
+    LEVEL_RE
+        .captures(text)
+        .and_then(|captures| captures[1].parse().ok())
+        .or_else(|| text.trim().parse().ok())
+}
+
+/// The name of the user we're logged in as.
+pub async fn username(client: &mw::Client) -> color_eyre::Result<String> {
+    let q = [("action", "query"), ("meta", "userinfo")];
+    let res = api_get(client, &q).await?;
+    res["query"]["userinfo"]["name"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| {
+            DefconError::Parse("no user name in the userinfo response".to_owned()).into()
+        })
+}
+
+/// Whether `text` has a `{{bots}}` or `{{nobots}}` template.
+pub fn has_bots_template(text: &str) -> bool {
+    BOTS_RE.is_match(text)
+}
+
+/// Whether `{{nobots}}` or `{{bots|allow=...}}`/`{{bots|deny=...}}` in `text`
+/// keep `user` from editing it.
+pub fn bot_excluded(text: &str, user: &str) -> bool {
+    let normalize = |name: &str| name.trim().replace('_', " ").to_lowercase();
+    let user = normalize(user);
+    BOTS_RE.captures_iter(text).any(|captures| {
+        if captures[1].eq_ignore_ascii_case("nobots") {
+            return true;
+        }
+        let params = captures.get(2).map_or("", |params| params.as_str());
+        params.split('|').any(|param| {
+            let (key, names) = match param.split_once('=') {
+                Some(pair) => pair,
+                None => return false,
+            };
+            let names: Vec<String> = names.split(',').map(normalize).collect();
+            let listed = names.iter().any(|name| name == "all" || *name == user);
+            match key.trim() {
+                "allow" => !listed,
+                "deny" => listed,
+                _ => false,
+            }
+        })
+    })
+}
+
+/// The latest revision of the report page. A missing page is created with
+/// `initial_report_text` as of `now` if `create_report_page` is set.
+pub async fn current_report(
+    client: &mw::Client,
+    settings: &Settings,
+    now: DateTime<Utc>,
+) -> color_eyre::Result<ReportRevision> {
+    let rvprop = "ids|timestamp|user|content";
+    let mut rev = latest_revision(client, &settings.report_page, rvprop).await?;
+    if rev.is_null() {
+        if !settings.create_report_page {
+            return Err(DefconError::Config(format!(
+                "{} doesn't exist; set `create_report_page = true` to create it",
+                settings.report_page
+            ))
+            .into());
+        }
+        if settings.dry_run {
+            println!("would create {}", settings.report_page);
+            return Ok(ReportRevision {
+                revid: 0,
+                timestamp: None,
+                user: String::new(),
+                level: 0,
+                rpm: None,
+                text: String::new(),
+            });
+        }
+        if !writes_enabled(client, settings).await? {
+            return Err(DefconError::WritesDisabled(format!(
+                "{} doesn't exist and can't be created",
+                settings.report_page
+            ))
+            .into());
+        }
+        create_page(
+            client,
+            &settings.report_page,
+            &initial_report_text(settings, now)?,
+            &settings.messages.get("defcon-summary-create", &[]),
+        )
+        .await?;
+        info!("created {}", settings.report_page);
+        rev = latest_revision(client, &settings.report_page, rvprop).await?;
+    }
+    ReportRevision::from_json(&rev)
+}
+
+/// The last `limit` revisions of the report page, newest first.
+pub async fn report_history(
+    client: &mw::Client,
+    settings: &Settings,
+    limit: u32,
+) -> color_eyre::Result<Vec<ReportRevision>> {
+    let limit = limit.to_string();
+    let q = [
+        ("action", "query"),
+        ("prop", "revisions"),
+        ("titles", &settings.report_page),
+        ("rvprop", "ids|timestamp|user|content"),
+        ("rvslots", "main"),
+        ("rvlimit", &limit),
+    ];
+    let res = api_get(client, &q).await?;
+    let revisions = res["query"]["pages"][0]["revisions"]
+        .as_array()
+        .map_or(&[][..], |revisions| &revisions[..]);
+    revisions.iter().map(ReportRevision::from_json).collect()
+}
+
+/// When the level on the report page last changed, going by its last
+/// `LEVEL_HISTORY_REVISIONS` revisions: the time of the oldest of the newest
+/// ones showing the current level. If they all show it, that's the time of
+/// the oldest, which is later than the change.
+pub async fn level_changed_at(
+    client: &mw::Client,
+    settings: &Settings,
+) -> color_eyre::Result<Option<DateTime<Utc>>> {
+    let revisions = report_history(client, settings, LEVEL_HISTORY_REVISIONS).await?;
+    let level = match revisions.first() {
+        Some(rev) => rev.level,
+        None => return Ok(None),
+    };
+    Ok(revisions
+        .iter()
+        .take_while(|rev| rev.level == level)
+        .last()
+        .and_then(|rev| rev.timestamp))
+}
+
+/// The wikitext of a new report page created at `now`, without a level until
+/// the first run sets one.
+pub fn initial_report_text(settings: &Settings, now: DateTime<Utc>) -> color_eyre::Result<String> {
+    let info = settings.messages.get("defcon-info-initial", &[]);
+    let metrics = Metrics::new();
+    let vars = ReportVars::initial(info, now, &metrics);
+    settings.report_format.render(&vars)
+}
+
+/// The wikitext of a page showing `update` in `format`.
+pub fn report_text(
+    settings: &Settings,
+    format: &ReportFormat,
+    update: &LevelUpdate<'_>,
+) -> color_eyre::Result<String> {
+    let rpm = format!("{:.2}", update.rpm);
+    let mut info = match update.forecast {
+        Some(forecast) => settings
+            .messages
+            .get("defcon-info-forecast", &[&rpm, &format!("{:.2}", forecast)]),
+        None => settings.messages.get("defcon-info", &[&rpm]),
+    };
+    if let Some(concentration) = update.concentration {
+        info.push_str(&settings.messages.get(
+            "defcon-info-concentration",
+            &[
+                &concentration.source,
+                &concentration.reverts.to_string(),
+                &format!("{:.0}", concentration.share * 100.0),
+            ],
+        ));
+    }
+    format.render(&ReportVars::new(update, info))
+}
+
+/// The summary of the edit publishing `update`: `summary_template` with
+/// `{level}`, `{rpm}`, `{severity}` and `{trend}` filled in, or the
+/// `defcon-summary` message.
+pub fn edit_summary(settings: &Settings, update: &LevelUpdate<'_>) -> String {
+    let level = update.level.to_string();
+    let rpm = format!("{:.2}", update.rpm);
+    let trend = update.trend.to_string();
+    match &settings.summary_template {
+        Some(template) => template
+            .replace("{level}", &level)
+            .replace("{rpm}", &rpm)
+            .replace("{severity}", &format!("{:.0}", update.severity))
+            .replace("{trend}", &trend),
+        None => settings
+            .messages
+            .get("defcon-summary", &[&level, &rpm, &trend]),
+    }
+}
+
+/// Fail with a `DefconError` if `res` has an `error`.
+fn check_response(res: &Value) -> color_eyre::Result<()> {
+    let error = match res.get("error") {
+        Some(error) => error,
+        None => return Ok(()),
+    };
+    let code = error["code"].as_str().unwrap_or("unknown").to_owned();
+    let info = error["info"].as_str().unwrap_or("").to_owned();
+    if code == "editconflict" {
+        return Err(DefconError::EditConflict(info).into());
+    }
+    Err(DefconError::Api { code, info }.into())
+}
+
+/// Why the wiki won't take an edit, if `e` is a protected page or the wiki
+/// being read-only.
+pub fn uneditable_reason(e: &color_eyre::Report) -> Option<String> {
+    let (code, info) = match DefconError::find(e)? {
+        DefconError::Api { code, info } => (code, info),
+        _ => return None,
+    };
+    match code.as_str() {
+        "protectedpage" | "protectednamespace" => Some(format!("the page is protected ({info})")),
+        "cascadeprotected" => Some(format!("the page is cascade-protected ({info})")),
+        "readonly" => Some(format!("the wiki is read-only ({info})")),
+        _ => None,
+    }
+}
+
+/// Whether `e` is the API refusing an edit because the page changed since
+/// `baserevid`.
+pub fn is_edit_conflict(e: &color_eyre::Report) -> bool {
+    matches!(DefconError::find(e), Some(DefconError::EditConflict(_)))
+}
+
+/// Replace the text of `title`. With a `baserevid`, the edit fails with an
+/// edit conflict if the page changed in the meantime.
+pub async fn edit_page(
+    client: &mw::Client,
+    title: &str,
+    text: &str,
+    summary: &str,
+    baserevid: Option<u64>,
+) -> color_eyre::Result<()> {
+    let token = csrf_token(client).await?;
+    let baserevid = baserevid.map(|revid| revid.to_string());
+    let mut q = vec![
+        ("action", "edit"),
+        ("title", title),
+        ("summary", summary),
+        ("text", text),
+        ("token", &token),
+    ];
+    if let Some(baserevid) = &baserevid {
+        q.push(("baserevid", baserevid));
+    }
+
+    let res = api_edit(client, &q).await?;
+    check_response(&res)
+}
+
+/// Create `title` with `text`, failing if it already exists.
+pub async fn create_page(
+    client: &mw::Client,
+    title: &str,
+    text: &str,
+    summary: &str,
+) -> color_eyre::Result<()> {
+    let token = csrf_token(client).await?;
+    let q = [
+        ("action", "edit"),
+        ("title", title),
+        ("summary", summary),
+        ("text", text),
+        ("createonly", "1"),
+        ("token", &token),
+    ];
+    let res = api_edit(client, &q).await?;
+    check_response(&res)
+}
+
+/// Add a new section to `title`, creating the page if needed.
+pub async fn add_section(
+    client: &mw::Client,
+    title: &str,
+    heading: &str,
+    text: &str,
+    summary: &str,
+) -> color_eyre::Result<()> {
+    let token = csrf_token(client).await?;
+    let q = [
+        ("action", "edit"),
+        ("title", title),
+        ("section", "new"),
+        ("sectiontitle", heading),
+        ("summary", summary),
+        ("text", text),
+        ("token", &token),
+    ];
+
+    let res = api_edit(client, &q).await?;
+    check_response(&res)
+}
+
+/// Append `text` to the end of `title`.
+pub async fn append_text(
+    client: &mw::Client,
+    title: &str,
+    text: &str,
+    summary: &str,
+) -> color_eyre::Result<()> {
+    let token = csrf_token(client).await?;
+    let q = [
+        ("action", "edit"),
+        ("title", title),
+        ("summary", summary),
+        ("appendtext", text),
+        ("token", &token),
+    ];
+
+    let res = api_edit(client, &q).await?;
+    check_response(&res)
+}
+
+/// Titles of the pages transcluding `title`.
+pub async fn transclusions(client: &mw::Client, title: &str) -> color_eyre::Result<Vec<String>> {
+    let query = [
+        ("action", "query"),
+        ("list", "embeddedin"),
+        ("eititle", title),
+        ("eilimit", "max"),
+    ];
+    #[derive(serde::Deserialize)]
+    struct Page {
+        title: String,
+    }
+    #[derive(serde::Deserialize)]
+    struct EmbeddedIn {
+        embeddedin: Vec<Page>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Res {
+        query: EmbeddedIn,
+    }
+    query_all(client, &query, |res: Res| {
+        Ok(res
+            .query
+            .embeddedin
+            .into_iter()
+            .map(|page| page.title)
+            .collect())
+    })
+    .await
+}
+
+/// Purge `titles` and update their links tables, so that they show what they
+/// transclude as it is now.
+pub async fn purge(client: &mw::Client, titles: &[String]) -> color_eyre::Result<()> {
+    // the most titles a request may have without apihighlimits
+    for chunk in titles.chunks(50) {
+        let titles = chunk.join("|");
+        let q = [
+            ("action", "purge"),
+            ("titles", &titles),
+            ("forcelinkupdate", "1"),
+        ];
+        let res = api_post(client, &q).await?;
+        check_response(&res)?;
+    }
+    Ok(())
+}
+
+/// Replace `title` with `value`, as a page with the JSON content model.
+pub async fn edit_json(
+    client: &mw::Client,
+    title: &str,
+    value: &Value,
+    summary: &str,
+) -> color_eyre::Result<()> {
+    let token = csrf_token(client).await?;
+    let text = serde_json::to_string_pretty(value)?;
+    let q = [
+        ("action", "edit"),
+        ("title", title),
+        ("summary", summary),
+        ("text", &text),
+        ("contentmodel", "json"),
+        ("contentformat", "application/json"),
+        ("token", &token),
+    ];
+
+    let res = api_edit(client, &q).await?;
+    check_response(&res)
+}
+
+/// The URL of `title` on the wiki of `settings`, via `index.php` next to the
+/// API.
+pub fn index_url(settings: &Settings, title: &str) -> String {
+    let base = settings.api_url.trim_end_matches("api.php");
+    format!("{base}index.php?title={}", title.replace(' ', "_"))
+}
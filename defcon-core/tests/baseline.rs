@@ -0,0 +1,58 @@
+//! The usual RPM for each hour of the week, and the `relative` metric
+//! measured against it.
+
+mod common;
+
+use chrono::prelude::*;
+use defcon_core::baseline::{apply, hour_of_week, Baseline, BaselineSettings};
+use defcon_core::Metrics;
+
+#[test]
+fn hours_count_from_monday() {
+    let at = |d, h, m, s| Utc.with_ymd_and_hms(2024, 1, d, h, m, s).unwrap();
+    // 2024-01-01 is a Monday
+    assert_eq!(hour_of_week(at(1, 0, 30, 0)), 0);
+    assert_eq!(hour_of_week(at(2, 5, 0, 0)), 29);
+    assert_eq!(hour_of_week(at(7, 23, 59, 59)), 167);
+}
+
+#[test]
+fn the_first_sample_is_the_baseline() {
+    let mut baseline = Baseline::default();
+    // nothing to compare to yet
+    assert_eq!(baseline.relative(3, 10.0), 1.0);
+    baseline.observe(3, 4.0, 0.1);
+    assert_eq!(baseline.get(3), Some(4.0));
+    baseline.observe(3, 14.0, 0.1);
+    assert_eq!(baseline.get(3), Some(5.0));
+    assert_eq!(baseline.relative(3, 10.0), 2.0);
+    assert_eq!(baseline.get(4), None);
+}
+
+#[test]
+fn quiet_hours_are_floored() {
+    let mut baseline = Baseline::default();
+    baseline.observe(0, 0.1, 0.1);
+    assert_eq!(baseline.relative(0, 1.0), 2.0);
+}
+
+#[test]
+fn apply_saves_only_when_asked() {
+    let settings = BaselineSettings {
+        file: common::temp_path("baseline.json"),
+        alpha: 0.5,
+    };
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let relative = |rpm, save| {
+        let mut metrics = Metrics::new();
+        metrics.insert("rpm".to_owned(), rpm);
+        apply(&settings, &mut metrics, now, save).unwrap();
+        metrics["relative"]
+    };
+    assert_eq!(relative(2.0, true), 1.0);
+    assert_eq!(relative(4.0, false), 2.0);
+    // the baseline is now halfway between 2 and 4
+    assert_eq!(relative(4.0, true), 2.0);
+    assert_eq!(relative(6.0, false), 2.0);
+    std::fs::remove_file(&settings.file).unwrap();
+}
@@ -0,0 +1,55 @@
+//! `{{bots}}` and `{{nobots}}` on pages the bot would edit.
+
+use defcon_core::wiki::{bot_excluded, has_bots_template};
+
+const USER: &str = "DefconBot";
+
+#[test]
+fn follows_the_exclusion_templates() {
+    // (page text, whether it keeps DefconBot out)
+    let cases = [
+        ("level = 3", false),
+        ("{{nobots}}", true),
+        ("{{NoBots}}", true),
+        ("before {{nobots}} after", true),
+        ("{{bots}}", false),
+        ("{{bots|allow=all}}", false),
+        ("{{bots|allow=none}}", true),
+        ("{{bots|allow=DefconBot}}", false),
+        ("{{bots|allow=OtherBot}}", true),
+        ("{{bots|allow=OtherBot,DefconBot}}", false),
+        ("{{bots|deny=all}}", true),
+        ("{{bots|deny=none}}", false),
+        ("{{bots|deny=DefconBot}}", true),
+        ("{{bots|deny=OtherBot}}", false),
+        ("{{bots|deny=OtherBot, DefconBot}}", true),
+        ("{{ bots | deny = DefconBot }}", true),
+        ("{{bots|deny=defconbot}}", true),
+        ("{{bots|optout=all}}", false),
+    ];
+    for (text, excluded) in cases {
+        assert_eq!(bot_excluded(text, USER), excluded, "{text}");
+    }
+}
+
+#[test]
+fn names_match_with_underscores_for_spaces() {
+    assert!(bot_excluded("{{bots|deny=Defcon_Bot}}", "Defcon Bot"));
+    assert!(bot_excluded("{{bots|deny=Defcon Bot}}", "Defcon_Bot"));
+    assert!(!bot_excluded("{{bots|allow=Defcon_Bot}}", "Defcon Bot"));
+}
+
+#[test]
+fn only_the_templates_themselves_count() {
+    let cases = [
+        ("{{nobots}}", true),
+        ("{{bots|deny=all}}", true),
+        ("{{botsx}}", false),
+        ("{{Bots-other}}", false),
+        ("nobots", false),
+    ];
+    for (text, found) in cases {
+        assert_eq!(has_bots_template(text), found, "{text}");
+    }
+    assert!(!bot_excluded("{{botsx|deny=all}}", USER));
+}
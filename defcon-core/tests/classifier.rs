@@ -0,0 +1,98 @@
+//! Keyword matching on edit summaries: outside of ASCII, as on
+//! other-language wikis, and around markup that shouldn't count.
+
+use defcon_core::classifier::{fold_case, strip_markup, strip_section_markers};
+use defcon_core::{Classifier, Keywords};
+
+fn classifier(vandalism: &[&str], not_vandalism: &[&str]) -> Classifier {
+    let keywords = Keywords {
+        vandalism: vandalism.iter().map(|&kwd| kwd.to_owned()).collect(),
+        not_vandalism: not_vandalism.iter().map(|&kwd| kwd.to_owned()).collect(),
+    };
+    Classifier::new(&keywords).unwrap()
+}
+
+#[test]
+fn folds_cyrillic() {
+    let classifier = classifier(&["откат вандализма"], &["не вандализм"]);
+    assert!(classifier.is_revert_of_vandalism("ОТКАТ ВАНДАЛИЗМА участника 1.2.3.4"));
+    assert!(classifier.is_revert_of_vandalism("Откат Вандализма"));
+    assert!(!classifier.is_revert_of_vandalism("Откат вандализма? НЕ ВАНДАЛИЗМ"));
+    assert!(!classifier.is_revert_of_vandalism("ОТКАТ правки"));
+}
+
+#[test]
+fn folds_greek_including_final_sigma() {
+    let classifier = classifier(&["βανδαλισμός"], &[]);
+    assert!(classifier.is_revert_of_vandalism("ΑΝΑΊΡΕΣΗ: ΒΑΝΔΑΛΙΣΜΌΣ"));
+    assert!(classifier.is_revert_of_vandalism("Βανδαλισμός"));
+    assert!(classifier.is_revert_of_vandalism("ΒΑΝΔΑΛΙΣΜΌΣ από IP"));
+    assert_eq!(fold_case("ΒΑΝΔΑΛΙΣΜΌΣ"), fold_case("βανδαλισμός"));
+}
+
+#[test]
+fn folds_turkish_dotted_and_dotless_i() {
+    let classifier = classifier(&["geri alındı", "vandalizm"], &["kaynaksız"]);
+    assert!(classifier.is_revert_of_vandalism("GERİ ALINDI"));
+    assert!(classifier.is_revert_of_vandalism("Geri alindi"));
+    assert!(classifier.is_revert_of_vandalism("VANDALİZM"));
+    assert!(!classifier.is_revert_of_vandalism("Vandalizm değil, KAYNAKSIZ"));
+    assert_eq!(fold_case("İSTANBUL"), fold_case("istanbul"));
+}
+
+#[test]
+fn still_folds_ascii() {
+    let classifier = classifier(&["rvv"], &["good faith"]);
+    assert!(classifier.is_revert_of_vandalism("RVV"));
+    assert!(!classifier.is_revert_of_vandalism("Rvv, GOOD FAITH though"));
+}
+
+#[test]
+fn ignores_usernames_in_links() {
+    let classifier = classifier(&["undid"], &["self"]);
+    assert!(classifier.is_revert_of_vandalism(
+        "Undid revision 123 by [[Special:Contributions/Selfie99|Selfie99]] \
+         ([[User talk:Selfie99|talk]])"
+    ));
+    assert!(!classifier.is_revert_of_vandalism("Undid my own edit, self-revert"));
+}
+
+#[test]
+fn keeps_the_text_of_other_links() {
+    let classifier = classifier(&["lta"], &[]);
+    assert!(classifier.is_revert_of_vandalism("rv [[WP:LTA|LTA]]"));
+    assert!(classifier.is_revert_of_vandalism("rv [[Wikipedia:LTA]]"));
+}
+
+#[test]
+fn strips_templates_and_urls() {
+    assert_eq!(
+        strip_markup("rv {{uw-vandalism1}} see https://example.org/self"),
+        "rv   see  "
+    );
+    assert_eq!(
+        strip_markup("[https://example.org/abuse abuse log] [[User:Foo|Foo]]"),
+        "  "
+    );
+}
+
+#[test]
+fn strips_each_section_marker() {
+    assert_eq!(
+        strip_section_markers("/* Early life */ rv /* Self-published */ x"),
+        "  rv   x"
+    );
+    assert_eq!(strip_section_markers("/* A */"), " ");
+    assert_eq!(strip_section_markers("rv /* unclosed"), "rv /* unclosed");
+    let classifier = classifier(&["rv", "revert"], &["self"]);
+    assert!(classifier.is_revert_of_vandalism("/* Early life */ rv /* Self-published */"));
+    assert!(classifier.is_revert_of_vandalism("/* A */rv/* B */"));
+}
+
+#[test]
+fn section_marker_alone_is_not_a_revert() {
+    let classifier = classifier(&["revert"], &[]);
+    assert!(!classifier.is_revert_of_vandalism("/* Reverts */"));
+    assert!(!classifier.is_revert_of_vandalism("/* Reverts */ /* Reverted edits */"));
+    assert!(!classifier.is_revert_of_vandalism("/**/"));
+}
@@ -0,0 +1,70 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+use defcon_core::{load_profiles, LevelUpdate, Metrics, Settings, Trend};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, query_param};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+pub const REPORT_PAGE: &str = "User:DefconBot/level";
+
+/// The one profile in `toml`, on top of the keys every profile needs.
+pub fn load(toml: &str) -> color_eyre::Result<Settings> {
+    let toml = format!("oauth_token = \"secret\"\nreport_page = \"{REPORT_PAGE}\"\n{toml}");
+    let config = config::Config::builder()
+        .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+        .build()?;
+    Ok(load_profiles(config)?.remove(0).1)
+}
+
+/// Like `load`, for settings that are known to be valid.
+pub fn settings(toml: &str) -> Settings {
+    load(toml).unwrap()
+}
+
+/// A path in the temporary directory that no other test process uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("defcon-test-{}-{name}", std::process::id()))
+}
+
+/// A change from level 4 to 3 at noon on 2024-01-01, with `metrics`.
+pub fn update(metrics: &Metrics) -> LevelUpdate<'_> {
+    LevelUpdate {
+        previous_level: 4,
+        level: 3,
+        rpm: 4.5,
+        severity: 45.0,
+        trend: Trend::Rising,
+        forecast: None,
+        concentration: None,
+        metrics,
+        time: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        base_revid: 1,
+    }
+}
+
+/// A GET request to the API with `key=value` in the query.
+pub fn get(key: &str, value: &str) -> MockBuilder {
+    Mock::given(method("GET")).and(query_param(key.to_owned(), value.to_owned()))
+}
+
+pub fn ok(body: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(body)
+}
+
+/// Answer the check of the bot account made when logging in.
+pub async fn mount_login(server: &MockServer) {
+    get("meta", "userinfo")
+        .respond_with(ok(json!({
+            "query": { "userinfo": { "id": 1, "name": "DefconBot", "rights": ["bot"] } }
+        })))
+        .mount(server)
+        .await;
+}
+
+pub fn api_url(server: &MockServer) -> String {
+    format!("{}/w/api.php", server.uri())
+}
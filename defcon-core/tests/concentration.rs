@@ -0,0 +1,88 @@
+//! Noticing when most reverts undo edits from one account or IP range.
+
+use defcon_core::concentration::{find, source, ConcentrationSettings};
+use defcon_core::rpm::{RecentEdits, Revert};
+use serde_json::json;
+
+fn settings(settings: serde_json::Value) -> ConcentrationSettings {
+    serde_json::from_value(settings).unwrap()
+}
+
+/// Reverts of edits by `reverted`, `None` for one whose user isn't known.
+fn recent(reverted: &[Option<&str>]) -> RecentEdits {
+    let reverts = reverted
+        .iter()
+        .enumerate()
+        .map(|(i, reverted)| {
+            serde_json::from_value::<Revert>(json!({
+                "title": format!("Page {i}"),
+                "user": "Patroller",
+                "reverted": reverted,
+            }))
+            .unwrap()
+        })
+        .collect();
+    RecentEdits {
+        edits: reverted.len(),
+        reverts,
+        minutes: 5.0,
+    }
+}
+
+#[test]
+fn groups_addresses_into_ranges() {
+    let defaults = settings(json!({}));
+    assert_eq!(source(&defaults, "192.0.2.123"), "192.0.2.0/24");
+    assert_eq!(
+        source(&defaults, "2001:db8:1:2:3:4:5:6"),
+        "2001:db8:1:2::/64"
+    );
+    assert_eq!(source(&defaults, "Vandal"), "Vandal");
+    let wide = settings(json!({ "ipv4_prefix": 16, "ipv6_prefix": 32 }));
+    assert_eq!(source(&wide, "192.0.2.123"), "192.0.0.0/16");
+    assert_eq!(source(&wide, "2001:db8:1:2:3:4:5:6"), "2001:db8::/32");
+    let all = settings(json!({ "ipv4_prefix": 0 }));
+    assert_eq!(source(&all, "192.0.2.123"), "0.0.0.0/0");
+}
+
+#[test]
+fn finds_the_source_of_most_reverts() {
+    let concentration = settings(json!({ "min_reverts": 3 }));
+    let recent = recent(&[
+        Some("192.0.2.1"),
+        Some("Someone"),
+        Some("192.0.2.77"),
+        None,
+        Some("192.0.2.200"),
+    ]);
+    let found = find(&concentration, &recent).unwrap();
+    assert_eq!(found.source, "192.0.2.0/24");
+    assert_eq!(found.reverts, 3);
+    assert_eq!(found.share, 0.6);
+
+    let concentration = settings(json!({ "min_reverts": 4 }));
+    assert!(find(&concentration, &recent).is_none());
+}
+
+#[test]
+fn a_minority_isnt_concentrated() {
+    let concentration = settings(json!({ "min_reverts": 3 }));
+    let recent = recent(&[
+        Some("Vandal"),
+        Some("Vandal"),
+        Some("Vandal"),
+        Some("A"),
+        Some("B"),
+        Some("C"),
+        None,
+    ]);
+    assert!(find(&concentration, &recent).is_none());
+}
+
+#[test]
+fn ties_go_to_the_first_source_by_name() {
+    let concentration = settings(json!({ "min_reverts": 1 }));
+    let found = find(&concentration, &recent(&[Some("B"), Some("A")])).unwrap();
+    assert_eq!(found.source, "A");
+    assert_eq!(found.share, 0.5);
+}
@@ -0,0 +1,76 @@
+//! Labeled edits for measuring the classifier: the TSV written for labeling
+//! and read back, and the scores computed from it.
+
+mod common;
+
+use chrono::prelude::*;
+use defcon_core::dataset::{evaluate, read_tsv, write_tsv, Labeled, Row};
+use defcon_core::Rules;
+
+#[test]
+fn reads_back_what_it_wrote_once_labeled() {
+    let rows = [Row {
+        rcid: 7,
+        time: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        title: "Page".to_owned(),
+        summary: "rv\tvandalism".to_owned(),
+        tags: vec!["mw-undo".to_owned(), "mw-rollback".to_owned()],
+        verdict: true,
+    }];
+    let mut tsv = Vec::new();
+    write_tsv(&rows, &mut tsv).unwrap();
+    let tsv = String::from_utf8(tsv).unwrap();
+    // not labeled yet
+    assert!(read_tsv(&tsv).unwrap().is_empty());
+
+    let labeled = read_tsv(&format!("{}1\n", tsv.strip_suffix('\n').unwrap())).unwrap();
+    assert_eq!(labeled.len(), 1);
+    assert_eq!(labeled[0].line, 2);
+    assert_eq!(labeled[0].summary, "rv vandalism");
+    assert_eq!(labeled[0].tags, ["mw-undo", "mw-rollback"]);
+    assert!(labeled[0].label);
+}
+
+#[test]
+fn reads_plain_summaries_and_labels() {
+    let labeled = read_tsv("rv vandalism\t1\ncopyedit\tno\n\nunlabeled\t\n").unwrap();
+    let read: Vec<(&str, bool)> = labeled
+        .iter()
+        .map(|edit| (edit.summary.as_str(), edit.label))
+        .collect();
+    assert_eq!(read, [("rv vandalism", true), ("copyedit", false)]);
+    assert!(read_tsv("rv vandalism\tmaybe\n").is_err());
+    assert!(read_tsv("rcid\tsummary\tverdict\n1\trv\t1\n").is_err());
+}
+
+#[test]
+fn scores_the_classifier() {
+    let settings = common::settings("");
+    let rules = Rules::new(&settings).unwrap();
+    let edit = |line, summary: &str, label| Labeled {
+        line,
+        summary: summary.to_owned(),
+        tags: Vec::new(),
+        label,
+    };
+    let labeled = [
+        edit(1, "rv vandalism", true),
+        edit(
+            2,
+            "Reverted edits by Vandal to last version by Patroller",
+            true,
+        ),
+        edit(3, "rv vandalism", false),
+        edit(4, "copyedit", false),
+        edit(5, "copyedit", true),
+    ];
+    let evaluation = evaluate(&settings, rules.classifier(), &labeled);
+    assert_eq!(evaluation.true_positives, 2);
+    assert_eq!(evaluation.true_negatives, 1);
+    let lines = |edits: &[&Labeled]| edits.iter().map(|edit| edit.line).collect::<Vec<_>>();
+    assert_eq!(lines(&evaluation.false_positives), [3]);
+    assert_eq!(lines(&evaluation.false_negatives), [5]);
+    assert_eq!(evaluation.precision(), 2.0 / 3.0);
+    assert_eq!(evaluation.recall(), 2.0 / 3.0);
+    assert!((evaluation.f1() - 2.0 / 3.0).abs() < 1e-9);
+}
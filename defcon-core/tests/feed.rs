@@ -0,0 +1,45 @@
+//! The Atom feed of published level changes.
+
+use chrono::prelude::*;
+use defcon_core::monitor::{atom_feed, record_change, LevelChange};
+
+fn change(wiki: &str, previous_level: u8, level: u8, h: u32, m: u32) -> LevelChange {
+    LevelChange {
+        wiki: wiki.to_owned(),
+        previous_level,
+        level,
+        rpm: 3.2,
+        time: Utc.with_ymd_and_hms(2024, 1, 1, h, m, 0).unwrap(),
+        link: "https://wiki.example/w/index.php?a=1&b=2".to_owned(),
+    }
+}
+
+// one test, as the changes are kept for the whole process
+#[test]
+fn lists_the_latest_changes_newest_first() {
+    record_change(change("enwiki", 0, 4, 12, 0));
+    record_change(change("R&D <wiki>", 4, 3, 12, 5));
+    let feed = atom_feed();
+    assert!(
+        feed.contains("<updated>2024-01-01T12:05:00Z</updated>"),
+        "{feed}"
+    );
+    let newest = feed
+        .find("<title>R&amp;D &lt;wiki&gt; is now at level 3 (was 4)</title>")
+        .unwrap();
+    // nothing was on the page before
+    let oldest = feed
+        .find("<title>enwiki is now at level 4</title>")
+        .unwrap();
+    assert!(newest < oldest);
+    assert!(feed.contains(r#"<link href="https://wiki.example/w/index.php?a=1&amp;b=2"/>"#));
+    assert!(feed.contains("<summary>Level 3 at 3.20 reverts per minute.</summary>"));
+    assert!(feed.ends_with("</feed>\n"));
+
+    for m in 0..50 {
+        record_change(change("enwiki", 3, 2, 13, m));
+    }
+    let feed = atom_feed();
+    assert_eq!(feed.matches("<entry>").count(), 50);
+    assert!(!feed.contains("was 4"));
+}
@@ -0,0 +1,49 @@
+//! Forecasting the RPM an hour ahead from the history of samples.
+
+mod common;
+
+use chrono::{prelude::*, Duration};
+use defcon_core::forecast::{forecast_rpm, holt};
+use defcon_core::history::{History, Sample};
+use defcon_core::Metrics;
+
+#[test]
+fn holt_needs_two_samples() {
+    assert_eq!(holt(&[], 1.0), None);
+    assert_eq!(holt(&[3.0], 1.0), None);
+}
+
+#[test]
+fn holt_follows_a_line() {
+    let series = [1.0, 2.0, 3.0, 4.0];
+    assert_eq!(holt(&series, 0.0), Some(4.0));
+    assert_eq!(holt(&series, 2.0), Some(6.0));
+    assert_eq!(holt(&[2.0; 5], 10.0), Some(2.0));
+    // a falling rate doesn't go below zero
+    assert_eq!(holt(&[6.0, 4.0, 2.0], 12.0), Some(0.0));
+}
+
+#[test]
+fn forecasts_from_the_last_hours() {
+    let path = common::temp_path("forecast-history");
+    let _ = std::fs::remove_file(&path);
+    let settings = common::settings("run_interval_mins = 5");
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let sample = |ago: Duration, rpm| Sample {
+        time: (now - ago).timestamp(),
+        rpm,
+        level: 5,
+        metrics: Metrics::new(),
+    };
+    {
+        let history = History::open(&path, "enwiki").unwrap();
+        // too old to count
+        history.record(&sample(Duration::hours(7), 100.0)).unwrap();
+        history.record(&sample(Duration::minutes(10), 1.0)).unwrap();
+        history.record(&sample(Duration::minutes(5), 2.0)).unwrap();
+        // 1, 2 and now 3, twelve runs on
+        let forecast = forecast_rpm(&history, &settings, now, 3.0).unwrap();
+        assert_eq!(forecast, Some(15.0));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
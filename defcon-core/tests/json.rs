@@ -0,0 +1,45 @@
+//! The JSON subpage for gadgets and other bots.
+
+mod common;
+
+use defcon_core::publish::json::JsonPage;
+use defcon_core::{Metrics, Publisher};
+use serde_json::{json, Value};
+
+#[test]
+fn defaults_to_a_subpage_of_the_report_page() {
+    let settings = common::settings("");
+    let metrics = Metrics::new();
+    let update = common::update(&metrics);
+    let preview = JsonPage::new(None).preview(&settings, &update);
+    assert!(
+        preview.starts_with("edit User:DefconBot/level.json:\n"),
+        "{preview}"
+    );
+    let page = JsonPage::new(Some("Project:Defcon.json".to_owned()));
+    let preview = page.preview(&settings, &update);
+    assert!(
+        preview.starts_with("edit Project:Defcon.json:\n"),
+        "{preview}"
+    );
+}
+
+#[test]
+fn holds_the_level_and_metrics() {
+    let settings = common::settings("");
+    let mut metrics = Metrics::new();
+    metrics.insert("rpm".to_owned(), 4.5);
+    let preview = JsonPage::new(None).preview(&settings, &common::update(&metrics));
+    let (_, content) = preview.split_once('\n').unwrap();
+    let content: Value = serde_json::from_str(content).unwrap();
+    assert_eq!(
+        content,
+        json!({
+            "level": 3,
+            "rpm": 4.5,
+            "severity": 45.0,
+            "timestamp": "2024-01-01T12:00:00Z",
+            "metrics": { "rpm": 4.5 }
+        })
+    );
+}
@@ -0,0 +1,150 @@
+//! Turning a value into a level: the thresholds, the hysteresis margin and
+//! the dwell time after a change.
+
+mod common;
+
+use chrono::{prelude::*, Duration};
+use defcon_core::level::DEFAULT_THRESHOLDS;
+use defcon_core::run::dwelling;
+use defcon_core::{next_level, rpm_to_level, severity, Levels};
+use serde_json::json;
+
+fn levels(levels: &[u8]) -> Levels {
+    serde_json::from_value(json!(levels)).unwrap()
+}
+
+#[test]
+fn a_value_on_a_threshold_stays_below_it() {
+    let levels = Levels::default();
+    let level = |rpm| rpm_to_level(rpm, &DEFAULT_THRESHOLDS, &levels);
+    assert_eq!(level(0.0), 5);
+    assert_eq!(level(2.0), 5);
+    assert_eq!(level(2.01), 4);
+    assert_eq!(level(4.0), 4);
+    assert_eq!(level(8.0), 2);
+    assert_eq!(level(8.01), 1);
+    assert_eq!(level(1000.0), 1);
+}
+
+#[test]
+fn rejects_malformed_thresholds() {
+    assert!(common::load("thresholds = [1.0, 2.5, 5.0, 10.0]").is_ok());
+    // one per level but the most severe
+    assert!(common::load("thresholds = [2.0, 4.0, 6.0]").is_err());
+    assert!(common::load("thresholds = [2.0, 4.0, 4.0, 8.0]").is_err());
+    assert!(common::load("thresholds = [2.0, 1.0, 6.0, 8.0]").is_err());
+    assert!(common::load("thresholds = [-1.0, 4.0, 6.0, 8.0]").is_err());
+}
+
+#[test]
+fn levels_follow_the_scale() {
+    // a scale where higher is worse, with fewer levels
+    let levels = levels(&[1, 2, 3]);
+    let thresholds = [1.5, 3.0];
+    assert_eq!(rpm_to_level(1.5, &thresholds, &levels), 1);
+    assert_eq!(rpm_to_level(2.0, &thresholds, &levels), 2);
+    assert_eq!(rpm_to_level(3.5, &thresholds, &levels), 3);
+}
+
+#[test]
+fn rejects_malformed_levels() {
+    let load = |levels: &str| common::load(&format!("levels = {levels}\nthresholds = [1.5, 3.0]"));
+    assert!(load("[1, 2, 3]").is_ok());
+    // 0 stands for no level
+    assert!(load("[5, 4, 0]").is_err());
+    assert!(load("[3, 3, 1]").is_err());
+    assert!(load("[1]").is_err());
+}
+
+#[test]
+fn severity_splits_the_scale_evenly() {
+    let severity = |value| severity(value, &DEFAULT_THRESHOLDS);
+    assert_eq!(severity(0.0), 0.0);
+    // each threshold is the top of its level's fifth
+    assert_eq!(severity(2.0), 20.0);
+    assert_eq!(severity(3.0), 30.0);
+    assert_eq!(severity(8.0), 80.0);
+    // as far past the last threshold as that is past the one before
+    assert_eq!(severity(9.0), 90.0);
+    assert_eq!(severity(10.0), 100.0);
+    assert_eq!(severity(50.0), 100.0);
+    assert_eq!(severity(-1.0), 0.0);
+}
+
+/// The level after one at `curr_level`, with a margin of 0.5 RPM.
+fn next(curr_level: u8, rpm: f32) -> u8 {
+    next_level(
+        curr_level,
+        rpm,
+        &DEFAULT_THRESHOLDS,
+        &Levels::default(),
+        0.5,
+    )
+}
+
+#[test]
+fn hovering_within_the_margin_keeps_the_level() {
+    // up from 5 only past 2.5, back down from 4 only at 1.5 or less
+    for rpm in [1.9, 2.1, 2.4, 2.5] {
+        assert_eq!(next(5, rpm), 5, "{rpm}");
+    }
+    assert_eq!(next(5, 2.6), 4);
+    for rpm in [2.1, 1.9, 1.6] {
+        assert_eq!(next(4, rpm), 4, "{rpm}");
+    }
+    // on the threshold counts as under it, as it does without a margin
+    assert_eq!(next(4, 1.5), 5);
+    assert_eq!(next(4, 1.4), 5);
+}
+
+#[test]
+fn a_jump_goes_as_far_as_the_margin_allows() {
+    assert_eq!(next(5, 6.6), 2);
+    // within the margin of the threshold of level 2
+    assert_eq!(next(5, 6.4), 3);
+    assert_eq!(next(1, 1.0), 5);
+    assert_eq!(next(1, 4.4), 3);
+}
+
+#[test]
+fn no_margin_is_plain_thresholds() {
+    let levels = Levels::default();
+    for curr_level in levels.iter() {
+        for rpm in [0.0, 2.0, 2.01, 5.0, 8.0, 9.0] {
+            assert_eq!(
+                next_level(curr_level, rpm, &DEFAULT_THRESHOLDS, &levels, 0.0),
+                rpm_to_level(rpm, &DEFAULT_THRESHOLDS, &levels)
+            );
+        }
+    }
+}
+
+#[test]
+fn a_level_off_the_scale_has_no_margin() {
+    // nothing on the page yet, or something the scale doesn't have
+    for curr_level in [0, 9] {
+        assert_eq!(next(curr_level, 2.1), 4);
+        assert_eq!(next(curr_level, 1.9), 5);
+        assert_eq!(next(curr_level, 8.1), 1);
+    }
+}
+
+#[test]
+fn dwell_holds_back_single_steps_only() {
+    let settings = common::settings("min_dwell_mins = 15");
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let ago = |mins| Some(now - Duration::minutes(mins));
+    assert!(dwelling(&settings, ago(10), now, 5, 4));
+    assert!(dwelling(&settings, ago(10), now, 4, 5));
+    // two or more levels at once always go through
+    assert!(!dwelling(&settings, ago(10), now, 5, 3));
+    assert!(!dwelling(&settings, ago(1), now, 4, 1));
+    // the cooldown is over
+    assert!(!dwelling(&settings, ago(15), now, 5, 4));
+    assert!(!dwelling(&settings, ago(60), now, 5, 4));
+    // no change known, or none to make
+    assert!(!dwelling(&settings, None, now, 5, 4));
+    assert!(!dwelling(&settings, ago(10), now, 4, 4));
+    // no level on the page to step from
+    assert!(!dwelling(&settings, ago(10), now, 0, 4));
+}
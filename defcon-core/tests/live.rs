@@ -0,0 +1,63 @@
+//! Parsing the `text/event-stream` body of EventStreams.
+
+use defcon_core::live::SseParser;
+
+fn parse(body: &[u8]) -> Vec<String> {
+    SseParser::default().feed(body)
+}
+
+#[test]
+fn keeps_only_the_data() {
+    let body = b": ok\n\nevent: message\nid: [{\"offset\":1}]\ndata: {\"a\":1}\n\n";
+    assert_eq!(parse(body), ["{\"a\":1}"]);
+    // events with no data at all are dropped
+    assert!(parse(b"event: message\nid: 1\n\n: comment\n\n").is_empty());
+}
+
+#[test]
+fn joins_data_lines() {
+    assert_eq!(parse(b"data: one\ndata: two\n\n"), ["one\ntwo"]);
+    // only one space after the colon is stripped
+    assert_eq!(
+        parse(b"data:tight\n\ndata:  spaced\n\n"),
+        ["tight", " spaced"]
+    );
+}
+
+#[test]
+fn handles_crlf() {
+    assert_eq!(
+        parse(b"event: message\r\ndata: one\r\ndata: two\r\n\r\ndata: three\r\n\r\n"),
+        ["one\ntwo", "three"]
+    );
+}
+
+#[test]
+fn waits_for_the_end_of_an_event() {
+    let mut parser = SseParser::default();
+    assert!(parser.feed(b"data: {\"title\":").is_empty());
+    assert!(parser.feed(b"\"Foo\"}\n").is_empty());
+    assert_eq!(parser.feed(b"\ndata: next"), ["{\"title\":\"Foo\"}"]);
+    assert_eq!(parser.feed(b"\n\n"), ["next"]);
+}
+
+#[test]
+fn splits_anywhere() {
+    let body = "data: {\"title\":\"Übersicht\"}\r\n\r\ndata: 2\r\n\r\n".as_bytes();
+    for at in 0..body.len() {
+        let (a, b) = body.split_at(at);
+        let mut parser = SseParser::default();
+        let mut events = parser.feed(a);
+        events.extend(parser.feed(b));
+        assert_eq!(events, ["{\"title\":\"Übersicht\"}", "2"], "{at}");
+    }
+}
+
+#[test]
+fn a_new_parser_starts_clean() {
+    // what a reconnect does with the event cut off by the old connection
+    let mut parser = SseParser::default();
+    assert!(parser.feed(b"data: cut off\ndata: mid").is_empty());
+    let mut parser = SseParser::default();
+    assert_eq!(parser.feed(b"data: fresh\n\n"), ["fresh"]);
+}
@@ -0,0 +1,21 @@
+//! The `lock_file` keeping runs of a profile from overlapping.
+
+mod common;
+
+use defcon_core::lock::RunLock;
+
+#[test]
+fn only_one_holder_at_a_time() {
+    let path = common::temp_path("run.lock");
+    let lock = RunLock::acquire(&path)
+        .unwrap()
+        .expect("nobody holds it yet");
+    let pid = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(pid.trim(), std::process::id().to_string());
+    assert!(RunLock::acquire(&path).unwrap().is_none());
+    drop(lock);
+    let lock = RunLock::acquire(&path).unwrap();
+    assert!(lock.is_some(), "released when dropped");
+    drop(lock);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,64 @@
+//! The Scribunto data module, run through Lua to check that what it returns
+//! is what was written.
+
+mod common;
+
+use defcon_core::publish::lua::LuaModule;
+use defcon_core::{Metrics, Publisher};
+
+/// Write a module for `metrics` at level 3 and run it.
+fn round_trip(lua: &mlua::Lua, metrics: &Metrics) -> mlua::Table {
+    let update = common::update(metrics);
+    let module = LuaModule::new("Module:Defcon/data".to_string());
+    let preview = module.preview(&common::settings(""), &update);
+    let (first, text) = preview.split_once('\n').unwrap();
+    assert_eq!(first, "edit Module:Defcon/data:");
+    lua.load(text).eval().unwrap()
+}
+
+#[test]
+fn returns_the_level() {
+    let lua = mlua::Lua::new();
+    let table = round_trip(&lua, &Metrics::new());
+    assert_eq!(table.get::<u8>("level").unwrap(), 3);
+    assert_eq!(table.get::<f64>("rpm").unwrap(), 4.5);
+    assert_eq!(table.get::<f64>("severity").unwrap(), 45.0);
+    assert_eq!(
+        table.get::<String>("timestamp").unwrap(),
+        "2024-01-01T12:00:00Z"
+    );
+}
+
+#[test]
+fn escapes_metric_names() {
+    let names = [
+        "plain",
+        "say \"hi\"",
+        "back\\slash",
+        "trailing\\",
+        "\\\"",
+        "new\nline",
+        "cr\rlf\r\n",
+        "tab\there",
+        "\u{0}nul",
+        "\u{7f}del",
+        "\u{1b}[31mred",
+        // a decimal escape is always three digits, so these stay apart
+        "\u{1}23",
+        "]] end",
+        "ünïcödé 日本語 🚨",
+    ];
+    let metrics: Metrics = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.to_string(), i as f64 + 0.5))
+        .collect();
+    let lua = mlua::Lua::new();
+    let table = round_trip(&lua, &metrics);
+    let read: mlua::Table = table.get("metrics").unwrap();
+    let read: Metrics = read
+        .pairs::<String, f64>()
+        .collect::<mlua::Result<_>>()
+        .unwrap();
+    assert_eq!(read, metrics);
+}
@@ -0,0 +1,233 @@
+//! Whole runs against a mock MediaWiki API serving canned recent changes and
+//! report page revisions, so that nothing touches a real wiki.
+
+mod common;
+
+use chrono::prelude::*;
+use common::{get, ok, REPORT_PAGE};
+use defcon_core::clock::FixedClock;
+use defcon_core::run::run_once;
+use defcon_core::{metrics, publish, wiki, Rules, Settings};
+use serde_json::{json, Value};
+use wiremock::matchers::{body_string_contains, method};
+use wiremock::{Mock, MockServer};
+
+/// A wiki whose report page shows `level` and whose recent changes within
+/// the last five minutes are `changes`.
+struct MockWiki {
+    server: MockServer,
+}
+
+impl MockWiki {
+    async fn start(level: u8, changes: Vec<Value>) -> Self {
+        let server = MockServer::start().await;
+        common::mount_login(&server).await;
+        get("meta", "tokens")
+            .respond_with(ok(
+                json!({ "query": { "tokens": { "csrftoken": "token+\\" } } }),
+            ))
+            .mount(&server)
+            .await;
+        get("list", "recentchanges")
+            .respond_with(ok(json!({ "query": { "recentchanges": changes } })))
+            .mount(&server)
+            .await;
+        get("prop", "revisions")
+            .respond_with(ok(report(100, level)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("action=edit"))
+            .respond_with(ok(
+                json!({ "edit": { "result": "Success", "newrevid": 101 } }),
+            ))
+            .mount(&server)
+            .await;
+        MockWiki { server }
+    }
+
+    fn settings(&self) -> Settings {
+        self.settings_with("")
+    }
+
+    /// The settings with `toml` added at the end.
+    fn settings_with(&self, toml: &str) -> Settings {
+        common::settings(&format!(
+            "api_url = \"{}\"\nwindow_mins = 5\nbucket_mins = 5\n{toml}",
+            common::api_url(&self.server)
+        ))
+    }
+
+    /// The texts of the edits made to the wiki.
+    async fn edits(&self) -> Vec<String> {
+        let requests = self.server.received_requests().await.unwrap();
+        requests
+            .iter()
+            .filter(|request| request.method.as_str() == "POST")
+            .filter_map(|request| {
+                let body = String::from_utf8_lossy(&request.body);
+                let form = reqwest::Url::parse(&format!("http://form/?{body}")).ok()?;
+                let pairs: Vec<(String, String)> = form.query_pairs().into_owned().collect();
+                let is_edit = pairs
+                    .iter()
+                    .any(|(key, value)| key == "action" && value == "edit");
+                let (_, text) = pairs.into_iter().find(|(key, _)| key == "text")?;
+                is_edit.then_some(text)
+            })
+            .collect()
+    }
+}
+
+/// The report page at `revid`, showing `level`.
+fn report(revid: u64, level: u8) -> Value {
+    json!({
+        "query": { "pages": [{
+            "title": REPORT_PAGE,
+            "revisions": [{
+                "revid": revid,
+                "user": "Someone",
+                "timestamp": "2023-01-01T00:00:00Z",
+                "slots": { "main": { "content": format!(
+                    "{{{{#switch: {{{{{{1}}}}}}\n| level = {level}\n| sign = ~~~~~\n\
+                     | info = 1.00 RPM\n}}}}"
+                ) } }
+            }]
+        }] }
+    })
+}
+
+/// `count` reverts of vandalism made a minute and a half before `now`.
+fn reverts(count: u64) -> Vec<Value> {
+    (0..count)
+        .map(|i| {
+            json!({
+                "rcid": 1000 + i,
+                "timestamp": "2024-01-01T00:01:00Z",
+                "title": format!("Page {i}"),
+                "user": "Patroller",
+                "comment": "rv vandalism",
+                "tags": []
+            })
+        })
+        .collect()
+}
+
+/// Run once at 00:02:30, two and a half minutes into the newest bucket, so
+/// that the window is 7.5 minutes long.
+async fn run(wiki: &MockWiki) {
+    run_with(wiki, wiki.settings()).await;
+}
+
+async fn run_with(wiki: &MockWiki, settings: Settings) {
+    let clock = FixedClock(Utc.with_ymd_and_hms(2024, 1, 1, 0, 2, 30).unwrap());
+    let client = wiki::connect(&settings.api_url, &settings.oauth_token)
+        .await
+        .unwrap();
+    let mut rules = Rules::new(&settings).unwrap();
+    let sources = metrics::sources(&settings).unwrap();
+    let publishers = publish::publishers(&settings).unwrap();
+    run_once(
+        &client,
+        &settings,
+        &clock,
+        &mut rules,
+        &sources,
+        &publishers,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn publishes_a_level_change() {
+    // 24 reverts in 7.5 minutes is 3.2 RPM, level 4
+    let wiki = MockWiki::start(5, reverts(24)).await;
+    run(&wiki).await;
+    let edits = wiki.edits().await;
+    assert_eq!(edits.len(), 1);
+    assert!(edits[0].contains("level = 4"), "{}", edits[0]);
+    assert!(edits[0].contains("3.20 RPM"), "{}", edits[0]);
+}
+
+#[tokio::test]
+async fn leaves_an_unchanged_level_alone() {
+    let wiki = MockWiki::start(4, reverts(24)).await;
+    run(&wiki).await;
+    assert!(wiki.edits().await.is_empty());
+}
+
+#[tokio::test]
+async fn quiet_wiki_is_level_five() {
+    let wiki = MockWiki::start(3, Vec::new()).await;
+    run(&wiki).await;
+    let edits = wiki.edits().await;
+    assert_eq!(edits.len(), 1);
+    assert!(edits[0].contains("level = 5"), "{}", edits[0]);
+}
+
+#[tokio::test]
+async fn declines_a_level_set_during_an_edit_conflict() {
+    let wiki = MockWiki::start(5, reverts(24)).await;
+    // someone sets level 4 between reading the page and editing it
+    get("prop", "revisions")
+        .respond_with(ok(report(100, 5)))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&wiki.server)
+        .await;
+    get("prop", "revisions")
+        .respond_with(ok(report(102, 4)))
+        .with_priority(2)
+        .mount(&wiki.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("action=edit"))
+        .respond_with(ok(json!({
+            "error": { "code": "editconflict", "info": "Edit conflict." }
+        })))
+        .with_priority(1)
+        .mount(&wiki.server)
+        .await;
+    let settings =
+        wiki.settings_with("[[publishers]]\ntype = \"wiki\"\n\n[[publishers]]\ntype = \"json\"");
+    run_with(&wiki, settings).await;
+    // the JSON page isn't written with the level that was declined
+    let edits = wiki.edits().await;
+    assert_eq!(edits.len(), 1);
+    assert!(edits[0].contains("level = 4"), "{}", edits[0]);
+}
+
+#[tokio::test]
+async fn reads_a_published_level_back_from_the_state() {
+    let wiki = MockWiki::start(5, reverts(24)).await;
+    get("prop", "revisions")
+        .respond_with(ok(report(100, 5)))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&wiki.server)
+        .await;
+    // our edit, with a template that doesn't show the level
+    get("prop", "revisions")
+        .respond_with(ok(json!({
+            "query": { "pages": [{
+                "title": REPORT_PAGE,
+                "revisions": [{
+                    "revid": 101,
+                    "user": "DefconBot",
+                    "timestamp": "2024-01-01T00:02:30Z",
+                    "slots": { "main": { "content": "{{Defcon banner}}" } }
+                }]
+            }] }
+        })))
+        .with_priority(2)
+        .mount(&wiki.server)
+        .await;
+    let path = common::temp_path("published.json");
+    let _ = std::fs::remove_file(&path);
+    let toml = format!("state_file = {:?}", path.display().to_string());
+    run_with(&wiki, wiki.settings_with(&toml)).await;
+    run_with(&wiki, wiki.settings_with(&toml)).await;
+    std::fs::remove_file(&path).unwrap();
+    // the second run knows the page is at level 4, not at none
+    assert_eq!(wiki.edits().await.len(), 1);
+}
@@ -0,0 +1,75 @@
+//! The n-gram model classifying edit summaries with `detection = "model"`.
+
+mod common;
+
+use std::path::PathBuf;
+
+use defcon_core::classifier::is_revert;
+use defcon_core::model::Model;
+use defcon_core::Rules;
+
+/// Write `json` to a model file named `name`.
+fn model_file(name: &str, json: &str) -> PathBuf {
+    let path = common::temp_path(name);
+    std::fs::write(&path, json).unwrap();
+    path
+}
+
+const MODEL: &str = r#"{
+    "min_n": 3, "max_n": 5, "bias": -2.0, "threshold": 0.5,
+    "weights": {" rv": 3.0, "vand": 3.0, "unsou": -5.0}
+}"#;
+
+#[test]
+fn scores_distinct_ngrams() {
+    let path = model_file("model.json", MODEL);
+    let model = Model::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // each n-gram counts once however often it's there
+    assert_eq!(
+        model.probability("vandal vandal"),
+        model.probability("vandal")
+    );
+    assert!(model.is_revert_of_vandalism("vandal"));
+    // " rv" only at the start of a word
+    assert!(model.is_revert_of_vandalism("rv"));
+    assert!(!model.is_revert_of_vandalism("perv"));
+}
+
+#[test]
+fn rejects_malformed_models() {
+    for (name, json) in [
+        ("no-min", r#"{"min_n": 0, "max_n": 3, "weights": {}}"#),
+        ("min-over-max", r#"{"min_n": 4, "max_n": 3, "weights": {}}"#),
+        (
+            "threshold",
+            r#"{"min_n": 1, "max_n": 3, "threshold": 1.5, "weights": {}}"#,
+        ),
+    ] {
+        let path = model_file(name, json);
+        assert!(Model::load(&path).is_err(), "{name}");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn classifies_normalized_summaries() {
+    let path = model_file("detection-model.json", MODEL);
+    let settings = common::settings(&format!(
+        "detection = \"model\"\nmodel = \"{}\"",
+        path.display()
+    ));
+    let rules = Rules::new(&settings).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let is_revert = |summary| is_revert(rules.classifier(), settings.detection, summary, &[]);
+    assert!(is_revert("Reverted VANDALISM"));
+    assert!(!is_revert("rv vandalism, unsourced"));
+    // the section name isn't part of the summary
+    assert!(!is_revert("/* Vandalism */ copyedit"));
+}
+
+#[test]
+fn detection_and_model_go_together() {
+    assert!(common::load("detection = \"model\"").is_err());
+    assert!(common::load("model = \"model.json\"").is_err());
+}
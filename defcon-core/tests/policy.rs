@@ -0,0 +1,55 @@
+//! `level_expression`, the level formula written in Lua.
+
+mod common;
+
+use defcon_core::policy::evaluate;
+use defcon_core::{Levels, Metrics};
+
+/// The commented out `level_expression` of settings.toml.example.
+fn example() -> String {
+    let text = include_str!("../../settings.toml.example");
+    let start = text.find("#level_expression = \"\"\"\n").unwrap();
+    text[start..]
+        .lines()
+        .skip(1)
+        .take_while(|line| *line != "#\"\"\"")
+        .map(|line| line.trim_start_matches('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn levels() -> Levels {
+    common::settings("").levels
+}
+
+fn metrics(rpm: f64, abusefilter: f64) -> Metrics {
+    [("rpm", rpm), ("abusefilter", abusefilter)]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+#[test]
+fn evaluates_the_example() {
+    let expression = example();
+    let level =
+        |rpm, abusefilter| evaluate(&expression, &levels(), &metrics(rpm, abusefilter), 5).unwrap();
+    assert_eq!(level(9.0, 0.0), 1);
+    assert_eq!(level(1.0, 4.0), 1);
+    assert_eq!(level(7.0, 0.0), 2);
+    assert_eq!(level(5.0, 0.0), 3);
+    assert_eq!(level(3.0, 0.0), 4);
+    assert_eq!(level(1.0, 0.0), 5);
+}
+
+#[test]
+fn stops_endless_loops() {
+    let e = evaluate("while true do end", &levels(), &metrics(1.0, 0.0), 5).unwrap_err();
+    assert!(e.to_string().contains("too many instructions"), "{e}");
+}
+
+#[test]
+fn limits_memory() {
+    let expression = "#string.rep(\"x\", 1e9) and 5";
+    assert!(evaluate(expression, &levels(), &metrics(1.0, 0.0), 5).is_err());
+}
@@ -0,0 +1,25 @@
+//! Splitting the settings into profiles.
+
+mod common;
+
+#[test]
+fn profiles_may_override_a_file() {
+    // a keeps the top-level file, b has its own
+    let loaded = common::load(
+        "state_file = \"shared.json\"\n[profiles.a]\n[profiles.b]\nstate_file = \"b.json\"",
+    );
+    if let Err(e) = loaded {
+        panic!("{e:?}");
+    }
+}
+
+#[test]
+fn rejects_a_shared_file() {
+    for key in ["state_file", "rc_cache_file"] {
+        let loaded = common::load(&format!(
+            "{key} = \"shared.json\"\n[profiles.a]\n[profiles.b]"
+        ));
+        let e = loaded.err().expect("two profiles share the file");
+        assert!(e.to_string().contains(&format!("`{key}`")), "{e}");
+    }
+}
@@ -0,0 +1,217 @@
+//! The window of recent changes kept between runs: only newer changes are
+//! fetched, and merging them counts every change once.
+
+mod common;
+
+use std::sync::Arc;
+
+use chrono::prelude::*;
+use defcon_core::metrics::Context;
+use defcon_core::rpm::{recent_edits, RecentEdits};
+use defcon_core::tape::Tape;
+use defcon_core::{wiki, Rules, Settings};
+use serde_json::{json, Value};
+use wiremock::MockServer;
+
+/// A change at `time` on 2024-01-01, a revert if `revert`.
+fn change(rcid: u64, time: &str, revert: bool) -> Value {
+    json!({
+        "rcid": rcid,
+        "timestamp": format!("2024-01-01T{time}Z"),
+        "title": format!("Page {rcid}"),
+        "user": "Patroller",
+        "comment": if revert { "rv vandalism" } else { "copyedit" },
+        "tags": []
+    })
+}
+
+/// Make `server` answer every recent changes query with `changes`.
+async fn serve(server: &MockServer, changes: Vec<Value>) {
+    server.reset().await;
+    common::mount_login(server).await;
+    common::get("list", "recentchanges")
+        .respond_with(common::ok(json!({ "query": { "recentchanges": changes } })))
+        .mount(server)
+        .await;
+}
+
+/// The edits counted by a run at `hh:mm:ss` on 2024-01-01.
+async fn run(server: &MockServer, h: u32, m: u32, s: u32) -> RecentEdits {
+    run_with(&settings(server, ""), None, h, m, s).await
+}
+
+/// The settings for `server`, with `toml` added at the end.
+fn settings(server: &MockServer, toml: &str) -> Settings {
+    common::settings(&format!(
+        "api_url = \"{}\"\nwindow_mins = 5\nbucket_mins = 5\n{toml}",
+        common::api_url(server)
+    ))
+}
+
+/// The edits counted by a run at `hh:mm:ss` on 2024-01-01 with `settings`,
+/// recording to or playing back from `tape` if any.
+async fn run_with(
+    settings: &Settings,
+    tape: Option<Arc<Tape>>,
+    h: u32,
+    m: u32,
+    s: u32,
+) -> RecentEdits {
+    let client = wiki::login(settings).await.unwrap();
+    let rules = Rules::new(settings).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, h, m, s).unwrap();
+    let ctx = Context::new(&client, settings, rules.classifier(), now);
+    wiki::in_profile(settings, tape, recent_edits(&ctx))
+        .await
+        .unwrap()
+        .clone()
+}
+
+/// The `rcstart` of the last recent changes query `server` got.
+async fn last_rcstart(server: &MockServer) -> String {
+    let requests = server.received_requests().await.unwrap();
+    requests
+        .iter()
+        .rev()
+        .find_map(|request| {
+            let pairs = request.url.query_pairs();
+            pairs
+                .into_iter()
+                .find(|(key, _)| key == "rcstart")
+                .map(|(_, value)| value.into_owned())
+        })
+        .unwrap()
+}
+
+#[tokio::test]
+async fn overlapping_batches_count_once() {
+    let server = MockServer::start().await;
+    let first = vec![
+        change(1, "00:01:00", true),
+        change(2, "00:01:00", false),
+        change(3, "00:01:30", true),
+    ];
+    serve(&server, first.clone()).await;
+    let recent = run(&server, 0, 2, 30).await;
+    assert_eq!((recent.edits, recent.reverts.len()), (3, 2));
+
+    // the API returns the newest change seen again, as `rcstart` includes it
+    let mut second = first;
+    second.extend([change(4, "00:03:00", true), change(5, "00:04:00", false)]);
+    serve(&server, second).await;
+    let recent = run(&server, 0, 4, 0).await;
+    assert_eq!(last_rcstart(&server).await, "2024-01-01T00:01:30Z");
+    assert_eq!((recent.edits, recent.reverts.len()), (5, 3));
+    let titles: Vec<&str> = recent.reverts.iter().map(|r| r.title.as_str()).collect();
+    assert_eq!(titles, ["Page 1", "Page 3", "Page 4"]);
+}
+
+#[tokio::test]
+async fn old_buckets_expire() {
+    let server = MockServer::start().await;
+    serve(
+        &server,
+        vec![change(1, "00:01:00", true), change(2, "00:06:00", true)],
+    )
+    .await;
+    let recent = run(&server, 0, 7, 30).await;
+    // 00:00 to 00:07:30
+    assert_eq!(recent.reverts.len(), 2);
+    assert_eq!(recent.minutes, 7.5);
+
+    serve(&server, vec![change(3, "00:11:00", true)]).await;
+    let recent = run(&server, 0, 12, 30).await;
+    // 00:05 to 00:12:30: the bucket from 00:00 is gone, the one from 00:05
+    // isn't
+    let titles: Vec<&str> = recent.reverts.iter().map(|r| r.title.as_str()).collect();
+    assert_eq!(titles, ["Page 2", "Page 3"]);
+    assert_eq!(recent.minutes, 7.5);
+
+    // long enough without runs that the whole window is fetched again
+    serve(&server, vec![change(4, "00:31:00", true)]).await;
+    let recent = run(&server, 0, 32, 30).await;
+    assert_eq!(last_rcstart(&server).await, "2024-01-01T00:25:00Z");
+    assert_eq!(recent.reverts.len(), 1);
+    assert_eq!(recent.edits, 1);
+}
+
+#[tokio::test]
+async fn tapes_bypass_the_cache() {
+    let server = MockServer::start().await;
+    let dir = common::temp_path("tape");
+    let cache = common::temp_path("tape-rc.json");
+    let _ = std::fs::remove_file(&cache);
+    let mut settings = settings(
+        &server,
+        &format!("rc_cache_file = {:?}", cache.display().to_string()),
+    );
+    serve(
+        &server,
+        vec![change(1, "00:01:00", true), change(2, "00:01:30", true)],
+    )
+    .await;
+    let tape = Tape::record(&dir, "default").unwrap();
+    let recorded = run_with(&settings, Some(Arc::new(tape)), 0, 2, 30).await;
+    assert_eq!(recorded.reverts.len(), 2);
+
+    // a later run leaves a window the recording never saw
+    serve(&server, vec![change(3, "00:02:00", true)]).await;
+    let cached = run_with(&settings, None, 0, 2, 30).await;
+    assert_eq!(cached.reverts.len(), 1);
+    let cache_text = std::fs::read_to_string(&cache).unwrap();
+
+    serve(&server, Vec::new()).await;
+    settings.dry_run = true;
+    let tape = Tape::replay(&dir, "default").unwrap();
+    let replayed = run_with(&settings, Some(Arc::new(tape)), 0, 2, 30).await;
+    let titles: Vec<&str> = replayed.reverts.iter().map(|r| r.title.as_str()).collect();
+    assert_eq!(titles, ["Page 1", "Page 2"]);
+    assert_eq!(std::fs::read_to_string(&cache).unwrap(), cache_text);
+    std::fs::remove_file(&cache).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The number of recent changes queries `server` got.
+async fn rc_queries(server: &MockServer) -> usize {
+    let requests = server.received_requests().await.unwrap();
+    requests
+        .iter()
+        .filter(|request| {
+            request
+                .url
+                .query_pairs()
+                .any(|(key, value)| key == "list" && value == "recentchanges")
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn fetched_once_per_run() {
+    let server = MockServer::start().await;
+    serve(&server, vec![change(1, "00:01:00", true)]).await;
+    let settings = settings(&server, "");
+    let client = wiki::login(&settings).await.unwrap();
+    let rules = Rules::new(&settings).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 2, 30).unwrap();
+    let ctx = Context::new(&client, &settings, rules.classifier(), now);
+    assert_eq!(recent_edits(&ctx).await.unwrap().reverts.len(), 1);
+    assert_eq!(recent_edits(&ctx).await.unwrap().reverts.len(), 1);
+    assert_eq!(rc_queries(&server).await, 1);
+}
+
+#[tokio::test]
+async fn other_settings_count_again() {
+    let server = MockServer::start().await;
+    serve(
+        &server,
+        vec![change(1, "00:01:00", true), change(2, "00:01:30", true)],
+    )
+    .await;
+    let recent = run(&server, 0, 2, 30).await;
+    assert_eq!(recent.reverts.len(), 2);
+    // were the window of the old settings kept, Page 2 would still count
+    let settings = settings(&server, "excluded_users = [\"Patroller\"]");
+    let recent = run_with(&settings, None, 0, 3, 0).await;
+    assert_eq!(last_rcstart(&server).await, "2023-12-31T23:55:00Z");
+    assert!(recent.reverts.is_empty());
+}
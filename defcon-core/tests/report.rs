@@ -0,0 +1,55 @@
+//! Reading the level back from the report page as it's written.
+
+mod common;
+
+use defcon_core::report::{ReportFormat, ReportPreset};
+use defcon_core::Levels;
+
+/// The template `text`, loaded from a file.
+fn template(name: &str, text: &str) -> ReportFormat {
+    let path = common::temp_path(name);
+    std::fs::write(&path, text).unwrap();
+    let format = ReportFormat::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    format
+}
+
+#[test]
+fn presets_read_back() {
+    for preset in [
+        ReportPreset::Switch,
+        ReportPreset::VandalismInformation,
+        ReportPreset::Number,
+    ] {
+        assert!(ReportFormat::from(preset).reads_back(&Levels::default()));
+    }
+}
+
+#[test]
+fn templates_read_back_if_they_show_the_level() {
+    let levels = Levels::default();
+    let shown = template("shown.tera", "level = {{ level }}<!-- {{ rpm }} RPM -->");
+    assert!(shown.reads_back(&levels));
+    // the level, but followed by something else
+    let commented = template("commented.tera", "{{ level }}<!-- {{ rpm }} RPM -->");
+    assert!(!commented.reads_back(&levels));
+    let hidden = template(
+        "hidden.tera",
+        "{% if level < 3 %}high{% else %}low{% endif %}",
+    );
+    assert!(!hidden.reads_back(&levels));
+}
+
+#[test]
+fn needs_a_readable_report_page() {
+    let unreadable = "[[publishers]]\ntype = \"json\"";
+    let e = common::load(unreadable)
+        .err()
+        .expect("nothing writes report_page");
+    assert!(e.to_string().contains("`report_page`"), "{e}");
+    // the state file knows the level of our own revision
+    let stateful = format!("state_file = \"state.json\"\n{unreadable}");
+    assert!(common::load(&stateful).is_ok());
+    let elsewhere = "[[publishers]]\ntype = \"wiki\"\npage = \"Elsewhere\"";
+    assert!(common::load(elsewhere).is_err());
+}
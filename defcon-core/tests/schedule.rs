@@ -0,0 +1,95 @@
+//! Cron expressions for `schedule`.
+
+mod common;
+
+use chrono::{prelude::*, Duration};
+use defcon_core::schedule::Schedule;
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+}
+
+fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    Schedule::parse(expression).unwrap().next_after(after)
+}
+
+#[test]
+fn steps_line_up_with_the_clock() {
+    assert_eq!(
+        next("*/5 * * * *", at(2024, 3, 1, 10, 2)),
+        Some(at(2024, 3, 1, 10, 5))
+    );
+    // strictly after
+    assert_eq!(
+        next("*/5 * * * *", at(2024, 3, 1, 10, 5)),
+        Some(at(2024, 3, 1, 10, 10))
+    );
+    assert_eq!(
+        next("2-59/5 * * * *", at(2024, 3, 1, 10, 58)),
+        Some(at(2024, 3, 1, 11, 2))
+    );
+}
+
+#[test]
+fn rolls_over_days_months_and_years() {
+    assert_eq!(
+        next("30 6 * * *", at(2024, 12, 31, 7, 0)),
+        Some(at(2025, 1, 1, 6, 30))
+    );
+    assert_eq!(
+        next("0 0 29 2 *", at(2023, 3, 1, 0, 0)),
+        Some(at(2024, 2, 29, 0, 0))
+    );
+    assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+}
+
+#[test]
+fn either_day_field_matches_when_both_are_set() {
+    // Friday the 1st, then Monday the 4th, then Friday the 8th
+    assert_eq!(
+        next("0 12 1 * 1", at(2024, 3, 1, 0, 0)),
+        Some(at(2024, 3, 1, 12, 0))
+    );
+    assert_eq!(
+        next("0 12 1 * 1", at(2024, 3, 1, 12, 0)),
+        Some(at(2024, 3, 4, 12, 0))
+    );
+    // only the weekday restricts, and 7 is Sunday
+    assert_eq!(
+        next("0 0 * * 7", at(2024, 3, 1, 0, 0)),
+        Some(at(2024, 3, 3, 0, 0))
+    );
+}
+
+#[test]
+fn rejects_invalid_expressions() {
+    for expression in [
+        "* * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "* * 0 * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "mon * * * *",
+    ] {
+        assert!(Schedule::parse(expression).is_err(), "{expression}");
+    }
+}
+
+#[test]
+fn finds_the_shortest_gap() {
+    let gap = |expression: &str| Schedule::parse(expression).unwrap().min_gap();
+    assert_eq!(gap("*/5 * * * *"), Some(Duration::minutes(5)));
+    assert_eq!(gap("0,50 * * * *"), Some(Duration::minutes(10)));
+    assert_eq!(gap("0 9,21 * * *"), Some(Duration::hours(12)));
+    // Friday to Monday
+    assert_eq!(gap("0 12 * * 1,5"), Some(Duration::days(3)));
+    assert_eq!(gap("0 0 29 2 *"), Some(Duration::days(366 + 3 * 365)));
+    assert_eq!(gap("0 0 30 2 *"), None);
+}
+
+#[test]
+fn the_window_covers_the_time_between_runs() {
+    assert!(common::load("window_mins = 60\nschedule = \"0 * * * *\"").is_ok());
+    assert!(common::load("window_mins = 30\nschedule = \"0 * * * *\"").is_err());
+}
@@ -0,0 +1,92 @@
+//! Leaving out reverts of the reverting user's own edits.
+
+mod common;
+
+use chrono::prelude::*;
+use common::{get, ok};
+use defcon_core::metrics::Context;
+use defcon_core::rpm::recent_edits;
+use defcon_core::{wiki, Rules};
+use serde_json::{json, Value};
+use wiremock::MockServer;
+
+fn change(rcid: u64, user: &str, comment: &str, old_revid: u64) -> Value {
+    json!({
+        "rcid": rcid,
+        "old_revid": old_revid,
+        "timestamp": "2024-01-01T00:01:00Z",
+        "title": format!("Page {rcid}"),
+        "user": user,
+        "comment": comment,
+        "tags": []
+    })
+}
+
+/// The titles of the pages with reverts counted by a run against a wiki
+/// with a revert of someone else's edit and one of the reverter's own, each
+/// once named in the summary and once not.
+async fn counted(exclude_self_reverts: bool) -> Vec<String> {
+    let server = MockServer::start().await;
+    common::mount_login(&server).await;
+    let changes = [
+        change(
+            1,
+            "Patroller",
+            "Reverted edits by [[Special:Contributions/Vandal|Vandal]]",
+            10,
+        ),
+        change(
+            2,
+            "Writer",
+            "Reverted edits by [[Special:Contributions/Writer|Writer]]",
+            20,
+        ),
+        change(3, "Patroller", "rv vandalism", 50),
+        change(4, "Writer", "rv vandalism", 60),
+    ];
+    get("list", "recentchanges")
+        .respond_with(ok(json!({ "query": { "recentchanges": changes } })))
+        .mount(&server)
+        .await;
+    get("prop", "revisions")
+        .respond_with(ok(json!({
+            "query": { "pages": [{
+                "title": "Page",
+                "revisions": [
+                    { "revid": 50, "user": "Vandal" },
+                    { "revid": 60, "user": "Writer" }
+                ]
+            }] }
+        })))
+        .mount(&server)
+        .await;
+
+    let settings = common::settings(&format!(
+        "api_url = \"{}\"\nwindow_mins = 5\nbucket_mins = 5\n\
+         exclude_self_reverts = {exclude_self_reverts}",
+        common::api_url(&server)
+    ));
+    let client = wiki::login(&settings).await.unwrap();
+    let rules = Rules::new(&settings).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 2, 30).unwrap();
+    let ctx = Context::new(&client, &settings, rules.classifier(), now);
+    let recent = recent_edits(&ctx).await.unwrap();
+    recent
+        .reverts
+        .iter()
+        .map(|revert| revert.title.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn leaves_out_self_reverts() {
+    assert_eq!(counted(true).await, ["Page 1", "Page 3"]);
+}
+
+#[tokio::test]
+async fn can_count_self_reverts() {
+    assert_eq!(
+        counted(false).await,
+        ["Page 1", "Page 2", "Page 3", "Page 4"]
+    );
+}
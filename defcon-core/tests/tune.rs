@@ -0,0 +1,59 @@
+//! Suggesting thresholds from the samples in the history.
+
+mod common;
+
+use defcon_core::history::Sample;
+use defcon_core::tune::{tune, validate_shares, DEFAULT_SHARES};
+use defcon_core::{Levels, Metrics};
+
+fn samples(rpms: impl IntoIterator<Item = f64>) -> Vec<Sample> {
+    rpms.into_iter()
+        .map(|rpm| Sample {
+            time: 0,
+            rpm,
+            level: 5,
+            metrics: Metrics::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn each_level_gets_its_share() {
+    let settings = common::settings("");
+    let samples = samples((1..=100).map(|i| f64::from(i) / 100.0));
+    let tuning = tune(&settings, &samples, &DEFAULT_SHARES).unwrap();
+    assert_eq!(tuning.thresholds, [0.7, 0.9, 0.96, 0.99]);
+    assert_eq!(tuning.shares, [70.0, 20.0, 6.0, 3.0, 1.0]);
+    assert_eq!(tuning.samples, 100);
+}
+
+#[test]
+fn equal_values_still_give_increasing_thresholds() {
+    let settings = common::settings("");
+    let tuning = tune(&settings, &samples([0.0; 20]), &DEFAULT_SHARES).unwrap();
+    let expected = [0.01, 0.02, 0.03, 0.04];
+    for (threshold, expected) in tuning.thresholds.iter().zip(expected) {
+        assert!(
+            (threshold - expected).abs() < 1e-6,
+            "{:?}",
+            tuning.thresholds
+        );
+    }
+}
+
+#[test]
+fn shares_are_checked() {
+    let levels = Levels::default();
+    assert!(validate_shares(&DEFAULT_SHARES, &levels).is_ok());
+    // a little off from rounding is fine
+    assert!(validate_shares(&[70.2, 20.0, 6.0, 3.0, 1.0], &levels).is_ok());
+    assert!(validate_shares(&[70.0, 20.0, 6.0, 4.0], &levels).is_err());
+    assert!(validate_shares(&[72.0, 20.0, 6.0, 3.0, 1.0], &levels).is_err());
+    assert!(validate_shares(&[70.0, 20.0, 6.0, 4.0, 0.0], &levels).is_err());
+}
+
+#[test]
+fn needs_samples() {
+    let settings = common::settings("");
+    assert!(tune(&settings, &[], &DEFAULT_SHARES).is_err());
+}
@@ -0,0 +1,49 @@
+//! Weighting reverts by the change tag saying how they were made.
+
+mod common;
+
+use defcon_core::rpm::{count_reverts, RevertWeights};
+
+fn tags(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|&tag| tag.to_owned()).collect()
+}
+
+#[test]
+fn the_strongest_tag_decides() {
+    let weights = RevertWeights {
+        rollback: 2.0,
+        undo: 1.5,
+        manual: 0.5,
+        other: 0.25,
+    };
+    assert_eq!(weights.of(&tags(&["mw-rollback"])), 2.0);
+    assert_eq!(weights.of(&tags(&["mw-manual-revert", "mw-undo"])), 1.5);
+    assert_eq!(weights.of(&tags(&["mw-manual-revert"])), 0.5);
+    assert_eq!(weights.of(&tags(&["mw-manual-revert", "mw-rollback"])), 2.0);
+    assert_eq!(weights.of(&tags(&["visualeditor"])), 0.25);
+    assert_eq!(weights.of(&[]), 0.25);
+}
+
+#[test]
+fn weights_default_to_one() {
+    let settings = common::settings("[revert_weights]\nrollback = 2.0");
+    let weights = settings.revert_weights;
+    assert_eq!(
+        (
+            weights.rollback,
+            weights.undo,
+            weights.manual,
+            weights.other
+        ),
+        (2.0, 1.0, 1.0, 1.0)
+    );
+    assert!(common::load("[revert_weights]\nundo = -1.0").is_err());
+}
+
+#[test]
+fn the_cap_per_page_keeps_the_heaviest() {
+    let reverts = [("A", 1.0), ("A", 2.0), ("A", 0.5), ("B", 1.0)];
+    assert_eq!(count_reverts(reverts, None), 4.5);
+    assert_eq!(count_reverts(reverts, Some(2)), 4.0);
+    assert_eq!(count_reverts(reverts, Some(1)), 3.0);
+}
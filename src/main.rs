@@ -1,196 +1,779 @@
-use chrono::{prelude::*, Duration};
-use config;
-use futures_util::TryStreamExt;
-use lazy_static::lazy_static;
-
-use mw::ua;
-use regex::Regex;
-use serde_json::Value;
-use tracing::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::WrapErr;
+use defcon_core::clock::{Clock, SystemClock};
+use defcon_core::history::{self, History, Sample};
+use defcon_core::live::run_live;
+use defcon_core::lock::RunLock;
+use defcon_core::metrics::{self, collect_all, Context};
+use defcon_core::publish;
+use defcon_core::run::{run_daemon, run_once};
+use defcon_core::state::RunState;
+use defcon_core::stop::{self, Stop};
+use defcon_core::tape::Tape;
+use defcon_core::wiki::{self, ReportRevision};
+use defcon_core::{backtest, baseline, chart, dataset, monitor, server, smoothing, stats, tune};
+use defcon_core::{load_profiles, DefconError, MetricSource, Publisher, Rules, Settings, Signal};
+use futures_util::future::join_all;
+use tracing::Instrument;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
-static VANDALISM_KEYWORDS: [&str; 8] = [
-    "revert",
-    "rv ",
-    "long-term abuse",
-    "long term abuse",
-    "lta",
-    "abuse",
-    "rvv ",
-    "undid",
-];
-static NOT_VANDALISM_KEYWORDS: [&str; 12] = [
-    "uaa",
-    "good faith",
-    "agf",
-    "unsourced",
-    "unreferenced",
-    "self",
-    "speculat",
-    "original research",
-    "rv tag",
-    "typo",
-    "incorrect",
-    "format",
-];
-const INTERVAL_IN_MINS: i64 = 60;
-
-lazy_static! {
-    static ref SECTION_HEADER_RE: Regex = Regex::new(r"/\*[\s\S]+?\*/").unwrap();
-    static ref LEVEL_RE: Regex = Regex::new(r"level\s*=\s*(\d+)").unwrap();
-}
-
-fn is_revert_of_vandalism(edit_summary: &str) -> bool {
-    let edit_summary = SECTION_HEADER_RE
-        .replace(edit_summary, "")
-        .to_ascii_lowercase();
-
-    if NOT_VANDALISM_KEYWORDS
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Only use the named profile instead of all of them
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Log as plain text or as one JSON object per line
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Save the API responses of a single `run` or `check` to
+    /// `<DIR>/<profile>.json`
+    #[arg(long, global = true, value_name = "DIR")]
+    record: Option<PathBuf>,
+    /// Run a single `run` or `check` from the responses saved with `--record`,
+    /// at the time they were recorded and without editing
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "record")]
+    replay: Option<PathBuf>,
+    /// Defaults to `run`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Compute the level and update the report page if it changed. With
+    /// `--daemon` or `--live`, the settings are reloaded on SIGHUP or when
+    /// the settings file changes, and SIGTERM or SIGINT stop it once the runs
+    /// in progress are done
+    Run {
+        /// Keep running, recomputing the level every `run_interval_mins`
+        #[arg(long)]
+        daemon: bool,
+        /// Keep running, counting reverts from the EventStreams feed
+        #[arg(long, conflicts_with = "daemon")]
+        live: bool,
+        /// Print what would be saved instead of editing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the current RPM and the level it maps to, without editing
+    Check,
+    /// Show the level on the report page and when it was set
+    Status,
+    /// Show recent levels and RPMs from the database, or from the report page
+    /// history without one
+    History {
+        /// Number of entries to show
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+    },
+    /// Draw the RPM chart from the database and upload it
+    Chart,
+    /// Post last week's statistics, if they aren't on the wiki yet
+    Stats,
+    /// Write the samples in the database as CSV to stdout
+    Export {
+        /// Only samples from this time on (RFC 3339)
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only samples before this time (RFC 3339)
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Show the levels the current settings would have set over a past period
+    Backtest {
+        /// Start of the period (a date or RFC 3339)
+        #[arg(long, value_parser = parse_time)]
+        from: DateTime<Utc>,
+        /// End of the period, excluded (a date or RFC 3339)
+        #[arg(long, value_parser = parse_time)]
+        to: DateTime<Utc>,
+        /// Read the recent changes from this file, one API entry as JSON per
+        /// line, instead of the wiki
+        #[arg(long)]
+        dump: Option<PathBuf>,
+    },
+    /// Write a sample of recent edits and the classifier's verdicts as TSV to
+    /// stdout, for labeling by hand
+    Dataset {
+        /// Sample from the edits of this many past hours
+        #[arg(long, default_value_t = 24)]
+        hours: u32,
+        /// Number of edits to sample
+        #[arg(long, default_value_t = 500)]
+        count: usize,
+    },
+    /// Measure the classifier against a TSV of labeled edits, as written by
+    /// `dataset` and labeled
+    Eval {
+        /// The labeled TSV
+        file: PathBuf,
+    },
+    /// Suggest thresholds from the samples in the database so that each level
+    /// is up for a share of the time
+    Tune {
+        /// Percentages of the time for each level, from the calmest to the
+        /// most severe [default: 70,20,6,3,1]
+        #[arg(long, value_delimiter = ',')]
+        shares: Vec<f64>,
+        /// Only samples from this time on (a date or RFC 3339)
+        #[arg(long, value_parser = parse_time)]
+        from: Option<DateTime<Utc>>,
+        /// Only samples before this time (a date or RFC 3339)
+        #[arg(long, value_parser = parse_time)]
+        to: Option<DateTime<Utc>>,
+    },
+}
+
+/// A time given as RFC 3339, or as a date for midnight UTC.
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = s.parse::<NaiveDate>() {
+        if let Some(midnight) = date.and_hms_opt(0, 0, 0) {
+            return Ok(Utc.from_utc_datetime(&midnight));
+        }
+    }
+    s.parse().map_err(|e| format!("{e}"))
+}
+
+/// Print the RPM and the level it maps to, without editing.
+async fn check(
+    name: &str,
+    client: &mw::Client,
+    settings: &Settings,
+    clock: &dyn Clock,
+    rules: &mut Rules,
+    sources: &[Box<dyn MetricSource>],
+) -> color_eyre::Result<()> {
+    rules.refresh(client).await;
+    let now = clock.now();
+    let ctx = Context::new(client, settings, rules.classifier(), now);
+    let mut metrics = collect_all(sources, &ctx).await?;
+    if let Some(baseline) = &settings.baseline {
+        baseline::apply(baseline, &mut metrics, now, false)?;
+    }
+    if let Some(smoothing) = &settings.smoothing {
+        smoothing::apply(smoothing, &mut metrics, false)?;
+    }
+    let rpm = metrics.get("rpm").copied().unwrap_or(0.0) as f32;
+    let signal = Signal::new(settings, &metrics)?;
+    let curr_level = wiki::current_report(client, settings, now)
+        .await
+        .map_or(0, |rev| rev.level);
+    let level = signal.level(settings, &metrics, curr_level)?;
+    println!(
+        "{name}: {rpm:.2} RPM, severity {:.1}, level {level}",
+        signal.severity()
+    );
+    for (metric, value) in &metrics {
+        println!("{name}:   {metric} = {value:.2}");
+    }
+    if let Some(score) = &signal.score {
+        println!("{name}: score {:.3}", score.total);
+        for (metric, contribution) in &score.contributions {
+            println!("{name}:   {metric} contributes {contribution:.3}");
+        }
+    }
+    Ok(())
+}
+
+/// Print the level changes of a backtest and how long each level would have
+/// been up.
+fn print_backtest(name: &str, settings: &Settings, steps: &[backtest::Step]) {
+    for step in steps {
+        let time = step.time.to_rfc3339();
+        if step.previous_level == 0 {
+            println!("{name}: {time} level {} ({:.2} RPM)", step.level, step.rpm);
+        } else if step.level != step.previous_level {
+            println!(
+                "{name}: {time} level {} -> {} ({:.2} RPM)",
+                step.previous_level, step.level, step.rpm
+            );
+        }
+    }
+    let changes = steps
         .iter()
-        .any(|kwd| edit_summary.contains(kwd))
-    {
-        return false;
+        .filter(|step| step.previous_level != 0 && step.level != step.previous_level)
+        .count();
+    println!("{name}: {} runs, {changes} level changes", steps.len());
+    for level in settings.levels.iter() {
+        let runs = steps.iter().filter(|step| step.level == level).count();
+        if runs > 0 {
+            let share = 100.0 * runs as f64 / steps.len() as f64;
+            println!("{name}:   level {level} {share:.1}% of the time");
+        }
     }
+}
 
-    VANDALISM_KEYWORDS
+/// Print how the classifier of `rules` does on the labeled edits in `file`.
+fn eval(name: &str, settings: &Settings, rules: &Rules, file: &Path) -> color_eyre::Result<()> {
+    let tsv = std::fs::read_to_string(file)
+        .wrap_err_with(|| format!("could not read {}", file.display()))?;
+    let labeled = dataset::read_tsv(&tsv)?;
+    let evaluation = dataset::evaluate(settings, rules.classifier(), &labeled);
+    println!(
+        "{name}: {} labeled edits, precision {:.3}, recall {:.3}, F1 {:.3}",
+        labeled.len(),
+        evaluation.precision(),
+        evaluation.recall(),
+        evaluation.f1()
+    );
+    for edit in &evaluation.false_positives {
+        println!(
+            "{name}: false positive on line {}: {}",
+            edit.line, edit.summary
+        );
+    }
+    for edit in &evaluation.false_negatives {
+        println!(
+            "{name}: false negative on line {}: {}",
+            edit.line, edit.summary
+        );
+    }
+    Ok(())
+}
+
+/// A `DefconError::Config` of `message`.
+fn config_error(message: impl Into<String>) -> color_eyre::Report {
+    DefconError::Config(message.into()).into()
+}
+
+fn describe_revision(rev: &ReportRevision) -> String {
+    let timestamp = rev
+        .timestamp
+        .map_or_else(|| "unknown time".to_owned(), |ts| ts.to_rfc3339());
+    // only known for our own revision, from the state file
+    let rpm = rev
+        .rpm
+        .map_or_else(String::new, |rpm| format!(" ({rpm:.2} RPM)"));
+    format!(
+        "level {}{} set at {} by {}",
+        rev.level, rpm, timestamp, rev.user
+    )
+}
+
+fn describe_sample(sample: &Sample) -> String {
+    let time = Utc
+        .timestamp_opt(sample.time, 0)
+        .single()
+        .map_or_else(|| "unknown time".to_owned(), |ts| ts.to_rfc3339());
+    format!(
+        "level {} ({:.2} RPM) computed at {}",
+        sample.level, sample.rpm, time
+    )
+}
+
+/// Dump the samples between `from` and `to` as CSV, without logging in.
+fn export(
+    settings: &Settings,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> color_eyre::Result<()> {
+    let path = match &settings.database {
+        Some(path) => path,
+        None => return Err(config_error("`export` needs a `database` to export from")),
+    };
+    let history = History::open(path, &settings.wiki)?;
+    let samples = history.between(
+        from.map_or(i64::MIN, |from| from.timestamp()),
+        to.map_or(i64::MAX, |to| to.timestamp()),
+    )?;
+    history::write_csv(&samples, std::io::stdout().lock())
+}
+
+/// Print the thresholds suggested from the database as a settings block,
+/// without logging in.
+fn tune(
+    name: &str,
+    settings: &Settings,
+    shares: &[f64],
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> color_eyre::Result<()> {
+    let path = match &settings.database {
+        Some(path) => path,
+        None => return Err(config_error("`tune` needs a `database` of samples")),
+    };
+    let shares = match shares {
+        [] if settings.levels.len() == tune::DEFAULT_SHARES.len() => &tune::DEFAULT_SHARES[..],
+        [] => {
+            return Err(config_error(format!(
+                "`tune` needs `--shares` for a scale of {} levels",
+                settings.levels.len()
+            )))
+        }
+        shares => shares,
+    };
+    let history = History::open(path, &settings.wiki)?;
+    let samples = history.between(
+        from.map_or(i64::MIN, |from| from.timestamp()),
+        to.map_or(i64::MAX, |to| to.timestamp()),
+    )?;
+    let tuning = tune::tune(settings, &samples, shares)?;
+    let shares = settings
+        .levels
         .iter()
-        .any(|kwd| edit_summary.contains(kwd))
-}
-
-async fn reverts_per_minute(client: &mw::Client) -> color_eyre::Result<f32> {
-    let time_one_interval_ago = Utc::now() - Duration::minutes(INTERVAL_IN_MINS);
-    let end_str = time_one_interval_ago.to_rfc3339_opts(SecondsFormat::Secs, true);
-    let query = [
-        ("action", "query"),
-        ("list", "recentchanges"),
-        ("rctype", "edit"),
-        (
-            "rcstart",
-            &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-        ),
-        ("rcend", &end_str),
-        ("rcprop", "comment"),
-        ("rclimit", "max"),
-    ];
-    #[derive(serde::Deserialize)]
-    struct Edit {
-        comment: String,
-    }
-    #[derive(serde::Deserialize)]
-    struct RecentChanges {
-        recentchanges: Vec<Edit>,
-    }
-    #[derive(serde::Deserialize)]
-    struct Res {
-        query: RecentChanges,
-    }
-    let num_reverts = client
-        .get_all(query, |res: Res| {
-            Ok(vec![res
-                .query
-                .recentchanges
-                .iter()
-                .filter(|edit| is_revert_of_vandalism(&edit.comment))
-                .count()])
-        })
-        .try_fold(0, |x, y| async move { Ok(x + y) })
-        .await?;
-    Ok((num_reverts as f32) / (INTERVAL_IN_MINS as f32))
+        .zip(&tuning.shares)
+        .map(|(level, share)| format!("level {level} {share:.1}%"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("# {name}: from {} samples: {shares}", tuning.samples);
+    if settings.scoring.is_some() {
+        println!("[scoring]");
+    }
+    let thresholds = tuning
+        .thresholds
+        .iter()
+        .map(|t| format!("{t:.2}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("thresholds = [{thresholds}]");
+    Ok(())
+}
+
+/// What the runs of the profiles keep across reloads of the settings: the
+/// logged-in clients, so that a reload doesn't log in again, and each
+/// profile's publishers, which may hold connections and rate limits, for as
+/// long as its settings stay the same.
+#[derive(Default)]
+struct Sessions {
+    /// By API URL and OAuth token.
+    clients: Mutex<HashMap<(String, String), Arc<mw::Client>>>,
+    /// By profile, with the `fingerprint` of the settings they were built
+    /// from.
+    publishers: Mutex<HashMap<String, (String, Arc<Vec<Box<dyn Publisher>>>)>>,
+}
+
+impl Sessions {
+    async fn client(&self, settings: &Settings) -> color_eyre::Result<Arc<mw::Client>> {
+        let key = (settings.api_url.clone(), settings.oauth_token.clone());
+        let cached = self.clients.lock().unwrap().get(&key).cloned();
+        if let Some(client) = cached {
+            return Ok(client);
+        }
+        let client = Arc::new(wiki::login(settings).await?);
+        self.clients.lock().unwrap().insert(key, client.clone());
+        Ok(client)
+    }
+
+    fn publishers(
+        &self,
+        name: &str,
+        settings: &Settings,
+    ) -> color_eyre::Result<Arc<Vec<Box<dyn Publisher>>>> {
+        let mut cache = self.publishers.lock().unwrap();
+        if let Some((fingerprint, publishers)) = cache.get(name) {
+            if *fingerprint == settings.fingerprint {
+                return Ok(publishers.clone());
+            }
+        }
+        let publishers = Arc::new(publish::publishers(settings)?);
+        cache.insert(
+            name.to_owned(),
+            (settings.fingerprint.clone(), publishers.clone()),
+        );
+        Ok(publishers)
+    }
 }
 
-fn rpm_to_level(rpm: f32) -> u8 {
-    if rpm <= 2.0 {
-        5
-    } else if rpm <= 4.0 {
-        4
-    } else if rpm <= 6.0 {
-        3
-    } else if rpm <= 8.0 {
-        2
-    } else {
-        1
+/// `profiles`, for handing to the tasks running them.
+fn shared(profiles: Vec<(String, Settings)>) -> Vec<(String, Arc<Settings>)> {
+    profiles
+        .into_iter()
+        .map(|(name, settings)| (name, Arc::new(settings)))
+        .collect()
+}
+
+async fn run_profile(
+    name: &str,
+    settings: &Settings,
+    clock: &dyn Clock,
+    command: &Command,
+    sessions: &Sessions,
+) -> color_eyre::Result<()> {
+    if let Command::Export { from, to } = *command {
+        return export(settings, from, to);
+    }
+    if let Command::Tune { shares, from, to } = command {
+        return tune(name, settings, shares, *from, *to);
+    }
+    let mut rules = Rules::new(settings)?;
+    let sources = metrics::sources(settings)?;
+    let publishers = sessions.publishers(name, settings)?;
+    let client = sessions.client(settings).await?;
+
+    match command {
+        Command::Run { live: true, .. } => {
+            run_live(&client, settings, clock, &mut rules, &sources, &publishers).await
+        }
+        Command::Run { daemon: true, .. } => {
+            run_daemon(&client, settings, clock, &mut rules, &sources, &publishers).await
+        }
+        Command::Run { .. } => {
+            run_once(&client, settings, clock, &mut rules, &sources, &publishers).await
+        }
+        Command::Check => check(name, &client, settings, clock, &mut rules, &sources).await,
+        Command::Status => {
+            let rev = match &settings.state_file {
+                Some(path) => {
+                    RunState::load(path)?
+                        .current_report(&client, settings, clock.now())
+                        .await?
+                }
+                None => wiki::current_report(&client, settings, clock.now()).await?,
+            };
+            println!("{name}: {}", describe_revision(&rev));
+            Ok(())
+        }
+        Command::History { limit } => match &settings.database {
+            Some(path) => {
+                let history = History::open(path, &settings.wiki)?;
+                for sample in history.latest(*limit)? {
+                    println!("{name}: {}", describe_sample(&sample));
+                }
+                Ok(())
+            }
+            None => {
+                for rev in wiki::report_history(&client, settings, *limit).await? {
+                    println!("{name}: {}", describe_revision(&rev));
+                }
+                Ok(())
+            }
+        },
+        Command::Chart => match &settings.chart {
+            Some(chart) => chart::update_chart(&client, settings, chart, clock.now()).await,
+            None => Err(config_error("no `[chart]` in the settings")),
+        },
+        Command::Stats => match &settings.stats {
+            Some(stats) => stats::post_weekly_stats(&client, settings, stats, clock.now()).await,
+            None => Err(config_error("no `[stats]` in the settings")),
+        },
+        Command::Backtest { from, to, dump } => {
+            rules.refresh(&client).await;
+            let steps = backtest::backtest(
+                &client,
+                settings,
+                rules.classifier(),
+                *from,
+                *to,
+                dump.as_deref(),
+            )
+            .await?;
+            print_backtest(name, settings, &steps);
+            Ok(())
+        }
+        Command::Eval { file } => {
+            rules.refresh(&client).await;
+            eval(name, settings, &rules, file)
+        }
+        Command::Dataset { hours, count } => {
+            rules.refresh(&client).await;
+            let since = clock.now() - chrono::Duration::hours(i64::from(*hours));
+            let rows =
+                dataset::sample(&client, settings, rules.classifier(), since, *count).await?;
+            dataset::write_tsv(&rows, std::io::stdout().lock())
+        }
+        Command::Export { .. } | Command::Tune { .. } => unreachable!("handled before logging in"),
     }
 }
 
-#[tokio::main]
-async fn main() -> color_eyre::Result<()> {
-    color_eyre::install()?;
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+/// The settings file, `settings` with any of the extensions `config` reads.
+fn settings_file() -> Option<PathBuf> {
+    ["toml", "json", "yaml", "yml", "ini", "ron", "json5"]
+        .iter()
+        .map(|ext| PathBuf::from(format!("settings.{ext}")))
+        .find(|path| path.exists())
+}
 
+/// The profiles `command` runs with: the one picked with `--profile`, or
+/// else all of them, which run concurrently.
+fn select_profiles(cli: &Cli, command: &Command) -> color_eyre::Result<Vec<(String, Settings)>> {
     let config = config::Config::builder()
         .add_source(config::File::with_name("settings"))
         .add_source(config::Environment::with_prefix("APP"))
         .build()?;
-    let oauth_token = config.get_string("oauth_token")?;
-
-    let (client, _) = mw::ClientBuilder::new("https://en.wikipedia.org/w/api.php")
-        .user_agent(ua!(concat!(
-            "DeadbeefBot/defcon-rs/",
-            env!("CARGO_PKG_VERSION"),
-            " (https://en.wikipedia.org/wiki/User:DeadbeefBot)"
-        )))
-        .login_oauth(&oauth_token)
-        .await?;
-
-    // get current on-wiki defcon level
-    let report_page = config.get_string("report_page")?;
-
-    let q = [
-        ("action", "query"),
-        ("prop", "revisions"),
-        ("titles", &report_page),
-        ("rvprop", "ids|content"),
-        ("rvslots", "main"),
-        ("rvlimit", "1"),
-    ];
-    let res = client
-        .get(q)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<Value>()
-        .await?;
-    let rev = &res["query"]["pages"][0]["revisions"][0];
-    let revid = rev["revid"].as_u64().unwrap();
-    let curr_text = rev["slots"]["main"]["content"].as_str().unwrap();
-
-    let curr_level = if let Some(captures) = LEVEL_RE.captures(curr_text) {
-        captures.get(1).unwrap().as_str().parse::<u8>().unwrap()
-    } else {
-        0
+    let mut profiles = load_profiles(config)?;
+    if let Some(name) = &cli.profile {
+        profiles.retain(|(profile, _)| profile == name);
+        if profiles.is_empty() {
+            return Err(config_error(format!(
+                "no profile named `{name}` in the settings"
+            )));
+        }
+    }
+    if let Command::Run { dry_run: true, .. } = command {
+        for (_, settings) in &mut profiles {
+            settings.dry_run = true;
+        }
+    }
+    if let Command::Run { live: true, .. } = command {
+        for (name, settings) in &profiles {
+            settings.validate_live().map_err(|e| {
+                config_error(format!("invalid settings for profile `{name}`: {e:#}"))
+            })?;
+        }
+    }
+    // a replay mustn't edit the wiki as it is now with what it was then
+    if cli.replay.is_some() {
+        for (_, settings) in &mut profiles {
+            settings.dry_run = true;
+        }
+    }
+    Ok(profiles)
+}
+
+/// The `lock_file`s of `profiles`, each once.
+fn lock_paths(profiles: &[(String, Settings)]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = profiles
+        .iter()
+        .filter_map(|(_, settings)| settings.lock_file.clone())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Send the failure of profile `name` to Sentry, if it's set up.
+fn report_failure(name: &str, settings: &Settings, e: &color_eyre::Report) {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("profile", name);
+            scope.set_tag("wiki", &settings.wiki);
+            if let Some(code) = monitor::last_api_code(&settings.wiki) {
+                scope.set_tag("last_api_code", code);
+            }
+        },
+        || sentry::capture_message(&format!("{e:#}"), sentry::Level::Error),
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {e:?}");
+        let code = DefconError::find(&e).map_or(1, DefconError::exit_code);
+        std::process::exit(code);
+    }
+}
+
+async fn run() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let cli = Cli::parse();
+    // `RUST_LOG` sets the verbosity, e.g. `RUST_LOG=defcon_core=debug` for
+    // every API call
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    // errors go to Sentry as well once it's set up below
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(sentry::integrations::tracing::layer());
+    match cli.log_format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .init(),
+    }
+    let command = cli.command.clone().unwrap_or(Command::Run {
+        daemon: false,
+        live: false,
+        dry_run: false,
+    });
+
+    let mut profiles = select_profiles(&cli, &command)?;
+    let single_output = match command {
+        Command::Export { .. } => Some("export"),
+        Command::Dataset { .. } => Some("dataset"),
+        _ => None,
     };
+    if let Some(name) = single_output {
+        // the rows of several profiles would end up in one file
+        if profiles.len() > 1 {
+            return Err(config_error(format!(
+                "`{name}` needs `--profile` when there are several profiles"
+            )));
+        }
+    }
+    let single_run = match command {
+        Command::Run { daemon, live, .. } => !daemon && !live,
+        Command::Check => true,
+        _ => false,
+    };
+    if (cli.record.is_some() || cli.replay.is_some()) && !single_run {
+        return Err(config_error(
+            "`--record` and `--replay` only work with a single `run` or `check`",
+        ));
+    }
+    // held until the process exits; profiles sharing a `lock_file` take it
+    // once
+    let mut locks = Vec::new();
+    if let Command::Run { daemon, live, .. } = command {
+        let mut skipped = Vec::new();
+        for path in lock_paths(&profiles) {
+            match RunLock::acquire(&path)? {
+                Some(lock) => locks.push(lock),
+                // the previous cron run is still going
+                None if !daemon && !live => {
+                    tracing::warn!("{} is locked by another run, skipping", path.display());
+                    skipped.push(path);
+                }
+                None => {
+                    return Err(DefconError::Locked(format!(
+                        "{} is locked by another run",
+                        path.display()
+                    ))
+                    .into())
+                }
+            }
+        }
+        profiles.retain(|(_, settings)| {
+            !settings
+                .lock_file
+                .as_ref()
+                .is_some_and(|path| skipped.contains(path))
+        });
+    }
+    let locked = lock_paths(&profiles);
+    let tapes = profiles
+        .iter()
+        .map(|(name, _)| match (&cli.record, &cli.replay) {
+            (Some(dir), _) => Tape::record(dir, name).map(|tape| Some(Arc::new(tape))),
+            (_, Some(dir)) => Tape::replay(dir, name).map(|tape| Some(Arc::new(tape))),
+            (None, None) => Ok(None),
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()?;
 
-    // compute current defcon level
-    let rpm = reverts_per_minute(&client).await?;
-    let level = rpm_to_level(rpm);
-
-    if curr_level != level {
-        let text = format!(
-            "{{{{#switch: {{{{{{1}}}}}}
-              | level = {}
-              | sign = ~~~~~
-              | info = {:.2} RPM according to [[User:DeadbeefBot|DeadbeefBot]]
-            }}}}",
-            level, rpm
-        );
-        let summary = format!("[[Wikipedia:Bots/Requests for approval/DeadbeefBot 4|Bot]] updating vandalism level to level {0} ({1:.2} RPM) #DEFCON{0}", level, rpm);
-        let token = client.get_token("csrf").await?;
-        let q = [
-            ("action", "edit"),
-            ("title", &report_page),
-            ("summary", &summary),
-            ("text", &text),
-            ("baserevid", &format!("{revid}")),
-            ("token", &token),
-        ];
-
-        client.post(q).send().await?.error_for_status()?;
-        tracing::info!("edited");
-    } else {
-        tracing::info!("not going to edit")
-        // No edit necessary
+    let server = profiles
+        .first()
+        .and_then(|(_, settings)| settings.server.clone());
+    if profiles
+        .iter()
+        .any(|(_, settings)| settings.server != server)
+    {
+        return Err(config_error("`server` must be the same in every profile"));
+    }
+
+    let sentry = profiles
+        .first()
+        .and_then(|(_, settings)| settings.sentry.clone());
+    if profiles
+        .iter()
+        .any(|(_, settings)| settings.sentry != sentry)
+    {
+        return Err(config_error("`sentry` must be the same in every profile"));
+    }
+    // flushes the reports when dropped
+    let _sentry = sentry.clone().map(|sentry| {
+        sentry::init((
+            sentry.dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                environment: sentry.environment.map(Into::into),
+                ..Default::default()
+            },
+        ))
+    });
+
+    let command = &command;
+    let long_running = matches!(
+        command,
+        Command::Run { daemon: true, .. } | Command::Run { live: true, .. }
+    );
+    if long_running {
+        tokio::spawn(async {
+            if let Err(e) = stop::watch(settings_file()).await {
+                tracing::error!("could not watch for signals: {e:?}");
+            }
+        });
+    }
+    let sessions = Arc::new(Sessions::default());
+    let runs = async {
+        let mut profiles = shared(profiles);
+        let mut tapes = tapes;
+        loop {
+            // each on a task of its own, so that one failing or panicking
+            // leaves the others running
+            let tasks = profiles.iter().zip(tapes).map(|((name, settings), tape)| {
+                let (name, settings) = (name.clone(), settings.clone());
+                let (command, sessions) = (command.clone(), sessions.clone());
+                // a tape is the clock of its run, so that a replay happens at
+                // the time of the recording
+                let clock: Arc<dyn Clock> = match &tape {
+                    Some(tape) => tape.clone(),
+                    None => Arc::new(SystemClock),
+                };
+                let span = tracing::info_span!("profile", %name);
+                tokio::spawn(
+                    async move {
+                        let run = run_profile(&name, &settings, &*clock, &command, &sessions);
+                        let res = wiki::in_profile(&settings, tape, run).await;
+                        if let Err(e) = &res {
+                            tracing::error!("profile failed: {e:?}");
+                            report_failure(&name, &settings, e);
+                        }
+                        res
+                    }
+                    .instrument(span),
+                )
+            });
+            let mut failure = None;
+            for res in join_all(tasks).await {
+                if let Err(e) = res.unwrap_or_else(|e| Err(e.into())) {
+                    failure.get_or_insert(e);
+                }
+            }
+            // daemons only return when asked to stop
+            if !long_running || stop::requested() == Some(Stop::Shutdown) {
+                return failure.map_or(Ok(()), Err);
+            }
+            match select_profiles(&cli, command) {
+                Ok(reloaded) => {
+                    if reloaded
+                        .iter()
+                        .any(|(_, settings)| settings.server != server || settings.sentry != sentry)
+                    {
+                        tracing::warn!("`server` and `sentry` only change on a restart");
+                    }
+                    if lock_paths(&reloaded) != locked {
+                        tracing::warn!("`lock_file` only changes on a restart");
+                    }
+                    profiles = shared(reloaded);
+                    tracing::info!("reloaded the settings");
+                }
+                Err(e) => tracing::error!("could not reload the settings, keeping them: {e:?}"),
+            }
+            tapes = profiles.iter().map(|_| None).collect();
+        }
+    };
+    match server.clone() {
+        // only worth monitoring if we keep running
+        Some(server) if long_running => {
+            tokio::select! {
+                res = server::serve(server) => res?,
+                res = runs => { res?; }
+            }
+        }
+        _ => {
+            runs.await?;
+        }
     }
     Ok(())
 }